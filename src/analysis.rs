@@ -0,0 +1,482 @@
+//! USI エンジンによる棋譜解析。
+
+use std::ffi::OsStr;
+use std::fmt::Write as _;
+use std::ops::ControlFlow;
+use std::time::Duration;
+
+use crate::kifu::*;
+use crate::move_::*;
+use crate::position::*;
+use crate::record::*;
+use crate::side::*;
+use crate::usi_command::*;
+use crate::usi_process::*;
+use crate::usi_response::*;
+
+/// 詰みの評価値をセンチポーン単位に換算する際の基準値。
+const MATE_CP: i32 = 100000;
+
+/// 1 局面あたりの思考の制限。
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum AnalysisLimit {
+    /// 探索深さ (`go depth`)。
+    Depth(u32),
+
+    /// 思考時間 (`go byoyomi`)。
+    Time(Duration),
+
+    /// 探索ノード数 (`go nodes`)。
+    Nodes(u64),
+}
+
+/// 解析の設定。
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct AnalysisConfig {
+    /// 1 局面あたりの思考の制限。
+    pub limit: AnalysisLimit,
+
+    /// 悪手とみなす評価値の下落幅(センチポーン単位)。
+    pub mistake_threshold: i32,
+}
+
+impl Default for AnalysisConfig {
+    /// 1 局面 1 秒、下落幅 300 以上を悪手とする。
+    fn default() -> Self {
+        Self {
+            limit: AnalysisLimit::Time(Duration::from_secs(1)),
+            mistake_threshold: 300,
+        }
+    }
+}
+
+/// 1 局面の解析結果。評価値は手番側から見た値。
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PositionAnalysis {
+    score: Option<UsiScore>,
+    best_move: UsiBestMove,
+    pv: Vec<Move>,
+}
+
+impl PositionAnalysis {
+    /// 評価値(手番側から見た値)を返す。エンジンが評価値を出力しなかった場合は `None` を返す。
+    pub const fn score(&self) -> Option<UsiScore> {
+        self.score
+    }
+
+    /// 評価値をセンチポーン単位に換算して返す。詰みは ±100000 から手数を引いた値とする。
+    ///
+    /// センチポーン単位の評価値は ±100000 の範囲に丸める。
+    pub fn score_cp(&self) -> Option<i32> {
+        self.score.map(|score| score_to_cp(score.value()))
+    }
+
+    /// 最善手を返す。
+    pub const fn best_move(&self) -> UsiBestMove {
+        self.best_move
+    }
+
+    /// 読み筋を返す。
+    pub fn pv(&self) -> &[Move] {
+        &self.pv
+    }
+}
+
+/// 棋譜の解析結果。
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct KifuAnalysis {
+    kifu: Kifu,
+    positions: Vec<PositionAnalysis>,
+    mistake_threshold: i32,
+}
+
+impl KifuAnalysis {
+    /// 解析した棋譜を返す。
+    pub const fn kifu(&self) -> &Kifu {
+        &self.kifu
+    }
+
+    /// 各局面の解析結果を返す。
+    ///
+    /// `i` 番目は `i` 手指した後の局面に対応する。
+    /// 要素数は通常は手数 + 1 だが、途中で詰んだ局面があればそこまでとなる。
+    pub fn positions(&self) -> &[PositionAnalysis] {
+        &self.positions
+    }
+
+    /// `i` 番目 (0-based) の指し手による、指した側から見た評価値の下落幅を返す。
+    ///
+    /// 指す前後いずれかの局面の評価値がなければ `None` を返す。
+    pub fn eval_drop(&self, i: usize) -> Option<i32> {
+        let before = self.positions.get(i)?.score_cp()?;
+        let after = -self.positions.get(i + 1)?.score_cp()?;
+
+        Some(before.saturating_sub(after))
+    }
+
+    /// `i` 番目 (0-based) の指し手が悪手(評価値の下落幅が閾値以上)かどうかを返す。
+    pub fn is_mistake(&self, i: usize) -> bool {
+        self.eval_drop(i)
+            .is_some_and(|drop| drop >= self.mistake_threshold)
+    }
+
+    /// 悪手の指し手のインデックス (0-based) を返す。
+    pub fn mistakes(&self) -> Vec<usize> {
+        (0..self.kifu.moves().len())
+            .filter(|&i| self.is_mistake(i))
+            .collect()
+    }
+
+    /// 解析結果をコメントとして書き込んだ棋譜を返す。
+    ///
+    /// 各局面のコメントには先手から見た評価値、最善手、読み筋を書き、悪手にはその旨を書く。
+    pub fn to_record(&self) -> Record {
+        let mut record = Record::from(self.kifu.clone());
+
+        let mut pos = Some(self.kifu.position().clone());
+        let mut prev = None;
+        for (i, analysis) in self.positions.iter().enumerate() {
+            let Some(pos_now) = &pos else {
+                break;
+            };
+            let mut comments = vec![];
+            if i > 0 && self.is_mistake(i - 1) {
+                comments.push(format!("悪手 (評価値 -{})", self.eval_drop(i - 1).unwrap()));
+            }
+            comments.extend(fmt_analysis(pos_now, prev, analysis));
+
+            if i == 0 {
+                record.comments_mut().extend(comments);
+            } else {
+                record.entries_mut()[i - 1].comments_mut().extend(comments);
+            }
+
+            if let Some(&mv) = self.kifu.moves().get(i) {
+                let legal = pos_now.is_legal_move(mv);
+                pos = pos.filter(|_| legal).map(|mut pos| {
+                    pos.do_move(mv).unwrap();
+                    pos
+                });
+                prev = Some(mv);
+            }
+        }
+
+        record
+    }
+}
+
+/// USI エンジンを用いて棋譜を解析する。
+#[derive(Debug)]
+pub struct KifuAnalyzer {
+    engine: UsiProcess,
+    config: AnalysisConfig,
+}
+
+impl KifuAnalyzer {
+    /// 起動済みのエンジンと設定を指定して作る。
+    pub fn new(engine: UsiProcess, config: AnalysisConfig) -> Self {
+        Self { engine, config }
+    }
+
+    /// エンジンを起動して作る。
+    pub fn spawn<I, S>(
+        program: impl AsRef<OsStr>,
+        args: I,
+        config: AnalysisConfig,
+    ) -> UsiProcessResult<Self>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let engine = UsiProcess::spawn(program, args)?;
+
+        Ok(Self::new(engine, config))
+    }
+
+    /// エンジンへの可変参照を返す。オプションの設定などに用いる。
+    pub fn engine_mut(&mut self) -> &mut UsiProcess {
+        &mut self.engine
+    }
+
+    /// 設定を返す。
+    pub const fn config(&self) -> &AnalysisConfig {
+        &self.config
+    }
+
+    /// 棋譜の開始局面および各指し手の後の局面を解析する。
+    ///
+    /// 詰んでいる局面はエンジンに問い合わせず、`mate 0` と投了を結果とする。
+    pub fn analyze(&mut self, kifu: &Kifu) -> UsiProcessResult<KifuAnalysis> {
+        self.engine.is_ready()?;
+        self.engine.new_game()?;
+
+        let mut params = UsiGoParams::default();
+        match self.config.limit {
+            AnalysisLimit::Depth(depth) => params.depth = Some(depth),
+            AnalysisLimit::Time(time) => {
+                params.btime = Some(Duration::ZERO);
+                params.wtime = Some(Duration::ZERO);
+                params.byoyomi = Some(time);
+            }
+            AnalysisLimit::Nodes(nodes) => params.nodes = Some(nodes),
+        }
+
+        let mut positions = Vec::with_capacity(kifu.moves().len() + 1);
+        let mut pos = Some(kifu.position().clone());
+        for i in 0..=kifu.moves().len() {
+            if pos.as_ref().is_some_and(Position::is_checkmate) {
+                positions.push(PositionAnalysis {
+                    score: Some(UsiScore::mate(0)),
+                    best_move: UsiBestMove::Resign,
+                    pv: vec![],
+                });
+                break;
+            }
+
+            let prefix = Kifu::new(kifu.position().clone(), kifu.moves()[..i].iter().copied());
+            let mut score = None;
+            let mut pv = vec![];
+            let best_move = self.engine.go(&prefix, &params, |info| {
+                // MultiPV の場合は第 1 候補のみを用いる。
+                if info.multipv.is_some_and(|k| k != 1) {
+                    return ControlFlow::Continue(());
+                }
+                if info.score.is_some() {
+                    score = info.score;
+                }
+                if !info.pv.is_empty() {
+                    pv.clone_from(&info.pv);
+                }
+                ControlFlow::Continue(())
+            })?;
+            positions.push(PositionAnalysis {
+                score,
+                best_move,
+                pv,
+            });
+
+            // 非合法な手順が含まれていても解析は続ける(詰みの判定のみ行わない)。
+            if let Some(&mv) = kifu.moves().get(i) {
+                pos = pos.and_then(|mut pos| pos.do_move(mv).ok().map(|()| pos));
+            }
+        }
+
+        Ok(KifuAnalysis {
+            kifu: kifu.clone(),
+            positions,
+            mistake_threshold: self.config.mistake_threshold,
+        })
+    }
+
+    /// エンジンを終了させる。
+    pub fn quit(self) -> UsiProcessResult<()> {
+        self.engine.quit()
+    }
+}
+
+fn score_to_cp(value: UsiScoreValue) -> i32 {
+    match value {
+        UsiScoreValue::Cp(cp) => cp.clamp(-MATE_CP, MATE_CP),
+        UsiScoreValue::Mate(plies) if plies > 0 => MATE_CP - plies,
+        UsiScoreValue::Mate(plies) => -MATE_CP - plies,
+        UsiScoreValue::MateUnknown(true) => MATE_CP,
+        UsiScoreValue::MateUnknown(false) => -MATE_CP,
+    }
+}
+
+/// 局面の解析結果をコメント行として返す。評価値は先手から見た値にする。
+fn fmt_analysis(pos: &Position, prev: Option<Move>, analysis: &PositionAnalysis) -> Vec<String> {
+    let mut lines = vec![];
+
+    if let Some(score) = analysis.score {
+        let sign = match pos.side_to_move() {
+            SENTE => 1,
+            GOTE => -1,
+        };
+        let s = match score.value() {
+            UsiScoreValue::Cp(cp) => format!("評価値 {:+}", cp.saturating_mul(sign)),
+            UsiScoreValue::Mate(plies) => {
+                let winner = if (plies > 0) == (sign > 0) {
+                    "先手"
+                } else {
+                    "後手"
+                };
+                format!("評価値 {winner}勝ち ({}手詰)", plies.unsigned_abs())
+            }
+            UsiScoreValue::MateUnknown(win) => {
+                let winner = if win == (sign > 0) {
+                    "先手"
+                } else {
+                    "後手"
+                };
+                format!("評価値 {winner}勝ち")
+            }
+        };
+        lines.push(s);
+    }
+
+    match analysis.best_move {
+        UsiBestMove::Move { mv, .. } => {
+            lines.push(format!("最善手 {}", fmt_moves(pos, prev, &[mv])));
+        }
+        UsiBestMove::Resign => lines.push("最善手 投了".to_owned()),
+        UsiBestMove::Win => lines.push("最善手 入玉宣言".to_owned()),
+    }
+
+    if !analysis.pv.is_empty() {
+        lines.push(format!("読み筋 {}", fmt_moves(pos, prev, &analysis.pv)));
+    }
+
+    lines
+}
+
+/// 手順を `▲７六歩 △３四歩` のように表記する。非合法手以降は SFEN 指し手で表記する。
+fn fmt_moves(pos: &Position, mut prev: Option<Move>, mvs: &[Move]) -> String {
+    let mut s = String::new();
+    let mut pos = Some(pos.clone());
+
+    for &mv in mvs {
+        if !s.is_empty() {
+            s.push(' ');
+        }
+        match pos.as_mut() {
+            Some(p) if p.is_legal_move(mv) => {
+                let mark = match p.side_to_move() {
+                    SENTE => '▲',
+                    GOTE => '△',
+                };
+                write!(s, "{mark}{}", mv.to_japanese(p, prev).unwrap()).unwrap();
+                p.do_move(mv).unwrap();
+            }
+            _ => {
+                pos = None;
+                write!(s, "{mv}").unwrap();
+            }
+        }
+        prev = Some(mv);
+    }
+
+    s
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::str::FromStr as _;
+
+    use super::*;
+
+    /// 局面の手数に応じて評価値を返すエンジン。
+    /// 平手初期局面から 2 手目以降で後手が大きく損をしたことにする。
+    const STUB_ENGINE: &str = r#"
+n=0
+while read -r cmd args; do
+    case "$cmd" in
+        usi) echo "id name Stub"; echo "usiok" ;;
+        isready) echo "readyok" ;;
+        position) set -- $args; n=$(($# - 2)); [ $n -lt 0 ] && n=0 ;;
+        go)
+            case $n in
+                0) echo "info depth 1 score cp 50 pv 7g7f 3c3d"; echo "bestmove 7g7f" ;;
+                1) echo "info depth 1 score cp -40 pv 3c3d"; echo "bestmove 3c3d" ;;
+                *) echo "info depth 1 score cp 500 pv 2g2f"; echo "bestmove 2g2f" ;;
+            esac
+            ;;
+        quit) exit 0 ;;
+    esac
+done
+"#;
+
+    #[test]
+    fn test_kifu_analyzer() {
+        let config = AnalysisConfig {
+            limit: AnalysisLimit::Depth(1),
+            mistake_threshold: 300,
+        };
+        let mut analyzer = KifuAnalyzer::spawn("sh", ["-c", STUB_ENGINE], config).unwrap();
+
+        let kifu = Kifu::from_str("position startpos moves 7g7f 8c8d").unwrap();
+        let analysis = analyzer.analyze(&kifu).unwrap();
+        analyzer.quit().unwrap();
+
+        assert_eq!(analysis.positions().len(), 3);
+        assert_eq!(analysis.positions()[0].score(), Some(UsiScore::cp(50)));
+        assert_eq!(
+            analysis.positions()[1].pv(),
+            [Move::from_str("3c3d").unwrap()]
+        );
+        assert_eq!(analysis.eval_drop(0), Some(10));
+        assert_eq!(analysis.eval_drop(1), Some(460));
+        assert_eq!(analysis.mistakes(), [1]);
+
+        let record = analysis.to_record();
+        assert_eq!(
+            record.comments(),
+            ["評価値 +50", "最善手 ▲７六歩", "読み筋 ▲７六歩 △３四歩"]
+        );
+        assert_eq!(
+            record.entries()[0].comments(),
+            ["評価値 +40", "最善手 △３四歩", "読み筋 △３四歩"]
+        );
+        assert_eq!(
+            record.entries()[1].comments(),
+            [
+                "悪手 (評価値 -460)",
+                "評価値 +500",
+                "最善手 ▲２六歩",
+                "読み筋 ▲２六歩"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_kifu_analysis_extreme_score() {
+        let position = |score| PositionAnalysis {
+            score: Some(score),
+            best_move: UsiBestMove::Resign,
+            pv: vec![],
+        };
+        let analysis = KifuAnalysis {
+            kifu: Kifu::from_str("position startpos moves 7g7f 3c3d").unwrap(),
+            positions: vec![
+                position(UsiScore::cp(i32::MAX)),
+                position(UsiScore::cp(i32::MIN)),
+                position(UsiScore::mate(i32::MIN)),
+            ],
+            mistake_threshold: 300,
+        };
+
+        assert_eq!(analysis.positions()[1].score_cp(), Some(-MATE_CP));
+        assert_eq!(analysis.eval_drop(0), Some(0));
+
+        let record = analysis.to_record();
+        assert_eq!(
+            record.entries()[0].comments(),
+            ["評価値 +2147483647", "最善手 投了"]
+        );
+        assert_eq!(
+            record.entries()[1].comments(),
+            [
+                "悪手 (評価値 -2147283648)",
+                "評価値 後手勝ち (2147483648手詰)",
+                "最善手 投了"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_kifu_analyzer_checkmate() {
+        let mut analyzer =
+            KifuAnalyzer::spawn("sh", ["-c", STUB_ENGINE], AnalysisConfig::default()).unwrap();
+
+        let kifu =
+            Kifu::from_str("position sfen 4k4/9/4P4/9/9/9/9/9/4K4 b G 1 moves G*5b").unwrap();
+        let analysis = analyzer.analyze(&kifu).unwrap();
+
+        assert_eq!(analysis.positions()[1].score(), Some(UsiScore::mate(0)));
+        assert_eq!(analysis.positions()[1].best_move(), UsiBestMove::Resign);
+        assert_eq!(
+            analysis.to_record().entries()[0].comments(),
+            ["評価値 先手勝ち (0手詰)", "最善手 投了"]
+        );
+    }
+}
@@ -0,0 +1,728 @@
+//! Apery の内部表現(定跡のハッシュキー、16 ビットの指し手、HuffmanCodedPos)と定跡ファイル。
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::OnceLock;
+
+use crate::board::*;
+use crate::book::*;
+use crate::hand::*;
+use crate::move_::*;
+use crate::movegen::*;
+use crate::packed_sfen::*;
+use crate::piece::*;
+use crate::position::*;
+use crate::side::*;
+use crate::square::*;
+
+/// Apery の駒種番号 (`Pawn` = 1, ..., `Dragon` = 14)。
+const fn apery_piece_type(pk: PieceKind) -> usize {
+    match pk {
+        PAWN => 1,
+        LANCE => 2,
+        KNIGHT => 3,
+        SILVER => 4,
+        BISHOP => 5,
+        ROOK => 6,
+        GOLD => 7,
+        KING => 8,
+        PRO_PAWN => 9,
+        PRO_LANCE => 10,
+        PRO_KNIGHT => 11,
+        PRO_SILVER => 12,
+        HORSE => 13,
+        DRAGON => 14,
+    }
+}
+
+/// Apery の駒番号 (先手の駒は駒種番号、後手の駒は駒種番号 + 16)。
+const fn apery_piece(pc: Piece) -> usize {
+    let pt = apery_piece_type(pc.kind());
+    match pc.side() {
+        SENTE => pt,
+        GOTE => pt + 16,
+    }
+}
+
+/// Apery の手駒の順序。
+const APERY_HAND_PIECES: [HandPieceKind; 7] = [
+    HAND_PAWN,
+    HAND_LANCE,
+    HAND_KNIGHT,
+    HAND_SILVER,
+    HAND_GOLD,
+    HAND_BISHOP,
+    HAND_ROOK,
+];
+
+/// Apery の HuffmanCodedPos のハフマン符号。
+///
+/// やねうら王の PackedSfen とは桂と銀の符号が入れ替わっている。
+const APERY_HUFFMAN: HuffmanTable = [
+    (HAND_PAWN, 0x01, 2),
+    (HAND_LANCE, 0x03, 4),
+    (HAND_KNIGHT, 0x07, 4),
+    (HAND_SILVER, 0x0B, 4),
+    (HAND_GOLD, 0x0F, 5),
+    (HAND_BISHOP, 0x1F, 6),
+    (HAND_ROOK, 0x3F, 6),
+];
+
+/// 標準の `std::mt19937_64`。
+struct Mt19937_64 {
+    state: [u64; Self::N],
+    index: usize,
+}
+
+impl Mt19937_64 {
+    const N: usize = 312;
+    const M: usize = 156;
+
+    /// C++ の `std::mt19937_64` のデフォルトシード。
+    const DEFAULT_SEED: u64 = 5489;
+
+    fn new(seed: u64) -> Self {
+        let mut state = [0; Self::N];
+        state[0] = seed;
+        for i in 1..Self::N {
+            let prev = state[i - 1];
+            state[i] = 6364136223846793005_u64
+                .wrapping_mul(prev ^ (prev >> 62))
+                .wrapping_add(i as u64);
+        }
+
+        Self {
+            state,
+            index: Self::N,
+        }
+    }
+
+    fn twist(&mut self) {
+        const UPPER: u64 = 0xFFFF_FFFF_8000_0000;
+        const LOWER: u64 = 0x0000_0000_7FFF_FFFF;
+        const MATRIX_A: u64 = 0xB502_6F5A_A966_19E9;
+
+        for i in 0..Self::N {
+            let x = (self.state[i] & UPPER) | (self.state[(i + 1) % Self::N] & LOWER);
+            let mut xa = x >> 1;
+            if x & 1 != 0 {
+                xa ^= MATRIX_A;
+            }
+            self.state[i] = self.state[(i + Self::M) % Self::N] ^ xa;
+        }
+        self.index = 0;
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        if self.index >= Self::N {
+            self.twist();
+        }
+
+        let mut y = self.state[self.index];
+        self.index += 1;
+
+        y ^= (y >> 29) & 0x5555_5555_5555_5555;
+        y ^= (y << 17) & 0x71D6_7FFF_EDA6_0000;
+        y ^= (y << 37) & 0xFFF7_EEE0_0000_0000;
+        y ^= y >> 43;
+
+        y
+    }
+}
+
+/// Apery の定跡用 Zobrist テーブル。
+struct AperyZobrist {
+    piece: [[u64; 81]; 31],
+    hand: [[u64; 19]; 7],
+    turn: u64,
+}
+
+impl AperyZobrist {
+    /// Apery の `Book::init()` と同じ順序で乱数を生成する。
+    fn new() -> Self {
+        let mut rng = Mt19937_64::new(Mt19937_64::DEFAULT_SEED);
+
+        let mut piece = [[0; 81]; 31];
+        for row in &mut piece {
+            row.fill_with(|| rng.next_u64());
+        }
+        let mut hand = [[0; 19]; 7];
+        for row in &mut hand {
+            row.fill_with(|| rng.next_u64());
+        }
+        let turn = rng.next_u64();
+
+        Self { piece, hand, turn }
+    }
+
+    fn get() -> &'static Self {
+        static ZOBRIST: OnceLock<AperyZobrist> = OnceLock::new();
+        ZOBRIST.get_or_init(Self::new)
+    }
+}
+
+impl Position {
+    /// Apery の定跡のハッシュキーを返す。
+    ///
+    /// 盤面、手番側の手駒、手番から計算される(手数と相手の手駒は含まない)。
+    pub fn apery_book_key(&self) -> u64 {
+        let zobrist = AperyZobrist::get();
+        let mut key = 0;
+
+        for sq in Square::all() {
+            if let Some(pc) = self.board()[sq] {
+                key ^= zobrist.piece[apery_piece(pc)][usize::from(sq.to_col_major_index())];
+            }
+        }
+
+        let hand = &self.hands()[self.side_to_move()];
+        for (i, hpk) in APERY_HAND_PIECES.into_iter().enumerate() {
+            let count = usize::from(hand[hpk]).min(18);
+            key ^= zobrist.hand[i][count];
+        }
+
+        if self.side_to_move() == GOTE {
+            key ^= zobrist.turn;
+        }
+
+        key
+    }
+
+    /// Apery の HuffmanCodedPos (32 バイト) を返す。手数は含まれない。
+    ///
+    /// 構成は PackedSfen と同様だが、ハフマン符号が異なる。
+    /// また、盤上の駒は手番ビットを成りビットより先に、手駒は成りビットを手番ビットより先に出力し、
+    /// 手駒は Apery の順序 (歩, 香, 桂, 銀, 金, 角, 飛) で並べる。
+    ///
+    /// # Panics
+    ///
+    /// `Position::to_packed_sfen` と同じく、両玉がない局面、
+    /// または玉以外の駒の枚数(盤上と手駒の合計)が平手と異なる局面では panic する。
+    pub fn to_hcp(&self) -> [u8; 32] {
+        assert!(
+            is_packable(self),
+            "HuffmanCodedPos requires both kings and the standard set of pieces"
+        );
+
+        let mut w = BitWriter::new();
+
+        w.write_bit(self.side_to_move() == GOTE);
+        for side in [SENTE, GOTE] {
+            let sq = king_square(self.board(), side).unwrap();
+            w.write_bits(u32::from(sq.to_col_major_index()), 7);
+        }
+
+        for i in 0..81 {
+            let sq = Square::from_col_major_index(i).unwrap();
+            match self.board()[sq] {
+                None => w.write_bit(false),
+                Some(pc) if pc.kind() == KING => {}
+                Some(pc) => {
+                    let hpk = HandPieceKind::try_from(pc.kind().unpromote()).unwrap();
+                    w.write_board_huffman(&APERY_HUFFMAN, hpk);
+                    w.write_bit(pc.side() == GOTE);
+                    if hpk != HAND_GOLD {
+                        w.write_bit(pc.kind().is_promoted());
+                    }
+                }
+            }
+        }
+
+        for side in [SENTE, GOTE] {
+            for hpk in APERY_HAND_PIECES {
+                for _ in 0..self.hands()[side][hpk] {
+                    w.write_hand_huffman(&APERY_HUFFMAN, hpk);
+                    if hpk != HAND_GOLD {
+                        w.write_bit(false);
+                    }
+                    w.write_bit(side == GOTE);
+                }
+            }
+        }
+
+        debug_assert_eq!(w.cursor(), 256);
+
+        w.into_data()
+    }
+
+    /// Apery の HuffmanCodedPos (32 バイト) から局面を作る。手数は 1 とする。
+    pub fn from_hcp(hcp: &[u8; 32]) -> Result<Self, PackedSfenError> {
+        let mut r = BitReader::new(hcp);
+
+        let side_to_move = if r.read_bit()? { GOTE } else { SENTE };
+
+        let mut board = Board::empty();
+        let mut king_sqs = vec![];
+        for side in [SENTE, GOTE] {
+            let i = r.read_bits(7)? as u8;
+            let sq = Square::from_col_major_index(i).ok_or(PackedSfenError::InvalidKingSquare)?;
+            if king_sqs.contains(&sq) {
+                return Err(PackedSfenError::InvalidKingSquare);
+            }
+            king_sqs.push(sq);
+            board[sq] = Some(Piece::new(side, KING));
+        }
+
+        for i in 0..81 {
+            let sq = Square::from_col_major_index(i).unwrap();
+            if king_sqs.contains(&sq) {
+                continue;
+            }
+            let Some(hpk) = r.read_board_huffman(&APERY_HUFFMAN)? else {
+                continue;
+            };
+            let side = if r.read_bit()? { GOTE } else { SENTE };
+            let promo = hpk != HAND_GOLD && r.read_bit()?;
+
+            let pk = PieceKind::from(hpk);
+            let pk = if promo { pk.promote().unwrap() } else { pk };
+            board[sq] = Some(Piece::new(side, pk));
+        }
+
+        let mut hands = Hands::empty();
+        while !r.is_end() {
+            let hpk = r.read_hand_huffman(&APERY_HUFFMAN)?;
+            if hpk != HAND_GOLD && r.read_bit()? {
+                return Err(PackedSfenError::PromotedHandPiece);
+            }
+            let side = if r.read_bit()? { GOTE } else { SENTE };
+
+            let count = &mut hands[side][hpk];
+            *count = count.saturating_add(1);
+        }
+
+        let pos = Self::new(side_to_move, board, hands, PLY_1);
+        if !is_packable(&pos) {
+            return Err(PackedSfenError::InvalidPieceCount);
+        }
+
+        Ok(pos)
+    }
+}
+
+impl Move {
+    /// Apery の 16 ビット表現を返す。
+    ///
+    /// ビット 0-6 が移動先、ビット 7-13 が移動元、ビット 14 が成りフラグ。
+    /// 駒打ちの場合、移動元は 80 + 駒種番号 (`Pawn` = 1, ..., `Gold` = 7)。
+    pub fn to_apery_u16(self) -> u16 {
+        let (src, promo) = match self {
+            Self::Walk(walk) => (
+                u16::from(walk.src().to_col_major_index()),
+                walk.is_promotion(),
+            ),
+            Self::Drop(drop) => {
+                let pt = apery_piece_type(PieceKind::from(drop.piece_kind())) as u16;
+                (80 + pt, false)
+            }
+        };
+
+        u16::from(self.dst().to_col_major_index()) | (src << 7) | (u16::from(promo) << 14)
+    }
+
+    /// Apery の 16 ビット表現から指し手を作る。
+    pub fn from_apery_u16(value: u16) -> Result<Self, MoveDecodeError> {
+        let dst = (value & 0x7F) as u8;
+        let src = ((value >> 7) & 0x7F) as u8;
+        let promo = value & (1 << 14) != 0;
+
+        if value >> 15 != 0 {
+            return Err(MoveDecodeError::InvalidFlags);
+        }
+        let dst = Square::from_col_major_index(dst).ok_or(MoveDecodeError::InvalidDst)?;
+
+        if src < 81 {
+            let src = Square::from_col_major_index(src).unwrap();
+            if src == dst {
+                return Err(MoveDecodeError::InvalidSrc);
+            }
+            return Ok(Self::walk(src, dst, promo));
+        }
+
+        if promo {
+            return Err(MoveDecodeError::InvalidFlags);
+        }
+        let hpk = HandPieceKind::all()
+            .into_iter()
+            .find(|&hpk| 80 + apery_piece_type(PieceKind::from(hpk)) == usize::from(src))
+            .ok_or(MoveDecodeError::InvalidSrc)?;
+
+        Ok(Self::drop(hpk, dst))
+    }
+}
+
+/// Apery 形式の定跡の候補手。
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct AperyBookMove {
+    /// 指し手。
+    pub mv: Move,
+
+    /// 出現回数。
+    pub count: u16,
+
+    /// 評価値。
+    pub score: i32,
+}
+
+/// Apery 形式の定跡ファイルのパースエラー。
+#[non_exhaustive]
+#[derive(Clone, Debug)]
+pub enum AperyBookParseError {
+    /// ファイルサイズがエントリサイズ (16 バイト) の倍数でない。
+    InvalidLength { len: usize },
+
+    /// `index` 番目(0 始まり)のエントリの指し手が不正。
+    InvalidMove {
+        index: usize,
+        error: MoveDecodeError,
+    },
+}
+
+impl std::fmt::Display for AperyBookParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::InvalidLength { len } => {
+                write!(f, "file length {len} is not a multiple of entry size")
+            }
+            Self::InvalidMove { index, error } => {
+                write!(f, "invalid move at entry {index}: {error}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for AperyBookParseError {}
+
+/// Apery (Bonanza) 形式のバイナリ定跡。
+///
+/// 各エントリは 16 バイトで、ハッシュキー (u64)、指し手 (u16)、出現回数 (u16)、評価値 (i32) を
+/// リトルエンディアンで並べたもの。局面は `Position::apery_book_key` のハッシュキーで識別する。
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct AperyBook {
+    entries: HashMap<u64, Vec<AperyBookMove>>,
+}
+
+impl AperyBook {
+    const ENTRY_SIZE: usize = 16;
+
+    /// 空の定跡を作る。
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 登録されている局面数を返す。
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// 空かどうかを返す。
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// ハッシュキー `key` の候補手を返す。
+    pub fn get(&self, key: u64) -> Option<&[AperyBookMove]> {
+        self.entries.get(&key).map(Vec::as_slice)
+    }
+
+    /// 局面 `pos` の候補手を返す。
+    pub fn probe(&self, pos: &Position) -> Option<&[AperyBookMove]> {
+        self.get(pos.apery_book_key())
+    }
+
+    /// ハッシュキー `key` に候補手を登録する。同じ指し手が既にあれば置き換える。
+    pub fn insert(&mut self, key: u64, bm: AperyBookMove) {
+        let bms = self.entries.entry(key).or_default();
+        match bms.iter_mut().find(|e| e.mv == bm.mv) {
+            Some(e) => *e = bm,
+            None => bms.push(bm),
+        }
+    }
+
+    /// 全てのハッシュキーと候補手を列挙する。順序は不定。
+    pub fn iter(&self) -> impl Iterator<Item = (u64, &[AperyBookMove])> {
+        self.entries.iter().map(|(&key, bms)| (key, bms.as_slice()))
+    }
+
+    /// 定跡ファイルの内容をパースする。
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, AperyBookParseError> {
+        if !bytes.len().is_multiple_of(Self::ENTRY_SIZE) {
+            return Err(AperyBookParseError::InvalidLength { len: bytes.len() });
+        }
+
+        let mut book = Self::new();
+        for (index, entry) in bytes.chunks_exact(Self::ENTRY_SIZE).enumerate() {
+            let key = u64::from_le_bytes(entry[0..8].try_into().unwrap());
+            let mv = u16::from_le_bytes(entry[8..10].try_into().unwrap());
+            let count = u16::from_le_bytes(entry[10..12].try_into().unwrap());
+            let score = i32::from_le_bytes(entry[12..16].try_into().unwrap());
+
+            let mv = Move::from_apery_u16(mv)
+                .map_err(|error| AperyBookParseError::InvalidMove { index, error })?;
+            book.insert(key, AperyBookMove { mv, count, score });
+        }
+
+        Ok(book)
+    }
+
+    /// 定跡ファイルの内容を返す。
+    ///
+    /// エントリはハッシュキーの昇順に並べる(Apery は二分探索で定跡を引くため)。
+    /// 同じハッシュキーの候補手の順序は保つ。
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut keys: Vec<_> = self.entries.keys().copied().collect();
+        keys.sort_unstable();
+
+        let mut bytes = Vec::with_capacity(Self::ENTRY_SIZE * self.entries.len());
+        for key in keys {
+            for bm in &self.entries[&key] {
+                bytes.extend_from_slice(&key.to_le_bytes());
+                bytes.extend_from_slice(&bm.mv.to_apery_u16().to_le_bytes());
+                bytes.extend_from_slice(&bm.count.to_le_bytes());
+                bytes.extend_from_slice(&bm.score.to_le_bytes());
+            }
+        }
+
+        bytes
+    }
+
+    /// 局面をキーとする定跡から作る。
+    ///
+    /// 評価値はそのまま、出現回数は `u16` に飽和させて変換する。予想応手と探索深さは捨てられる。
+    pub fn from_book(book: &Book) -> Self {
+        let mut apery = Self::new();
+
+        for (pos, bms) in book.iter() {
+            let key = pos.apery_book_key();
+            for bm in bms {
+                apery.insert(
+                    key,
+                    AperyBookMove {
+                        mv: bm.mv,
+                        count: u16::try_from(bm.count).unwrap_or(u16::MAX),
+                        score: bm.eval,
+                    },
+                );
+            }
+        }
+
+        apery
+    }
+
+    /// 局面をキーとする定跡に変換する。
+    ///
+    /// ハッシュキーからは局面を復元できないので、局面 `roots` から定跡手を辿って到達できる局面のみを変換する。
+    /// 合法でない候補手(ハッシュの衝突によるものなど)は無視する。
+    pub fn to_book(&self, roots: &[Position]) -> Book {
+        let mut book = Book::new();
+        let mut visited = HashSet::new();
+        let mut queue: VecDeque<_> = roots.iter().cloned().collect();
+
+        while let Some(pos) = queue.pop_front() {
+            let key = pos.apery_book_key();
+            if !visited.insert(key) {
+                continue;
+            }
+            let Some(bms) = self.get(key) else {
+                continue;
+            };
+
+            for bm in bms {
+                if !pos.is_legal_move(bm.mv) {
+                    continue;
+                }
+                book.insert(
+                    &pos,
+                    BookMove {
+                        eval: bm.score,
+                        count: u64::from(bm.count),
+                        ..BookMove::new(bm.mv)
+                    },
+                );
+
+                let mut next = pos.clone();
+                next.do_move(bm.mv).unwrap();
+                queue.push_back(next);
+            }
+        }
+
+        book
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr as _;
+
+    use super::*;
+
+    #[test]
+    fn test_mt19937_64() {
+        let mut rng = Mt19937_64::new(Mt19937_64::DEFAULT_SEED);
+        assert_eq!(rng.next_u64(), 14514284786278117030);
+
+        // C++ 標準が規定する 10000 番目の値。
+        let mut rng = Mt19937_64::new(Mt19937_64::DEFAULT_SEED);
+        let value = (0..10000).map(|_| rng.next_u64()).last().unwrap();
+        assert_eq!(value, 9981545732273789042);
+    }
+
+    #[test]
+    fn test_position_apery_book_key() {
+        let pos = |s| Position::from_str(s).unwrap();
+
+        let startpos = Position::startpos();
+        let key = startpos.apery_book_key();
+        assert_ne!(key, 0);
+
+        // 手数は無視する。
+        assert_eq!(
+            pos("sfen lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 99")
+                .apery_book_key(),
+            key
+        );
+        // 手番は区別する。
+        assert_ne!(
+            pos("sfen lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1")
+                .apery_book_key(),
+            key
+        );
+        // 相手の手駒は含まない。
+        assert_eq!(
+            pos("sfen 4k4/9/9/9/9/9/9/9/4K4 b Pp 1").apery_book_key(),
+            pos("sfen 4k4/9/9/9/9/9/9/9/4K4 b P2p 1").apery_book_key()
+        );
+        assert_ne!(
+            pos("sfen 4k4/9/9/9/9/9/9/9/4K4 b Pp 1").apery_book_key(),
+            pos("sfen 4k4/9/9/9/9/9/9/9/4K4 b 2Pp 1").apery_book_key()
+        );
+    }
+
+    #[test]
+    fn test_position_hcp() {
+        let startpos = Position::startpos();
+        let hcp = startpos.to_hcp();
+
+        // 手番 (先手 = 0)、先手玉 5九 (= 44)。
+        assert_eq!(hcp[0], 44 << 1);
+        // 後手玉 5一 (= 36)、1一 の後手香の符号の先頭ビット (1)。
+        assert_eq!(hcp[1], 36 | 0x80);
+        // 1一 の後手香の残り (1, 0, 0, 手番 1, 成り 0)、1二 の空き (0)、1三 の後手歩の先頭 2 ビット (1, 0)。
+        assert_eq!(hcp[2], 0b0100_1001);
+        assert_ne!(hcp, startpos.to_packed_sfen());
+        assert_eq!(Position::from_hcp(&hcp), Ok(startpos));
+
+        for sfen in [
+            "sfen lnsgkgsnl/1r5b1/ppppppppp/9/9/2P6/PP1PPPPPP/1B5R1/LNSGKGSNL w - 1",
+            "sfen ln1g3nl/1r1s1kgs1/p1pppp1pp/6p2/1p7/2P6/PPSPPPPPP/7R1/LN1GKGSNL w Bb 1",
+            "sfen 8l/1+R5k1/p1+Bpppg1p/6p2/9/2P6/P2PPPP1P/1+p3K3/L+n2G3L b RBG3SNL2Pgs2n2p 1",
+            "sfen 4k4/9/9/9/9/9/9/9/4K4 b 2R2B4G4S4N4L9P9p 1",
+        ] {
+            let pos = Position::from_str(sfen).unwrap();
+            let hcp = pos.to_hcp();
+            assert_eq!(Position::from_hcp(&hcp), Ok(pos), "{sfen}");
+        }
+
+        assert_eq!(
+            Position::from_hcp(&[0; 32]),
+            Err(PackedSfenError::InvalidKingSquare)
+        );
+    }
+
+    #[test]
+    fn test_move_apery_u16() {
+        let mv = |s| Move::from_str(s).unwrap();
+
+        // 7七 → 7六: 移動元 = 9*6+6 = 60, 移動先 = 9*6+5 = 59。
+        assert_eq!(mv("7g7f").to_apery_u16(), 59 | (60 << 7));
+        assert_eq!(mv("8h2b+").to_apery_u16(), 10 | (70 << 7) | (1 << 14));
+        assert_eq!(mv("P*5e").to_apery_u16(), 40 | (81 << 7));
+        assert_eq!(mv("G*1a").to_apery_u16(), 87 << 7);
+
+        for s in ["7g7f", "8h2b+", "2b8h", "P*5e", "L*1i", "R*9a", "G*1a"] {
+            assert_eq!(Move::from_apery_u16(mv(s).to_apery_u16()), Ok(mv(s)));
+        }
+
+        assert_eq!(Move::from_apery_u16(0), Err(MoveDecodeError::InvalidSrc));
+        assert_eq!(Move::from_apery_u16(81), Err(MoveDecodeError::InvalidDst));
+        assert_eq!(
+            Move::from_apery_u16(40 | (88 << 7)),
+            Err(MoveDecodeError::InvalidSrc)
+        );
+        assert_eq!(
+            Move::from_apery_u16(40 | (81 << 7) | (1 << 14)),
+            Err(MoveDecodeError::InvalidFlags)
+        );
+        assert_eq!(
+            Move::from_apery_u16(59 | (60 << 7) | (1 << 15)),
+            Err(MoveDecodeError::InvalidFlags)
+        );
+    }
+
+    #[test]
+    fn test_apery_book() {
+        let mv = |s| Move::from_str(s).unwrap();
+
+        let mut book = Book::new();
+        let startpos = Position::startpos();
+        book.insert(
+            &startpos,
+            BookMove {
+                eval: 30,
+                count: 5,
+                ..BookMove::new(mv("7g7f"))
+            },
+        );
+        book.insert(
+            &startpos,
+            BookMove {
+                eval: 10,
+                count: 100000,
+                ..BookMove::new(mv("2g2f"))
+            },
+        );
+        let mut pos = startpos.clone();
+        pos.do_move(mv("7g7f")).unwrap();
+        book.insert(&pos, BookMove::new(mv("3c3d")));
+        // 初期局面から到達できない局面。
+        let orphan = Position::from_str("sfen 4k4/9/9/9/9/9/9/9/4K4 b - 1").unwrap();
+        book.insert(&orphan, BookMove::new(mv("5i5h")));
+
+        let apery = AperyBook::from_book(&book);
+        assert_eq!(apery.len(), 3);
+        assert_eq!(
+            apery.probe(&startpos).unwrap(),
+            [
+                AperyBookMove {
+                    mv: mv("7g7f"),
+                    count: 5,
+                    score: 30,
+                },
+                AperyBookMove {
+                    mv: mv("2g2f"),
+                    count: u16::MAX,
+                    score: 10,
+                },
+            ]
+        );
+
+        let bytes = apery.to_bytes();
+        assert_eq!(bytes.len(), 16 * 4);
+        let keys: Vec<_> = bytes
+            .chunks_exact(16)
+            .map(|entry| u64::from_le_bytes(entry[..8].try_into().unwrap()))
+            .collect();
+        assert!(keys.windows(2).all(|w| w[0] <= w[1]));
+        assert_eq!(AperyBook::from_bytes(&bytes).unwrap(), apery);
+
+        assert!(matches!(
+            AperyBook::from_bytes(&bytes[..20]),
+            Err(AperyBookParseError::InvalidLength { len: 20 })
+        ));
+
+        let book = apery.to_book(std::slice::from_ref(&startpos));
+        assert_eq!(book.len(), 2);
+        assert_eq!(book.get(&startpos).unwrap().len(), 2);
+        assert_eq!(book.get(&pos).unwrap(), [BookMove::new(mv("3c3d"))]);
+        assert!(book.get(&orphan).is_none());
+    }
+}
@@ -3,6 +3,7 @@ use std::fmt::Write as _;
 use crate::bytes::Bytes;
 use crate::parse::*;
 use crate::piece::*;
+use crate::pretty::*;
 use crate::square::*;
 
 /// 盤面。
@@ -152,7 +153,13 @@ impl std::str::FromStr for Board {
 
 impl std::fmt::Display for Board {
     /// SFEN 盤面文字列を出力する。
+    ///
+    /// `{:#}` の場合は端末向けの表示 (`PrettyStyle::Unicode`) を出力する。
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if f.alternate() {
+            return self.pretty(PrettyStyle::Unicode).fmt(f);
+        }
+
         for row in Row::all_private() {
             if row != ROW_1 {
                 f.write_char('/')?;
@@ -0,0 +1,413 @@
+//! BOD 形式の局面図 (KIF ファイルなどに埋め込まれる盤面図)。
+
+use std::fmt::Write as _;
+use std::num::NonZeroU32;
+
+use crate::board::*;
+use crate::hand::*;
+use crate::japanese::*;
+use crate::kif::*;
+use crate::piece::*;
+use crate::position::*;
+use crate::side::*;
+use crate::square::*;
+
+/// BOD 形式の局面図のパースエラー。
+#[non_exhaustive]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum BodParseError {
+    /// `line` 行目(1 始まり)が不正。
+    InvalidLine {
+        line: usize,
+        description: &'static str,
+    },
+
+    /// 盤面の行が 9 行揃っていない。
+    IncompleteBoard,
+}
+
+impl std::fmt::Display for BodParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::InvalidLine { line, description } => {
+                write!(f, "invalid line {line}: {description}")
+            }
+            Self::IncompleteBoard => f.write_str("board diagram is incomplete"),
+        }
+    }
+}
+
+impl std::error::Error for BodParseError {}
+
+const HAND_LABELS: [(&str, Side); 4] = [
+    ("先手の持駒：", SENTE),
+    ("下手の持駒：", SENTE),
+    ("後手の持駒：", GOTE),
+    ("上手の持駒：", GOTE),
+];
+
+const SIDE_TO_MOVE_LINES: [(&str, Side); 4] = [
+    ("先手番", SENTE),
+    ("下手番", SENTE),
+    ("後手番", GOTE),
+    ("上手番", GOTE),
+];
+
+/// 持駒欄での駒の並び順。
+pub(crate) const HAND_ORDER: [HandPieceKind; HandPieceKind::NUM] = [
+    HAND_ROOK,
+    HAND_BISHOP,
+    HAND_GOLD,
+    HAND_SILVER,
+    HAND_KNIGHT,
+    HAND_LANCE,
+    HAND_PAWN,
+];
+
+const KANJI_DIGITS: [char; 9] = ['一', '二', '三', '四', '五', '六', '七', '八', '九'];
+
+const BORDER: &str = "+---------------------------+";
+
+/// 持駒の枚数を漢数字で出力する。1 枚の場合は何も出力しない。
+fn fmt_hand_count(s: &mut String, n: u8) {
+    if n >= 10 {
+        s.push('十');
+    }
+    let ones = n % 10;
+    if ones != 0 && n != 1 {
+        s.push(KANJI_DIGITS[usize::from(ones - 1)]);
+    }
+}
+
+/// 持駒の枚数(漢数字)をパースする。空文字列は 1 枚とみなす。
+fn parse_hand_count(s: &str) -> Option<u8> {
+    if s.is_empty() {
+        return Some(1);
+    }
+
+    let (tens, s) = match s.strip_prefix('十') {
+        Some(s) => (10, s),
+        None => (0, s),
+    };
+    let ones = match s {
+        "" => 0,
+        _ => {
+            let (remain, n) = parse_kanji_digit(s)?;
+            if !remain.is_empty() {
+                return None;
+            }
+            n
+        }
+    };
+
+    Some(tens + ones)
+}
+
+/// 持駒欄の値 (`角　歩二` または `なし`) をパースする。
+fn parse_hand(s: &str) -> Option<Hand> {
+    let mut hand = Hand::empty();
+
+    if s == "なし" {
+        return Some(hand);
+    }
+
+    for token in s.split(['　', ' ']).filter(|token| !token.is_empty()) {
+        let (remain, pk) = parse_piece_kind(token)?;
+        let hpk = HandPieceKind::try_from(pk).ok()?;
+        hand[hpk] = hand[hpk].checked_add(parse_hand_count(remain)?)?;
+    }
+
+    Some(hand)
+}
+
+/// 盤面の行 (`|v香v桂 ・ ...|一`) をパースする。
+fn parse_board_row(s: &str) -> Option<[Option<Piece>; Col::NUM]> {
+    let s = s.strip_prefix(['|', '｜'])?;
+    let mut chars = s.chars();
+
+    let mut row = [None; Col::NUM];
+    for cell in &mut row {
+        let (side, c) = match chars.next()? {
+            'v' | 'ｖ' => (GOTE, chars.next()?),
+            ' ' | '　' | '^' => (SENTE, chars.next()?),
+            c => (SENTE, c),
+        };
+        if c == '・' {
+            continue;
+        }
+        let mut buf = [0; 4];
+        let (remain, pk) = parse_piece_kind(c.encode_utf8(&mut buf))?;
+        if !remain.is_empty() {
+            return None;
+        }
+        *cell = Some(Piece::new(side, pk));
+    }
+
+    // 行末の段番号は任意。
+    matches!(chars.next(), Some('|' | '｜')).then_some(row)
+}
+
+/// 筋番号の行 (`９ ８ ７ ６ ５ ４ ３ ２ １`) かどうかを返す。
+fn is_col_header(s: &str) -> bool {
+    let digits = s.chars().filter(|&c| c != ' ' && c != '　');
+    digits.clone().eq("９８７６５４３２１".chars()) || digits.eq("987654321".chars())
+}
+
+/// BOD 形式の局面図の読み取り状態。KIF のヘッダ中の局面図の読み取りにも用いる。
+pub(crate) struct BodReader {
+    board: Board,
+    hands: Hands,
+    side_to_move: Side,
+    ply: NonZeroU32,
+    n_rows: usize,
+    started: bool,
+}
+
+impl BodReader {
+    pub(crate) fn new() -> Self {
+        Self {
+            board: Board::empty(),
+            hands: Hands::empty(),
+            side_to_move: SENTE,
+            ply: PLY_1,
+            n_rows: 0,
+            started: false,
+        }
+    }
+
+    /// 局面図の行なら読み取って `true` を返す。局面図の行でなければ何もせず `false` を返す。
+    ///
+    /// `line` の前後の空白は除かれているものとする。
+    pub(crate) fn read_line(&mut self, line: &str) -> Result<bool, &'static str> {
+        if let Some((value, side)) = HAND_LABELS
+            .iter()
+            .find_map(|&(label, side)| line.strip_prefix(label).map(|value| (value, side)))
+        {
+            self.hands[side] = parse_hand(trim_kif(value)).ok_or("invalid hand")?;
+        } else if line.starts_with(['|', '｜']) {
+            if self.n_rows == Row::NUM {
+                return Err("too many board rows");
+            }
+            let row = Row::all()[self.n_rows];
+            let pcs = parse_board_row(line).ok_or("invalid board row")?;
+            for (col, pc) in Col::all().into_iter().zip(pcs) {
+                self.board[Square::new(col, row)] = pc;
+            }
+            self.n_rows += 1;
+        } else if let Some(rest) = line.strip_prefix("手数＝") {
+            let digits = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .map_or(rest, |end| &rest[..end]);
+            let n_moves: u32 = digits.parse().map_err(|_| "invalid move count")?;
+            self.ply = n_moves
+                .checked_add(1)
+                .and_then(NonZeroU32::new)
+                .ok_or("invalid move count")?;
+        } else if let Some(&(_, side)) = SIDE_TO_MOVE_LINES.iter().find(|&&(s, _)| s == line) {
+            self.side_to_move = side;
+        } else if !(line.starts_with('+') || is_col_header(line)) {
+            return Ok(false);
+        }
+
+        self.started = true;
+        Ok(true)
+    }
+
+    /// 読み取った局面を返す。局面図の行を 1 行も読んでいなければ `None` を返す。
+    pub(crate) fn finish(self) -> Result<Option<Position>, &'static str> {
+        if !self.started {
+            return Ok(None);
+        }
+        if self.n_rows != Row::NUM {
+            return Err("incomplete board");
+        }
+
+        Ok(Some(Position::new(
+            self.side_to_move,
+            self.board,
+            self.hands,
+            self.ply,
+        )))
+    }
+}
+
+/// 局面図を出力する。
+pub(crate) fn write_bod(s: &mut String, pos: &Position) {
+    let write_hand = |s: &mut String, label: &str, hand: &Hand| {
+        s.push_str(label);
+        if HAND_ORDER.iter().all(|&hpk| hand[hpk] == 0) {
+            s.push_str("なし");
+        }
+        for (i, hpk) in HAND_ORDER
+            .into_iter()
+            .filter(|&hpk| hand[hpk] > 0)
+            .enumerate()
+        {
+            if i > 0 {
+                s.push('　');
+            }
+            s.push_str(piece_kind_to_name(hpk.into()));
+            fmt_hand_count(s, hand[hpk]);
+        }
+        s.push('\n');
+    };
+
+    write_hand(s, "後手の持駒：", &pos.hands()[GOTE]);
+    s.push_str("  ９ ８ ７ ６ ５ ４ ３ ２ １\n");
+    s.push_str(BORDER);
+    s.push('\n');
+    for row in Row::all() {
+        s.push('|');
+        for col in Col::all() {
+            match pos.board()[Square::new(col, row)] {
+                Some(pc) => {
+                    s.push(if pc.side() == SENTE { ' ' } else { 'v' });
+                    s.push(piece_kind_to_kanji(pc.kind()));
+                }
+                None => s.push_str(" ・"),
+            }
+        }
+        s.push('|');
+        s.push(row_to_kanji(row));
+        s.push('\n');
+    }
+    s.push_str(BORDER);
+    s.push('\n');
+    write_hand(s, "先手の持駒：", &pos.hands()[SENTE]);
+
+    if pos.ply().get() > 1 {
+        writeln!(s, "手数＝{}", pos.ply().get() - 1).unwrap();
+    }
+    s.push_str(match pos.side_to_move() {
+        SENTE => "先手番\n",
+        GOTE => "後手番\n",
+    });
+}
+
+impl Position {
+    /// BOD 形式の局面図をパースする。
+    ///
+    /// `上手`/`下手` の表記も受け付ける。`手数＝` 行がなければ手数は 1、手番行がなければ先手番とする。
+    pub fn from_bod(s: &str) -> Result<Self, BodParseError> {
+        let mut reader = BodReader::new();
+
+        for (i, line) in s.lines().enumerate() {
+            let line = trim_kif(line);
+            if line.is_empty() {
+                continue;
+            }
+            let invalid = |description| BodParseError::InvalidLine {
+                line: i + 1,
+                description,
+            };
+            if !reader.read_line(line).map_err(invalid)? {
+                return Err(invalid("unknown line"));
+            }
+        }
+
+        reader
+            .finish()
+            .ok()
+            .flatten()
+            .ok_or(BodParseError::IncompleteBoard)
+    }
+
+    /// BOD 形式の局面図を返す。
+    ///
+    /// `手数＝` 行は手数が 2 以上の場合のみ出力する。手番行は常に出力する。
+    pub fn to_bod(&self) -> String {
+        let mut s = String::new();
+        write_bod(&mut s, self);
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr as _;
+
+    use super::*;
+
+    const SAMPLE: &str = "\
+後手の持駒：飛　金　銀二　歩十一
+  ９ ８ ７ ６ ５ ４ ３ ２ １
++---------------------------+
+|v香v桂 ・ ・ ・ ・ ・v桂v香|一
+| ・ ・ ・ ・ ・ ・ ・ ・ ・|二
+| ・ ・ ・ ・v玉 ・ ・ ・ ・|三
+| ・ ・ ・ ・ ・ ・ ・ ・ ・|四
+| ・ ・ ・ ・ ・ ・ ・ ・ ・|五
+| ・ ・ ・ ・ ・ ・ ・ ・ ・|六
+| ・ ・ ・ ・ ・ ・ ・ ・ ・|七
+| ・ ・ ・ ・ 馬 ・ 龍 ・ ・|八
+| 香 桂 ・ ・ 玉 ・ ・ 桂 香|九
++---------------------------+
+先手の持駒：金二　銀二　歩七
+手数＝40
+後手番
+";
+
+    #[test]
+    fn test_bod_parse() {
+        let pos = Position::from_bod(SAMPLE).unwrap();
+        assert_eq!(
+            pos,
+            Position::from_str("ln5nl/9/4k4/9/9/9/9/4+B1+R2/LN2K2NL w 2G2S7Prg2s11p 41").unwrap()
+        );
+
+        let pos = Position::from_bod(
+            "\
+上手の持駒：なし
+｜ｖ香ｖ桂ｖ銀ｖ金ｖ玉ｖ金ｖ銀ｖ桂ｖ香｜一
+｜ ・ｖ飛 ・ ・ ・ ・ ・ｖ角 ・｜二
+｜ｖ歩ｖ歩ｖ歩ｖ歩ｖ歩ｖ歩ｖ歩ｖ歩ｖ歩｜三
+｜ ・ ・ ・ ・ ・ ・ ・ ・ ・｜四
+｜ ・ ・ ・ ・ ・ ・ ・ ・ ・｜五
+｜ ・ ・ ・ ・ ・ ・ ・ ・ ・｜六
+｜ 歩 歩 歩 歩 歩 歩 歩 歩 歩｜七
+｜ ・ 角 ・ ・ ・ ・ ・ 飛 ・｜八
+｜ 香 桂 銀 金 王 金 銀 桂 香｜九
+下手の持駒：なし
+",
+        )
+        .unwrap();
+        assert_eq!(pos, Position::startpos());
+    }
+
+    #[test]
+    fn test_bod_parse_error() {
+        assert_eq!(
+            Position::from_bod("後手の持駒：なし\n先手の持駒：なし\n"),
+            Err(BodParseError::IncompleteBoard)
+        );
+        assert_eq!(
+            Position::from_bod("後手の持駒：玉\n"),
+            Err(BodParseError::InvalidLine {
+                line: 1,
+                description: "invalid hand"
+            })
+        );
+        assert_eq!(
+            Position::from_bod("| ・ ・ ・ ・ ・ ・ ・ ・|一\n"),
+            Err(BodParseError::InvalidLine {
+                line: 1,
+                description: "invalid board row"
+            })
+        );
+        assert!(matches!(
+            Position::from_bod("先手：名人\n"),
+            Err(BodParseError::InvalidLine { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_bod_fmt() {
+        let pos = Position::from_bod(SAMPLE).unwrap();
+        assert_eq!(pos.to_bod(), SAMPLE);
+
+        let bod = Position::startpos().to_bod();
+        assert!(bod.starts_with("後手の持駒：なし\n"));
+        assert!(bod.ends_with("先手の持駒：なし\n先手番\n"));
+        assert_eq!(Position::from_bod(&bod).unwrap(), Position::startpos());
+    }
+}
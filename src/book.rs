@@ -0,0 +1,408 @@
+//! 定跡。
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::num::NonZeroU32;
+
+use crate::move_::*;
+use crate::position::*;
+
+/// 定跡の候補手。
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct BookMove {
+    /// 指し手。
+    pub mv: Move,
+
+    /// 予想応手。
+    pub ponder: Option<Move>,
+
+    /// 評価値。
+    pub eval: i32,
+
+    /// 探索深さ。
+    pub depth: u32,
+
+    /// 出現回数。
+    pub count: u64,
+}
+
+impl BookMove {
+    /// 指し手のみを指定して作る。その他の値は空(0)とする。
+    pub const fn new(mv: Move) -> Self {
+        Self {
+            mv,
+            ponder: None,
+            eval: 0,
+            depth: 0,
+            count: 0,
+        }
+    }
+}
+
+/// 定跡のパースエラー。
+#[non_exhaustive]
+#[derive(Clone, Debug)]
+pub enum BookParseError {
+    /// `line` 行目(1 始まり)のパースに失敗した。
+    InvalidLine {
+        line: usize,
+        description: &'static str,
+    },
+}
+
+impl std::fmt::Display for BookParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::InvalidLine { line, description } => {
+                write!(f, "invalid line {line}: {description}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BookParseError {}
+
+/// メモリ上の定跡。
+///
+/// 局面をキーとし、手数は無視する(手数のみが異なる局面は同一とみなす)。
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Book {
+    /// 手数を 1 とした局面 → (最初に登録された手数, 候補手)。
+    positions: HashMap<Position, (NonZeroU32, Vec<BookMove>)>,
+}
+
+const YANEURAOU_DB_HEADER: &str = "#YANEURAOU-DB2016 1.00";
+
+impl Book {
+    /// 空の定跡を作る。
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 登録されている局面数を返す。
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// 空かどうかを返す。
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// 局面 `pos` の候補手を返す。
+    pub fn get(&self, pos: &Position) -> Option<&[BookMove]> {
+        self.positions
+            .get(&pos.without_ply())
+            .map(|(_, bms)| bms.as_slice())
+    }
+
+    /// 局面 `pos` の候補手への可変参照を返す。
+    pub fn get_mut(&mut self, pos: &Position) -> Option<&mut Vec<BookMove>> {
+        self.positions
+            .get_mut(&pos.without_ply())
+            .map(|(_, bms)| bms)
+    }
+
+    /// 局面 `pos` に候補手を登録する。同じ指し手が既にあれば置き換える。
+    pub fn insert(&mut self, pos: &Position, bm: BookMove) {
+        let (_, bms) = self
+            .positions
+            .entry(pos.without_ply())
+            .or_insert_with(|| (pos.ply(), vec![]));
+
+        match bms.iter_mut().find(|e| e.mv == bm.mv) {
+            Some(e) => *e = bm,
+            None => bms.push(bm),
+        }
+    }
+
+    /// 局面 `pos` とその候補手を削除し、候補手を返す。
+    pub fn remove(&mut self, pos: &Position) -> Option<Vec<BookMove>> {
+        self.positions
+            .remove(&pos.without_ply())
+            .map(|(_, bms)| bms)
+    }
+
+    /// 全ての局面と候補手を列挙する。順序は不定。
+    ///
+    /// 局面の手数は最初に登録されたときのものになる。
+    pub fn iter(&self) -> impl Iterator<Item = (Position, &[BookMove])> {
+        self.positions.iter().map(|(key, (ply, bms))| {
+            let pos = Position::new(
+                key.side_to_move(),
+                key.board().clone(),
+                key.hands().clone(),
+                *ply,
+            );
+            (pos, bms.as_slice())
+        })
+    }
+
+    /// 他の定跡をマージする。
+    ///
+    /// 両方にある指し手については、出現回数を合計し、その他の値は探索深さが大きい方を採る
+    /// (等しければ `self` の方を採る)。
+    pub fn merge(&mut self, other: Book) {
+        for (key, (ply, other_bms)) in other.positions {
+            let (_, bms) = self.positions.entry(key).or_insert_with(|| (ply, vec![]));
+            for other_bm in other_bms {
+                match bms.iter_mut().find(|e| e.mv == other_bm.mv) {
+                    Some(e) => {
+                        let count = e.count.saturating_add(other_bm.count);
+                        if other_bm.depth > e.depth {
+                            *e = other_bm;
+                        }
+                        e.count = count;
+                    }
+                    None => bms.push(other_bm),
+                }
+            }
+        }
+    }
+
+    /// 各局面の候補手を出現回数の降順、次いで評価値の降順に並べ替える。
+    pub fn sort(&mut self) {
+        for (_, bms) in self.positions.values_mut() {
+            bms.sort_by(|lhs, rhs| {
+                rhs.count
+                    .cmp(&lhs.count)
+                    .then_with(|| rhs.eval.cmp(&lhs.eval))
+            });
+        }
+    }
+
+    /// やねうら王の定跡形式 (`#YANEURAOU-DB2016 1.00`) の文字列をパースする。
+    ///
+    /// `#` で始まる行、`//` で始まる行、空行は無視する。
+    /// 候補手の行の評価値、探索深さ、出現回数は省略でき、省略時は 0 とする。
+    /// 同一局面が複数回現れた場合、候補手は `insert` と同様に登録される。
+    pub fn from_yaneuraou_db(s: &str) -> Result<Self, BookParseError> {
+        let mut book = Self::new();
+        let mut pos = None;
+
+        for (i, line) in s.lines().enumerate() {
+            let invalid = |description| BookParseError::InvalidLine {
+                line: i + 1,
+                description,
+            };
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') || line.starts_with("//") {
+                continue;
+            }
+
+            if line.starts_with("sfen ") {
+                let p: Position = line.parse().map_err(|_| invalid("invalid position"))?;
+                pos = Some(p);
+                continue;
+            }
+
+            let pos = pos
+                .as_ref()
+                .ok_or(invalid("`sfen` line expected before moves"))?;
+
+            let mut words = line.split_ascii_whitespace();
+            let mv = words
+                .next()
+                .unwrap()
+                .parse()
+                .map_err(|_| invalid("invalid move"))?;
+            let ponder = match words.next() {
+                None | Some("none") => None,
+                Some(s) => Some(s.parse().map_err(|_| invalid("invalid ponder move"))?),
+            };
+            let eval = words
+                .next()
+                .map_or(Ok(0), str::parse)
+                .map_err(|_| invalid("invalid eval"))?;
+            let depth = words
+                .next()
+                .map_or(Ok(0), str::parse)
+                .map_err(|_| invalid("invalid depth"))?;
+            let count = words
+                .next()
+                .map_or(Ok(0), str::parse)
+                .map_err(|_| invalid("invalid count"))?;
+
+            book.insert(
+                pos,
+                BookMove {
+                    mv,
+                    ponder,
+                    eval,
+                    depth,
+                    count,
+                },
+            );
+        }
+
+        Ok(book)
+    }
+
+    /// やねうら王の定跡形式 (`#YANEURAOU-DB2016 1.00`) の文字列を返す。
+    ///
+    /// 局面は (手数を除いた) SFEN 文字列の昇順に並べる。候補手の順序は保つ。
+    pub fn to_yaneuraou_db(&self) -> String {
+        let mut entries: Vec<_> = self
+            .positions
+            .iter()
+            .map(|(key, (ply, bms))| {
+                let sfen = format!(
+                    "sfen {} {} {}",
+                    key.board(),
+                    key.side_to_move(),
+                    key.hands()
+                );
+                (sfen, ply, bms)
+            })
+            .collect();
+        entries.sort_unstable_by(|lhs, rhs| lhs.0.cmp(&rhs.0));
+
+        let mut s = String::new();
+        s.push_str(YANEURAOU_DB_HEADER);
+        s.push('\n');
+
+        for (sfen, ply, bms) in entries {
+            writeln!(s, "{sfen} {ply}").unwrap();
+            for bm in bms {
+                let ponder = bm.ponder.map_or("none".to_owned(), |mv| mv.to_string());
+                writeln!(
+                    s,
+                    "{} {ponder} {} {} {}",
+                    bm.mv, bm.eval, bm.depth, bm.count
+                )
+                .unwrap();
+            }
+        }
+
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr as _;
+
+    use super::*;
+
+    fn mv(s: &str) -> Move {
+        Move::from_str(s).unwrap()
+    }
+
+    const DB: &str = "\
+#YANEURAOU-DB2016 1.00
+sfen lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1
+7g7f 3c3d 30 20 5
+2g2f none -10 18 2
+sfen lnsgkgsnl/1r5b1/ppppppppp/9/9/2P6/PP1PPPPPP/1B5R1/LNSGKGSNL w - 2
+3c3d
+";
+
+    #[test]
+    fn test_book_yaneuraou_db() {
+        let book = Book::from_yaneuraou_db(DB).unwrap();
+        assert_eq!(book.len(), 2);
+
+        // 手数は無視する。
+        let pos = Position::from_str(
+            "sfen lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 99",
+        )
+        .unwrap();
+        assert_eq!(
+            book.get(&pos).unwrap(),
+            [
+                BookMove {
+                    mv: mv("7g7f"),
+                    ponder: Some(mv("3c3d")),
+                    eval: 30,
+                    depth: 20,
+                    count: 5,
+                },
+                BookMove {
+                    mv: mv("2g2f"),
+                    ponder: None,
+                    eval: -10,
+                    depth: 18,
+                    count: 2,
+                },
+            ]
+        );
+
+        let pos = Position::from_str(
+            "sfen lnsgkgsnl/1r5b1/ppppppppp/9/9/2P6/PP1PPPPPP/1B5R1/LNSGKGSNL w - 2",
+        )
+        .unwrap();
+        assert_eq!(book.get(&pos).unwrap(), [BookMove::new(mv("3c3d"))]);
+
+        assert_eq!(
+            book.to_yaneuraou_db(),
+            "\
+#YANEURAOU-DB2016 1.00
+sfen lnsgkgsnl/1r5b1/ppppppppp/9/9/2P6/PP1PPPPPP/1B5R1/LNSGKGSNL w - 2
+3c3d none 0 0 0
+sfen lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1
+7g7f 3c3d 30 20 5
+2g2f none -10 18 2
+"
+        );
+        assert_eq!(
+            Book::from_yaneuraou_db(&book.to_yaneuraou_db()).unwrap(),
+            book
+        );
+
+        assert!(matches!(
+            Book::from_yaneuraou_db("7g7f none 0 0 0"),
+            Err(BookParseError::InvalidLine { line: 1, .. })
+        ));
+        assert!(matches!(
+            Book::from_yaneuraou_db("sfen 4k4/9/9/9/9/9/9/9/4K4 b - 1\n5i5h none x"),
+            Err(BookParseError::InvalidLine { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn test_book_merge_sort() {
+        let startpos = Position::startpos();
+        let bm = |s, eval, depth, count| BookMove {
+            mv: mv(s),
+            ponder: None,
+            eval,
+            depth,
+            count,
+        };
+
+        let mut book = Book::new();
+        book.insert(&startpos, bm("7g7f", 30, 20, 5));
+        book.insert(&startpos, bm("2g2f", 10, 18, 2));
+
+        let mut other = Book::new();
+        other.insert(&startpos, bm("2g2f", 50, 24, 4));
+        other.insert(&startpos, bm("5g5f", 10, 10, 7));
+        let pos = Position::from_str("sfen 4k4/9/9/9/9/9/9/9/4K4 b - 1").unwrap();
+        other.insert(&pos, bm("5i5h", 0, 1, 1));
+
+        book.merge(other);
+        assert_eq!(book.len(), 2);
+        assert_eq!(
+            book.get(&startpos).unwrap(),
+            [
+                bm("7g7f", 30, 20, 5),
+                bm("2g2f", 50, 24, 6),
+                bm("5g5f", 10, 10, 7),
+            ]
+        );
+
+        book.sort();
+        assert_eq!(
+            book.get(&startpos).unwrap(),
+            [
+                bm("5g5f", 10, 10, 7),
+                bm("2g2f", 50, 24, 6),
+                bm("7g7f", 30, 20, 5),
+            ]
+        );
+
+        assert_eq!(book.remove(&pos).unwrap(), [bm("5i5h", 0, 1, 1)]);
+        assert_eq!(book.len(), 1);
+    }
+}
@@ -0,0 +1,1028 @@
+//! CSA 形式の指し手、局面、および CSA サーバプロトコルの対局条件 (`Game_Summary`)。
+
+use std::fmt::Write as _;
+use std::time::Duration;
+
+use crate::board::*;
+use crate::hand::*;
+use crate::kifu::*;
+use crate::move_::*;
+use crate::piece::*;
+use crate::position::*;
+use crate::referee::*;
+use crate::side::*;
+use crate::square::*;
+
+/// CSA 形式の指し手のパースエラー。
+#[non_exhaustive]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum CsaMoveParseError {
+    /// 表記として不正。
+    InvalidNotation { description: &'static str },
+
+    /// 先後記号が手番と一致しない。
+    SideMismatch,
+
+    /// 駒種が移動元の駒(または手駒)と整合しない。
+    PieceMismatch,
+
+    /// 指し手を局面に適用できない。
+    InvalidMove(MoveError),
+}
+
+impl std::fmt::Display for CsaMoveParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::InvalidNotation { description } => {
+                write!(f, "invalid CSA move notation: {description}")
+            }
+            Self::SideMismatch => f.write_str("side of move does not match side to move"),
+            Self::PieceMismatch => f.write_str("piece kind does not match the moving piece"),
+            Self::InvalidMove(e) => write!(f, "invalid move: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for CsaMoveParseError {}
+
+/// CSA 形式の棋譜データのパースエラー。
+#[non_exhaustive]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum CsaParseError {
+    /// `line` 行目(1 始まり)が不正。
+    InvalidLine {
+        line: usize,
+        description: &'static str,
+    },
+
+    /// 必須の項目 `name` がない。
+    MissingField { name: &'static str },
+}
+
+impl std::fmt::Display for CsaParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::InvalidLine { line, description } => {
+                write!(f, "invalid line {line}: {description}")
+            }
+            Self::MissingField { name } => write!(f, "missing field: {name}"),
+        }
+    }
+}
+
+impl std::error::Error for CsaParseError {}
+
+const fn side_to_csa(side: Side) -> char {
+    match side {
+        SENTE => '+',
+        GOTE => '-',
+    }
+}
+
+const fn side_from_csa(c: u8) -> Option<Side> {
+    match c {
+        b'+' => Some(SENTE),
+        b'-' => Some(GOTE),
+        _ => None,
+    }
+}
+
+/// `"77"` のような 2 桁の数字をマスとしてパースする。
+fn parse_square(s: &[u8]) -> Option<Square> {
+    let [col, row] = s else {
+        return None;
+    };
+    let col = Col::from_num(col.wrapping_sub(b'0'))?;
+    let row = Row::from_num(row.wrapping_sub(b'0'))?;
+
+    Some(Square::new(col, row))
+}
+
+fn parse_piece_kind(s: &[u8]) -> Option<PieceKind> {
+    PieceKind::from_csa(std::str::from_utf8(s).ok()?)
+}
+
+impl Move {
+    /// 局面 `pos` における指し手を CSA 形式 (`+7776FU`, `-0055KA` など) で返す。
+    ///
+    /// 駒種は移動後のものを書く。
+    pub fn to_csa(self, pos: &Position) -> Result<String, MoveError> {
+        let us = pos.side_to_move();
+        pos.clone().do_move(self)?;
+
+        let (src, pk) = match self {
+            Self::Walk(walk) => {
+                let pk = pos.board()[walk.src()].unwrap().kind();
+                let pk = if walk.is_promotion() {
+                    pk.promote().unwrap()
+                } else {
+                    pk
+                };
+                (
+                    format!("{}{}", walk.src().col().to_num(), walk.src().row().to_num()),
+                    pk,
+                )
+            }
+            Self::Drop(drop) => ("00".to_owned(), PieceKind::from(drop.piece_kind())),
+        };
+        let dst = self.dst();
+
+        Ok(format!(
+            "{}{src}{}{}{}",
+            side_to_csa(us),
+            dst.col().to_num(),
+            dst.row().to_num(),
+            pk.to_csa()
+        ))
+    }
+
+    /// 局面 `pos` における CSA 形式の指し手をパースする。
+    ///
+    /// 先後記号は必須で、手番と一致しなければならない。
+    /// 駒種が移動元の駒の成駒なら成る手とみなす。
+    pub fn from_csa(s: &str, pos: &Position) -> Result<Self, CsaMoveParseError> {
+        let invalid = |description| CsaMoveParseError::InvalidNotation { description };
+
+        let bytes = s.as_bytes();
+        if bytes.len() != 7 {
+            return Err(invalid("CSA move must be 7 characters"));
+        }
+
+        let side = side_from_csa(bytes[0]).ok_or(invalid("`+` or `-` expected"))?;
+        if side != pos.side_to_move() {
+            return Err(CsaMoveParseError::SideMismatch);
+        }
+        let dst = parse_square(&bytes[3..5]).ok_or(invalid("invalid destination square"))?;
+        let pk = parse_piece_kind(&bytes[5..7]).ok_or(invalid("invalid piece kind"))?;
+
+        let mv = if &bytes[1..3] == b"00" {
+            let hpk = HandPieceKind::try_from(pk).map_err(|_| CsaMoveParseError::PieceMismatch)?;
+            Self::drop(hpk, dst)
+        } else {
+            let src = parse_square(&bytes[1..3]).ok_or(invalid("invalid source square"))?;
+            let pc = pos.board()[src]
+                .ok_or(CsaMoveParseError::InvalidMove(MoveError::NoOwnPieceAtSrc))?;
+            let promo = if pc.kind() == pk {
+                false
+            } else if pc.kind().promote() == Some(pk) {
+                true
+            } else {
+                return Err(CsaMoveParseError::PieceMismatch);
+            };
+            Self::walk(src, dst, promo)
+        };
+
+        pos.clone()
+            .do_move(mv)
+            .map_err(CsaMoveParseError::InvalidMove)?;
+
+        Ok(mv)
+    }
+}
+
+/// CSA サーバプロトコルの特殊な指し手。
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum CsaSpecialMove {
+    /// `%TORYO`: 投了。
+    Resign,
+
+    /// `%KACHI`: 入玉宣言。
+    DeclareWin,
+}
+
+impl CsaSpecialMove {
+    pub(crate) const fn to_csa(self) -> &'static str {
+        match self {
+            Self::Resign => "%TORYO",
+            Self::DeclareWin => "%KACHI",
+        }
+    }
+
+    pub(crate) fn from_csa(s: &str) -> Option<Self> {
+        [Self::Resign, Self::DeclareWin]
+            .into_iter()
+            .find(|special| special.to_csa() == s)
+    }
+}
+
+/// CSA サーバプロトコルの終局理由 (`#RESIGN` など)。
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum CsaEndReason {
+    /// `#RESIGN`: 投了。
+    Resign,
+
+    /// `#TIME_UP`: 時間切れ。
+    TimeUp,
+
+    /// `#ILLEGAL_MOVE`: 反則。
+    IllegalMove,
+
+    /// `#SENNICHITE`: 千日手。
+    Sennichite,
+
+    /// `#OUTE_SENNICHITE`: 連続王手の千日手。
+    OuteSennichite,
+
+    /// `#JISHOGI`: 入玉宣言。
+    Jishogi,
+
+    /// `#MAX_MOVES`: 最大手数。
+    MaxMoves,
+}
+
+impl CsaEndReason {
+    const ALL: [Self; 7] = [
+        Self::Resign,
+        Self::TimeUp,
+        Self::IllegalMove,
+        Self::Sennichite,
+        Self::OuteSennichite,
+        Self::Jishogi,
+        Self::MaxMoves,
+    ];
+
+    pub(crate) const fn to_csa(self) -> &'static str {
+        match self {
+            Self::Resign => "#RESIGN",
+            Self::TimeUp => "#TIME_UP",
+            Self::IllegalMove => "#ILLEGAL_MOVE",
+            Self::Sennichite => "#SENNICHITE",
+            Self::OuteSennichite => "#OUTE_SENNICHITE",
+            Self::Jishogi => "#JISHOGI",
+            Self::MaxMoves => "#MAX_MOVES",
+        }
+    }
+
+    pub(crate) fn from_csa(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|reason| reason.to_csa() == s)
+    }
+}
+
+/// CSA サーバプロトコルの対局結果 (`#WIN` など)。受信者から見た結果。
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum CsaGameResult {
+    /// `#WIN`: 勝ち。
+    Win,
+
+    /// `#LOSE`: 負け。
+    Lose,
+
+    /// `#DRAW`: 引き分け。
+    Draw,
+
+    /// `#CENSORED`: 打ち切り。
+    Censored,
+
+    /// `#CHUDAN`: 中断。
+    Interrupted,
+}
+
+impl CsaGameResult {
+    const ALL: [Self; 5] = [
+        Self::Win,
+        Self::Lose,
+        Self::Draw,
+        Self::Censored,
+        Self::Interrupted,
+    ];
+
+    pub(crate) const fn to_csa(self) -> &'static str {
+        match self {
+            Self::Win => "#WIN",
+            Self::Lose => "#LOSE",
+            Self::Draw => "#DRAW",
+            Self::Censored => "#CENSORED",
+            Self::Interrupted => "#CHUDAN",
+        }
+    }
+
+    pub(crate) fn from_csa(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|result| result.to_csa() == s)
+    }
+}
+
+/// 指し手の行末の消費時間 (`,T12`) を分離する。
+pub(crate) fn split_time(line: &str) -> Result<(&str, Option<u64>), &'static str> {
+    match line.split_once(',') {
+        None => Ok((line, None)),
+        Some((body, time)) => {
+            let time = time
+                .strip_prefix('T')
+                .and_then(|n| n.parse().ok())
+                .ok_or("invalid time report")?;
+            Ok((body, Some(time)))
+        }
+    }
+}
+
+/// 平手の駒の総数(手駒になりうる駒種のみ)。`00AL` の処理に用いる。
+const fn total_count(hpk: HandPieceKind) -> u8 {
+    match hpk {
+        HAND_PAWN => 18,
+        HAND_LANCE | HAND_KNIGHT | HAND_SILVER | HAND_GOLD => 4,
+        HAND_BISHOP | HAND_ROOK => 2,
+    }
+}
+
+/// CSA 形式の局面(`P1`〜`P9`, `PI`, `P+`, `P-`, 手番行)と、それに続く指し手を 1 行ずつ読む。
+#[derive(Debug)]
+pub(crate) struct CsaPositionReader {
+    board: Board,
+    hands: Hands,
+    start: Option<Position>,
+    pos: Option<Position>,
+    mvs: Vec<Move>,
+}
+
+impl CsaPositionReader {
+    pub(crate) fn new() -> Self {
+        Self {
+            board: Board::empty(),
+            hands: Hands::empty(),
+            start: None,
+            pos: None,
+            mvs: vec![],
+        }
+    }
+
+    /// 1 行を読む。`,` で区切られた複数の文は順に処理する。
+    pub(crate) fn read_line(&mut self, line: &str) -> Result<(), &'static str> {
+        if line.starts_with('\'') {
+            return Ok(());
+        }
+        line.split(',')
+            .try_for_each(|stmt| self.read_statement(stmt.trim_end_matches(['\r', '\n'])))
+    }
+
+    fn read_statement(&mut self, stmt: &str) -> Result<(), &'static str> {
+        let bytes = stmt.as_bytes();
+        if stmt.trim().is_empty() || bytes[0] == b'T' || bytes[0] == b'%' {
+            return Ok(());
+        }
+
+        // 手番行以降は指し手。
+        if let Some(pos) = &mut self.pos {
+            let mv = Move::from_csa(stmt.trim(), pos).map_err(|_| "invalid move")?;
+            pos.do_move(mv).unwrap();
+            self.mvs.push(mv);
+            return Ok(());
+        }
+
+        match bytes {
+            [b'P', b'I', rest @ ..] => {
+                self.board = Board::startpos();
+                self.hands = Hands::empty();
+                for chunk in rest.chunks(4) {
+                    let [sq @ .., a, b] = chunk else {
+                        return Err("invalid piece in `PI` line");
+                    };
+                    let sq = parse_square(sq).ok_or("invalid square in `PI` line")?;
+                    let pk = parse_piece_kind(&[*a, *b]).ok_or("invalid piece kind")?;
+                    if self.board[sq].map(Piece::kind) != Some(pk) {
+                        return Err("piece to remove does not exist");
+                    }
+                    self.board[sq] = None;
+                }
+            }
+            [b'P', sign @ (b'+' | b'-'), rest @ ..] => {
+                let side = side_from_csa(*sign).unwrap();
+                for chunk in rest.chunks(4) {
+                    let [sq @ .., a, b] = chunk else {
+                        return Err("invalid piece in `P+`/`P-` line");
+                    };
+                    if sq == b"00" && [*a, *b] == *b"AL" {
+                        self.put_rest_in_hand(side)?;
+                        continue;
+                    }
+                    let pk = parse_piece_kind(&[*a, *b]).ok_or("invalid piece kind")?;
+                    if sq == b"00" {
+                        let hpk =
+                            HandPieceKind::try_from(pk).map_err(|_| "invalid piece in hand")?;
+                        let n = &mut self.hands[side][hpk];
+                        *n = n.saturating_add(1);
+                    } else {
+                        let sq = parse_square(sq).ok_or("invalid square")?;
+                        self.board[sq] = Some(Piece::new(side, pk));
+                    }
+                }
+            }
+            [b'P', row @ b'1'..=b'9', rest @ ..] => {
+                let row = Row::from_num(row - b'0').unwrap();
+                if rest.len() > 27 {
+                    return Err("board row too long");
+                }
+                let mut cells = rest.to_vec();
+                cells.resize(27, b' ');
+                for (col, cell) in Col::all_private().into_iter().zip(cells.chunks(3)) {
+                    let sq = Square::new(col, row);
+                    self.board[sq] = match cell {
+                        b" * " | b"   " => None,
+                        [sign, a, b] => {
+                            let side = side_from_csa(*sign).ok_or("invalid board cell")?;
+                            let pk = parse_piece_kind(&[*a, *b]).ok_or("invalid piece kind")?;
+                            Some(Piece::new(side, pk))
+                        }
+                        _ => unreachable!(),
+                    };
+                }
+            }
+            [sign @ (b'+' | b'-')] => {
+                let side = side_from_csa(*sign).unwrap();
+                let pos = Position::new(side, self.board.clone(), self.hands.clone(), PLY_1);
+                self.start = Some(pos.clone());
+                self.pos = Some(pos);
+            }
+            _ => return Err("unknown line in position"),
+        }
+
+        Ok(())
+    }
+
+    /// 盤上と手駒にない残りの駒を全て `side` の手駒にする (`00AL`)。
+    ///
+    /// 盤上と手駒の駒が平手の駒の総数を超えていればエラーを返す。
+    fn put_rest_in_hand(&mut self, side: Side) -> Result<(), &'static str> {
+        const TOO_MANY: &str = "too many pieces for `00AL`";
+
+        for hpk in HandPieceKind::all() {
+            let on_board = Square::all()
+                .into_iter()
+                .filter(|&sq| {
+                    self.board[sq].is_some_and(|pc| pc.kind().unpromote() == PieceKind::from(hpk))
+                })
+                .count();
+            let used = [self.hands[SENTE][hpk], self.hands[GOTE][hpk]]
+                .into_iter()
+                .try_fold(on_board, |acc, n| acc.checked_add(usize::from(n)))
+                .ok_or(TOO_MANY)?;
+            let rest = usize::from(total_count(hpk))
+                .checked_sub(used)
+                .ok_or(TOO_MANY)?;
+            let n = &mut self.hands[side][hpk];
+            *n = n.checked_add(rest as u8).ok_or(TOO_MANY)?;
+        }
+
+        Ok(())
+    }
+
+    /// 読み終えて、開始局面と指し手からなる棋譜を返す。
+    pub(crate) fn finish(self) -> Result<Kifu, &'static str> {
+        let start = self.start.ok_or("side to move is missing")?;
+
+        Ok(Kifu::new(start, self.mvs))
+    }
+}
+
+/// 局面を CSA 形式(`P1`〜`P9`, `P+`, `P-`, 手番行)で書く。各行は改行で終わる。
+pub(crate) fn write_csa_position(s: &mut String, pos: &Position) {
+    for row in Row::all_private() {
+        write!(s, "P{}", row.to_num()).unwrap();
+        for col in Col::all_private() {
+            match pos.board()[Square::new(col, row)] {
+                Some(pc) => write!(s, "{}{}", side_to_csa(pc.side()), pc.kind().to_csa()).unwrap(),
+                None => s.push_str(" * "),
+            }
+        }
+        s.push('\n');
+    }
+
+    for side in [SENTE, GOTE] {
+        write!(s, "P{}", side_to_csa(side)).unwrap();
+        for hpk in HandPieceKind::all() {
+            for _ in 0..pos.hands()[side][hpk] {
+                write!(s, "00{}", PieceKind::from(hpk).to_csa()).unwrap();
+            }
+        }
+        s.push('\n');
+    }
+
+    s.push(side_to_csa(pos.side_to_move()));
+    s.push('\n');
+}
+
+/// CSA サーバプロトコルの対局条件 (`BEGIN Game_Summary`〜`END Game_Summary`)。
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CsaGameSummary {
+    /// `Protocol_Version`。
+    pub protocol_version: Option<String>,
+
+    /// `Game_ID`。
+    pub game_id: String,
+
+    /// `Name+`: 先手の名前。
+    pub sente_name: String,
+
+    /// `Name-`: 後手の名前。
+    pub gote_name: String,
+
+    /// `Your_Turn`: 受信者の手番。
+    pub your_turn: Side,
+
+    /// `Rematch_On_Draw`。
+    pub rematch_on_draw: bool,
+
+    /// `Max_Moves`。
+    pub max_moves: Option<u32>,
+
+    /// `Time_Unit`: 時間の単位。
+    pub time_unit: Duration,
+
+    /// `Total_Time`, `Byoyomi`, `Increment`。
+    pub time_control: TimeControl,
+
+    /// `Least_Time_Per_Move`: 1 手の最小消費時間。
+    pub least_time_per_move: Duration,
+
+    /// `BEGIN Position`〜`END Position`: 開始局面と、既に指された手順。
+    pub kifu: Kifu,
+}
+
+impl CsaGameSummary {
+    /// 既に指された手順を適用した現局面を返す。
+    ///
+    /// 手順中の指し手を局面に適用できなければエラーを返す。
+    pub fn position(&self) -> Result<Position, MoveError> {
+        let mut pos = self.kifu.position().clone();
+        for &mv in self.kifu.moves() {
+            pos.do_move(mv)?;
+        }
+        Ok(pos)
+    }
+
+    /// `BEGIN Game_Summary` から `END Game_Summary` までの文字列を返す。末尾に改行は付けない。
+    ///
+    /// 手順中の指し手を局面に適用できなければエラーを返す。
+    pub fn to_csa(&self) -> Result<String, MoveError> {
+        let units = |d: Duration| duration_to_units(d, self.time_unit);
+        let yes_no = |b: bool| if b { "YES" } else { "NO" };
+
+        let mut s = String::new();
+
+        s.push_str("BEGIN Game_Summary\n");
+        if let Some(version) = &self.protocol_version {
+            writeln!(s, "Protocol_Version:{version}").unwrap();
+        }
+        s.push_str("Protocol_Mode:Server\n");
+        s.push_str("Format:Shogi 1.0\n");
+        writeln!(s, "Game_ID:{}", self.game_id).unwrap();
+        writeln!(s, "Name+:{}", self.sente_name).unwrap();
+        writeln!(s, "Name-:{}", self.gote_name).unwrap();
+        writeln!(s, "Your_Turn:{}", side_to_csa(self.your_turn)).unwrap();
+        writeln!(s, "Rematch_On_Draw:{}", yes_no(self.rematch_on_draw)).unwrap();
+        writeln!(
+            s,
+            "To_Move:{}",
+            side_to_csa(self.kifu.position().side_to_move())
+        )
+        .unwrap();
+        if let Some(max_moves) = self.max_moves {
+            writeln!(s, "Max_Moves:{max_moves}").unwrap();
+        }
+
+        s.push_str("BEGIN Time\n");
+        writeln!(s, "Time_Unit:{}", fmt_time_unit(self.time_unit)).unwrap();
+        writeln!(s, "Total_Time:{}", units(self.time_control.main)).unwrap();
+        writeln!(s, "Byoyomi:{}", units(self.time_control.byoyomi)).unwrap();
+        if !self.time_control.increment.is_zero() {
+            writeln!(s, "Increment:{}", units(self.time_control.increment)).unwrap();
+        }
+        writeln!(s, "Least_Time_Per_Move:{}", units(self.least_time_per_move)).unwrap();
+        s.push_str("END Time\n");
+
+        s.push_str("BEGIN Position\n");
+        let mut pos = self.kifu.position().clone();
+        write_csa_position(&mut s, &pos);
+        for &mv in self.kifu.moves() {
+            writeln!(s, "{}", mv.to_csa(&pos)?).unwrap();
+            pos.do_move(mv)?;
+        }
+        s.push_str("END Position\n");
+
+        s.push_str("END Game_Summary");
+
+        Ok(s)
+    }
+}
+
+/// `Time_Unit` の値 (`1sec`, `1min`, `1msec` など) をパースする。
+fn parse_time_unit(s: &str) -> Option<Duration> {
+    let (n, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit())?);
+    let n: u64 = if n.is_empty() { 1 } else { n.parse().ok()? };
+
+    match unit {
+        "msec" => Some(Duration::from_millis(n)),
+        "sec" => Some(Duration::from_secs(n)),
+        "min" => Some(Duration::from_secs(n.checked_mul(60)?)),
+        _ => None,
+    }
+}
+
+fn fmt_time_unit(unit: Duration) -> String {
+    let ms = unit.as_millis();
+    if ms.is_multiple_of(60000) {
+        format!("{}min", ms / 60000)
+    } else if ms.is_multiple_of(1000) {
+        format!("{}sec", ms / 1000)
+    } else {
+        format!("{ms}msec")
+    }
+}
+
+/// 時間を単位 `unit` の個数に換算する(切り捨て)。
+pub(crate) fn duration_to_units(d: Duration, unit: Duration) -> u64 {
+    (d.as_millis() / unit.as_millis().max(1)) as u64
+}
+
+impl std::str::FromStr for CsaGameSummary {
+    type Err = CsaParseError;
+
+    /// `BEGIN Game_Summary` から `END Game_Summary` までをパースする。
+    ///
+    /// 未知の項目は無視する。`BEGIN Time+`/`BEGIN Time-` (先後別の持ち時間)は `BEGIN Time` と同様に扱う。
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        #[derive(PartialEq)]
+        enum Section {
+            Outside,
+            Summary,
+            Time,
+            Position,
+            Done,
+        }
+
+        let mut section = Section::Outside;
+        let mut protocol_version = None;
+        let mut game_id = None;
+        let mut sente_name = String::new();
+        let mut gote_name = String::new();
+        let mut your_turn = None;
+        let mut rematch_on_draw = false;
+        let mut max_moves = None;
+        let mut time_unit = Duration::from_secs(1);
+        // 各時間の値と、その行番号。
+        let mut times = [(0_u64, 0_usize); 4];
+        let mut reader = CsaPositionReader::new();
+        let mut kifu = None;
+
+        for (i, line) in s.lines().enumerate() {
+            let invalid = |description| CsaParseError::InvalidLine {
+                line: i + 1,
+                description,
+            };
+            let line = line.trim_end_matches('\r');
+
+            match section {
+                Section::Outside => {
+                    if line == "BEGIN Game_Summary" {
+                        section = Section::Summary;
+                    } else if !line.trim().is_empty() {
+                        return Err(invalid("`BEGIN Game_Summary` expected"));
+                    }
+                    continue;
+                }
+                Section::Done => {
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    return Err(invalid("trailing line after `END Game_Summary`"));
+                }
+                Section::Position => {
+                    if line == "END Position" {
+                        let mut done = CsaPositionReader::new();
+                        std::mem::swap(&mut reader, &mut done);
+                        kifu = Some(done.finish().map_err(invalid)?);
+                        section = Section::Summary;
+                    } else {
+                        reader.read_line(line).map_err(invalid)?;
+                    }
+                    continue;
+                }
+                Section::Summary | Section::Time => {}
+            }
+
+            match line {
+                "END Game_Summary" if section == Section::Summary => {
+                    section = Section::Done;
+                    continue;
+                }
+                "BEGIN Time" | "BEGIN Time+" | "BEGIN Time-" if section == Section::Summary => {
+                    section = Section::Time;
+                    continue;
+                }
+                "END Time" | "END Time+" | "END Time-" if section == Section::Time => {
+                    section = Section::Summary;
+                    continue;
+                }
+                "BEGIN Position" if section == Section::Summary => {
+                    section = Section::Position;
+                    continue;
+                }
+                "" => continue,
+                _ => {}
+            }
+
+            let (key, value) = line
+                .split_once(':')
+                .ok_or(invalid("`key:value` expected"))?;
+            let parse_u64 = || {
+                value
+                    .parse::<u64>()
+                    .map_err(|_| invalid("integer expected"))
+            };
+            match (&section, key) {
+                (Section::Summary, "Protocol_Version") => protocol_version = Some(value.to_owned()),
+                (Section::Summary, "Game_ID") => game_id = Some(value.to_owned()),
+                (Section::Summary, "Name+") => value.clone_into(&mut sente_name),
+                (Section::Summary, "Name-") => value.clone_into(&mut gote_name),
+                (Section::Summary, "Your_Turn") => {
+                    let side = match value {
+                        "+" => SENTE,
+                        "-" => GOTE,
+                        _ => return Err(invalid("`+` or `-` expected")),
+                    };
+                    your_turn = Some(side);
+                }
+                (Section::Summary, "Rematch_On_Draw") => rematch_on_draw = value == "YES",
+                (Section::Summary, "Max_Moves") => {
+                    max_moves =
+                        Some(u32::try_from(parse_u64()?).map_err(|_| invalid("too large"))?);
+                }
+                (Section::Time, "Time_Unit") => {
+                    time_unit = parse_time_unit(value).ok_or(invalid("invalid time unit"))?;
+                }
+                (Section::Time, "Total_Time") => times[0] = (parse_u64()?, i + 1),
+                (Section::Time, "Byoyomi") => times[1] = (parse_u64()?, i + 1),
+                (Section::Time, "Increment") => times[2] = (parse_u64()?, i + 1),
+                (Section::Time, "Least_Time_Per_Move") => times[3] = (parse_u64()?, i + 1),
+                _ => {}
+            }
+        }
+
+        if section != Section::Done {
+            return Err(CsaParseError::MissingField {
+                name: "END Game_Summary",
+            });
+        }
+
+        let units = |(n, line): (u64, usize)| {
+            u32::try_from(n)
+                .ok()
+                .and_then(|n| time_unit.checked_mul(n))
+                .ok_or(CsaParseError::InvalidLine {
+                    line,
+                    description: "time too large",
+                })
+        };
+
+        Ok(Self {
+            protocol_version,
+            game_id: game_id.ok_or(CsaParseError::MissingField { name: "Game_ID" })?,
+            sente_name,
+            gote_name,
+            your_turn: your_turn.ok_or(CsaParseError::MissingField { name: "Your_Turn" })?,
+            rematch_on_draw,
+            max_moves,
+            time_unit,
+            time_control: TimeControl {
+                main: units(times[0])?,
+                byoyomi: units(times[1])?,
+                increment: units(times[2])?,
+            },
+            least_time_per_move: units(times[3])?,
+            kifu: kifu.ok_or(CsaParseError::MissingField { name: "Position" })?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr as _;
+
+    use super::*;
+
+    #[test]
+    fn test_move_csa() {
+        let pos = Position::from_str(
+            "lnsgkgsnl/1r5b1/pppppp1pp/6p2/9/2P6/PP1PPPPPP/1B5R1/LNSGKGSNL b - 3",
+        )
+        .unwrap();
+
+        let cases = [
+            ("+2726FU", None),
+            ("+8822UM", Some("8h2b+")),
+            ("+8822KA", Some("8h2b")),
+        ];
+        for (csa, sfen) in cases {
+            let mv = Move::from_csa(csa, &pos).unwrap();
+            if let Some(sfen) = sfen {
+                assert_eq!(mv, Move::from_str(sfen).unwrap());
+            }
+            assert_eq!(mv.to_csa(&pos).unwrap(), csa);
+        }
+
+        let pos = Position::from_str("4k4/9/9/9/9/9/9/9/4K4 w B 1").unwrap();
+        assert_eq!(
+            Move::from_csa("-5958OU", &pos),
+            Err(CsaMoveParseError::InvalidMove(MoveError::NoOwnPieceAtSrc))
+        );
+        let pos = Position::from_str("4k4/9/9/9/9/9/9/9/4K4 b B 1").unwrap();
+        let mv = Move::from_csa("+0055KA", &pos).unwrap();
+        assert_eq!(mv, Move::from_str("B*5e").unwrap());
+        assert_eq!(mv.to_csa(&pos).unwrap(), "+0055KA");
+
+        assert_eq!(
+            Move::from_csa("-5152OU", &pos),
+            Err(CsaMoveParseError::SideMismatch)
+        );
+        assert_eq!(
+            Move::from_csa("+5958KI", &pos),
+            Err(CsaMoveParseError::PieceMismatch)
+        );
+        assert_eq!(
+            Move::from_csa("+0055HI", &pos),
+            Err(CsaMoveParseError::InvalidMove(MoveError::NotInHand))
+        );
+        assert!(matches!(
+            Move::from_csa("+5958O", &pos),
+            Err(CsaMoveParseError::InvalidNotation { .. })
+        ));
+    }
+
+    fn read_position(lines: &str) -> Result<Kifu, &'static str> {
+        let mut reader = CsaPositionReader::new();
+        for line in lines.lines() {
+            reader.read_line(line)?;
+        }
+        reader.finish()
+    }
+
+    #[test]
+    fn test_csa_position_read() {
+        let kifu = read_position("PI\n+\n+7776FU,T3\n-3334FU\n").unwrap();
+        assert_eq!(kifu.to_string(), "position startpos moves 7g7f 3c3d");
+
+        // 角落ち。
+        let kifu = read_position("PI22KA\n-\n").unwrap();
+        assert_eq!(
+            kifu.position().to_string(),
+            "sfen lnsgkgsnl/1r7/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1"
+        );
+
+        // 詰将棋(残り全部を後手の手駒に)。
+        let kifu = read_position(
+            "\
+P1 *  *  *  * -OU *  *  *  *
+P2
+P3 *  *  *  * +FU
+P+00KI
+P-00AL
++
+",
+        )
+        .unwrap();
+        assert_eq!(
+            kifu.position().to_string(),
+            "sfen 4k4/9/4P4/9/9/9/9/9/9 b G2r2b3g4s4n4l17p 1"
+        );
+
+        // 駒が平手の総数を超えていれば `00AL` はエラー。
+        let many_pawns = format!("P+{}\nP-00AL\n+\n", "00FU".repeat(300));
+        assert!(read_position(&many_pawns).is_err());
+        let many_pawns = format!("P+{}\nP-00AL\n+\n", "00FU".repeat(19));
+        assert!(read_position(&many_pawns).is_err());
+
+        assert!(read_position("P1 *  *  *  * -OU\n").is_err());
+        assert!(read_position("PX\n+\n").is_err());
+        assert!(read_position("PI\n+\n+7776KI\n").is_err());
+    }
+
+    #[test]
+    fn test_csa_position_write() {
+        let pos = Position::from_str("4k4/9/4P4/9/9/9/9/9/4K4 w G2Pb 1").unwrap();
+        let mut s = String::new();
+        write_csa_position(&mut s, &pos);
+        // 盤面の行は 9 マス分 (27 文字) を書くので行末に空白が付く。
+        assert!(s.starts_with("P1 *  *  *  * -OU *  *  *  * \n"));
+        assert_eq!(
+            s.lines().map(str::trim_end).collect::<Vec<_>>().join("\n") + "\n",
+            "\
+P1 *  *  *  * -OU *  *  *  *
+P2 *  *  *  *  *  *  *  *  *
+P3 *  *  *  * +FU *  *  *  *
+P4 *  *  *  *  *  *  *  *  *
+P5 *  *  *  *  *  *  *  *  *
+P6 *  *  *  *  *  *  *  *  *
+P7 *  *  *  *  *  *  *  *  *
+P8 *  *  *  *  *  *  *  *  *
+P9 *  *  *  * +OU *  *  *  *
+P+00FU00FU00KI
+P-00KA
+-
+"
+        );
+
+        let mut reader = CsaPositionReader::new();
+        for line in s.lines() {
+            reader.read_line(line).unwrap();
+        }
+        assert_eq!(reader.finish().unwrap().position(), &pos);
+    }
+
+    const SUMMARY: &str = "\
+BEGIN Game_Summary
+Protocol_Version:1.2
+Protocol_Mode:Server
+Format:Shogi 1.0
+Game_ID:20150505-CSA25-3-5-7
+Name+:TANUKI
+Name-:KITSUNE
+Your_Turn:-
+Rematch_On_Draw:NO
+To_Move:+
+Max_Moves:256
+BEGIN Time
+Time_Unit:1sec
+Total_Time:600
+Byoyomi:10
+Least_Time_Per_Move:1
+END Time
+BEGIN Position
+P1-KY-KE-GI-KI-OU-KI-GI-KE-KY
+P2 * -HI *  *  *  *  * -KA *
+P3-FU-FU-FU-FU-FU-FU-FU-FU-FU
+P4 *  *  *  *  *  *  *  *  *
+P5 *  *  *  *  *  *  *  *  *
+P6 *  *  *  *  *  *  *  *  *
+P7+FU+FU+FU+FU+FU+FU+FU+FU+FU
+P8 * +KA *  *  *  *  * +HI *
+P9+KY+KE+GI+KI+OU+KI+GI+KE+KY
+P+
+P-
++
++2726FU
+END Position
+END Game_Summary";
+
+    #[test]
+    fn test_csa_game_summary() {
+        let summary = CsaGameSummary::from_str(SUMMARY).unwrap();
+        assert_eq!(summary.protocol_version.as_deref(), Some("1.2"));
+        assert_eq!(summary.game_id, "20150505-CSA25-3-5-7");
+        assert_eq!(summary.sente_name, "TANUKI");
+        assert_eq!(summary.gote_name, "KITSUNE");
+        assert_eq!(summary.your_turn, GOTE);
+        assert!(!summary.rematch_on_draw);
+        assert_eq!(summary.max_moves, Some(256));
+        assert_eq!(
+            summary.time_control,
+            TimeControl {
+                main: Duration::from_secs(600),
+                byoyomi: Duration::from_secs(10),
+                increment: Duration::ZERO,
+            }
+        );
+        assert_eq!(summary.least_time_per_move, Duration::from_secs(1));
+        assert_eq!(summary.kifu.to_string(), "position startpos moves 2g2f");
+        assert_eq!(summary.position().unwrap().side_to_move(), GOTE);
+
+        // 盤面の行末の空白以外は元の文字列と一致する。
+        let s = summary.to_csa().unwrap();
+        assert_eq!(
+            s.lines().map(str::trim_end).collect::<Vec<_>>(),
+            SUMMARY.lines().collect::<Vec<_>>()
+        );
+        assert_eq!(CsaGameSummary::from_str(&s).unwrap(), summary);
+
+        // 手順を適用できない。
+        let mut bad = summary.clone();
+        bad.kifu = Kifu::from_str("position startpos moves 2g2f 2g2f").unwrap();
+        assert_eq!(bad.position(), Err(MoveError::NoOwnPieceAtSrc));
+        assert_eq!(bad.to_csa(), Err(MoveError::NoOwnPieceAtSrc));
+
+        assert_eq!(
+            CsaGameSummary::from_str("BEGIN Game_Summary\nGame_ID:x\nEND Game_Summary"),
+            Err(CsaParseError::MissingField { name: "Your_Turn" })
+        );
+        assert_eq!(
+            CsaGameSummary::from_str("BEGIN Game_Summary\nGame_ID:x\n"),
+            Err(CsaParseError::MissingField {
+                name: "END Game_Summary"
+            })
+        );
+        assert!(matches!(
+            CsaGameSummary::from_str("BEGIN Game_Summary\nfoo\nEND Game_Summary"),
+            Err(CsaParseError::InvalidLine { line: 2, .. })
+        ));
+
+        // 時間が `Duration` で表せなければエラー。
+        let huge = SUMMARY.replace("Time_Unit:1sec", "Time_Unit:18446744073709551615min");
+        assert!(matches!(
+            CsaGameSummary::from_str(&huge),
+            Err(CsaParseError::InvalidLine { line: 13, .. })
+        ));
+        let huge = SUMMARY.replace("Time_Unit:1sec", "Time_Unit:18446744073709551615sec");
+        assert!(matches!(
+            CsaGameSummary::from_str(&huge),
+            Err(CsaParseError::InvalidLine { line: 14, .. })
+        ));
+    }
+}
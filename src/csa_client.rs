@@ -0,0 +1,528 @@
+//! CSA サーバプロトコルのクライアント。
+
+use std::io::{BufRead as _, BufReader, Write as _};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use crate::csa::*;
+use crate::move_::*;
+use crate::position::*;
+use crate::side::*;
+
+/// CSA サーバとの通信エラー。
+#[non_exhaustive]
+#[derive(Debug)]
+pub enum CsaClientError {
+    /// 入出力エラー。
+    Io(std::io::Error),
+
+    /// 制限時間内に応答がなかった。
+    Timeout,
+
+    /// サーバとの接続が切れた。
+    Disconnected,
+
+    /// ログインを拒否された。
+    LoginFailed,
+
+    /// 対局条件への同意が拒否された(相手が `REJECT` した)。
+    Rejected,
+
+    /// 対局中でないのに対局中のみの操作をした。
+    NotInGame,
+
+    /// サーバからのメッセージが不正。
+    InvalidMessage {
+        line: String,
+        description: &'static str,
+    },
+
+    /// `Game_Summary` が不正。
+    InvalidSummary(CsaParseError),
+
+    /// サーバから受け取った指し手が不正。
+    InvalidMove {
+        line: String,
+        error: CsaMoveParseError,
+    },
+
+    /// 送ろうとした指し手を現局面に適用できない。
+    IllegalMove(MoveError),
+}
+
+impl std::fmt::Display for CsaClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "I/O error: {e}"),
+            Self::Timeout => f.write_str("server did not respond in time"),
+            Self::Disconnected => f.write_str("server disconnected"),
+            Self::LoginFailed => f.write_str("login failed"),
+            Self::Rejected => f.write_str("game was rejected"),
+            Self::NotInGame => f.write_str("not in game"),
+            Self::InvalidMessage { line, description } => {
+                write!(f, "invalid message {line:?}: {description}")
+            }
+            Self::InvalidSummary(e) => write!(f, "invalid Game_Summary: {e}"),
+            Self::InvalidMove { line, error } => write!(f, "invalid move {line:?}: {error}"),
+            Self::IllegalMove(e) => write!(f, "illegal move: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for CsaClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::InvalidSummary(e) => Some(e),
+            Self::InvalidMove { error, .. } => Some(error),
+            Self::IllegalMove(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for CsaClientError {
+    fn from(e: std::io::Error) -> Self {
+        match e.kind() {
+            std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut => Self::Timeout,
+            std::io::ErrorKind::BrokenPipe
+            | std::io::ErrorKind::ConnectionReset
+            | std::io::ErrorKind::UnexpectedEof => Self::Disconnected,
+            _ => Self::Io(e),
+        }
+    }
+}
+
+pub type CsaClientResult<T> = Result<T, CsaClientError>;
+
+/// 対局中にサーバから受け取るイベント。
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum CsaEvent {
+    /// 指し手。自分の指し手の確認も含む。`time` は消費時間。
+    Move {
+        side: Side,
+        mv: Move,
+        time: Option<Duration>,
+    },
+
+    /// 特殊な指し手 (`%TORYO` など) の確認。
+    Special {
+        special: CsaSpecialMove,
+        time: Option<Duration>,
+    },
+
+    /// 終局。`reason` は結果に先立って通知された終局理由。
+    GameEnd {
+        reason: Option<CsaEndReason>,
+        result: CsaGameResult,
+    },
+}
+
+#[derive(Debug)]
+struct CsaGame {
+    summary: CsaGameSummary,
+    pos: Position,
+}
+
+/// CSA サーバプロトコルのクライアント。
+///
+/// 対局中は現局面を追跡し、指し手を `Move` として送受信する。
+#[derive(Debug)]
+pub struct CsaClient {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    game: Option<CsaGame>,
+    reason: Option<CsaEndReason>,
+}
+
+impl CsaClient {
+    /// サーバに接続する。
+    pub fn connect(addr: impl ToSocketAddrs) -> CsaClientResult<Self> {
+        let stream = TcpStream::connect(addr)?;
+
+        Ok(Self::new(stream)?)
+    }
+
+    /// 接続済みのストリームから作る。
+    pub fn new(stream: TcpStream) -> std::io::Result<Self> {
+        let writer = stream.try_clone()?;
+
+        Ok(Self {
+            reader: BufReader::new(stream),
+            writer,
+            game: None,
+            reason: None,
+        })
+    }
+
+    /// 受信の制限時間を設定する。`None` なら無制限。
+    pub fn set_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        self.writer.set_read_timeout(timeout)
+    }
+
+    /// 1 行送る。
+    fn send_line(&mut self, line: &str) -> CsaClientResult<()> {
+        writeln!(self.writer, "{line}")?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// 空行(キープアライブ)を除いて 1 行受け取る。
+    fn recv_line(&mut self) -> CsaClientResult<String> {
+        loop {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Err(CsaClientError::Disconnected);
+            }
+            let line = line.trim_end_matches(['\r', '\n']);
+            if !line.is_empty() {
+                return Ok(line.to_owned());
+            }
+        }
+    }
+
+    /// `LOGIN` を送り、成否を待つ。
+    pub fn login(&mut self, name: &str, password: &str) -> CsaClientResult<()> {
+        self.send_line(&format!("LOGIN {name} {password}"))?;
+
+        let line = self.recv_line()?;
+        if line == format!("LOGIN:{name} OK") {
+            Ok(())
+        } else if line.starts_with("LOGIN:") {
+            Err(CsaClientError::LoginFailed)
+        } else {
+            Err(invalid_message(line, "login response expected"))
+        }
+    }
+
+    /// `LOGOUT` を送り、`LOGOUT:completed` を待つ。
+    pub fn logout(&mut self) -> CsaClientResult<()> {
+        self.send_line("LOGOUT")?;
+
+        loop {
+            if self.recv_line()? == "LOGOUT:completed" {
+                return Ok(());
+            }
+        }
+    }
+
+    /// `Game_Summary` を受け取る。
+    pub fn recv_game_summary(&mut self) -> CsaClientResult<CsaGameSummary> {
+        let line = self.recv_line()?;
+        if line != "BEGIN Game_Summary" {
+            return Err(invalid_message(line, "`BEGIN Game_Summary` expected"));
+        }
+
+        let mut text = line;
+        loop {
+            let line = self.recv_line()?;
+            text.push('\n');
+            text.push_str(&line);
+            if line == "END Game_Summary" {
+                break;
+            }
+        }
+
+        let summary: CsaGameSummary = text.parse().map_err(CsaClientError::InvalidSummary)?;
+        self.game = Some(CsaGame {
+            // 手順はパース時に検査済み。
+            pos: summary.position().unwrap(),
+            summary: summary.clone(),
+        });
+        self.reason = None;
+
+        Ok(summary)
+    }
+
+    fn game(&self) -> CsaClientResult<&CsaGame> {
+        self.game.as_ref().ok_or(CsaClientError::NotInGame)
+    }
+
+    /// 対局条件に同意し、対局開始 (`START`) を待つ。
+    ///
+    /// 相手が拒否した場合は `CsaClientError::Rejected` を返す。
+    pub fn agree(&mut self) -> CsaClientResult<()> {
+        let game_id = self.game()?.summary.game_id.clone();
+        self.send_line(&format!("AGREE {game_id}"))?;
+
+        let line = self.recv_line()?;
+        if line == format!("START:{game_id}") {
+            Ok(())
+        } else if line.starts_with("REJECT:") {
+            self.game = None;
+            Err(CsaClientError::Rejected)
+        } else {
+            Err(invalid_message(line, "`START` or `REJECT` expected"))
+        }
+    }
+
+    /// 対局条件を拒否する。
+    pub fn reject(&mut self) -> CsaClientResult<()> {
+        let game_id = self.game()?.summary.game_id.clone();
+        self.send_line(&format!("REJECT {game_id}"))?;
+        self.game = None;
+
+        let line = self.recv_line()?;
+        if line.starts_with("REJECT:") {
+            Ok(())
+        } else {
+            Err(invalid_message(line, "`REJECT` expected"))
+        }
+    }
+
+    /// 対局中の現局面を返す。
+    pub fn position(&self) -> Option<&Position> {
+        self.game.as_ref().map(|game| &game.pos)
+    }
+
+    /// 対局中の対局条件を返す。
+    pub fn game_summary(&self) -> Option<&CsaGameSummary> {
+        self.game.as_ref().map(|game| &game.summary)
+    }
+
+    /// 指し手を送る。現局面はサーバからの確認 (`recv_event`) を受け取った時点で更新される。
+    pub fn send_move(&mut self, mv: Move) -> CsaClientResult<()> {
+        let csa = mv
+            .to_csa(&self.game()?.pos)
+            .map_err(CsaClientError::IllegalMove)?;
+
+        self.send_line(&csa)
+    }
+
+    /// 特殊な指し手 (`%TORYO`, `%KACHI`) を送る。
+    pub fn send_special(&mut self, special: CsaSpecialMove) -> CsaClientResult<()> {
+        self.game()?;
+
+        self.send_line(special.to_csa())
+    }
+
+    /// 対局中のイベントを 1 つ受け取る。
+    ///
+    /// 終局理由 (`#RESIGN` など) は結果 (`#WIN` など) と合わせて `CsaEvent::GameEnd` として返す。
+    /// 終局後は対局中でなくなる。
+    pub fn recv_event(&mut self) -> CsaClientResult<CsaEvent> {
+        let time_unit = self.game()?.summary.time_unit;
+
+        loop {
+            let line = self.recv_line()?;
+
+            if line.starts_with('#') {
+                if let Some(reason) = CsaEndReason::from_csa(&line) {
+                    self.reason = Some(reason);
+                    continue;
+                }
+                if let Some(result) = CsaGameResult::from_csa(&line) {
+                    self.game = None;
+                    return Ok(CsaEvent::GameEnd {
+                        reason: self.reason.take(),
+                        result,
+                    });
+                }
+                return Err(invalid_message(line, "unknown game end message"));
+            }
+
+            let (body, time) = split_time(&line).map_err(|e| invalid_message(line.clone(), e))?;
+            let time = time
+                .map(|n| {
+                    u32::try_from(n)
+                        .ok()
+                        .and_then(|n| time_unit.checked_mul(n))
+                        .ok_or_else(|| invalid_message(line.clone(), "time too large"))
+                })
+                .transpose()?;
+
+            if let Some(special) = CsaSpecialMove::from_csa(body) {
+                return Ok(CsaEvent::Special { special, time });
+            }
+
+            let game = self.game.as_mut().unwrap();
+            let side = game.pos.side_to_move();
+            let mv =
+                Move::from_csa(body, &game.pos).map_err(|error| CsaClientError::InvalidMove {
+                    line: line.clone(),
+                    error,
+                })?;
+            game.pos.do_move(mv).unwrap();
+
+            return Ok(CsaEvent::Move { side, mv, time });
+        }
+    }
+}
+
+fn invalid_message(line: String, description: &'static str) -> CsaClientError {
+    CsaClientError::InvalidMessage { line, description }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufRead as _;
+    use std::net::TcpListener;
+    use std::str::FromStr as _;
+
+    use crate::kifu::*;
+    use crate::referee::*;
+
+    use super::*;
+
+    /// 台本どおりに応答するテスト用のサーバ。
+    /// 各要素は (期待する受信行, 送信する行) で、受信行が `None` なら受信を待たずに送信する。
+    fn spawn_server(script: Vec<(Option<&'static str>, String)>) -> std::net::SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            for (expected, send) in script {
+                if let Some(expected) = expected {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    assert_eq!(line.trim_end(), expected);
+                }
+                writeln!(writer, "{send}").unwrap();
+            }
+        });
+
+        addr
+    }
+
+    fn summary() -> CsaGameSummary {
+        CsaGameSummary {
+            protocol_version: Some("1.2".to_owned()),
+            game_id: "test-game".to_owned(),
+            sente_name: "alice".to_owned(),
+            gote_name: "bob".to_owned(),
+            your_turn: SENTE,
+            rematch_on_draw: false,
+            max_moves: Some(256),
+            time_unit: Duration::from_secs(1),
+            time_control: TimeControl {
+                main: Duration::from_secs(600),
+                byoyomi: Duration::from_secs(10),
+                increment: Duration::ZERO,
+            },
+            least_time_per_move: Duration::ZERO,
+            kifu: Kifu::startpos(),
+        }
+    }
+
+    #[test]
+    fn test_csa_client() {
+        let addr = spawn_server(vec![
+            (Some("LOGIN alice pass"), "LOGIN:alice OK".to_owned()),
+            (None, summary().to_csa().unwrap()),
+            (Some("AGREE test-game"), "START:test-game".to_owned()),
+            (Some("+7776FU"), "+7776FU,T3".to_owned()),
+            (None, String::new()),
+            (None, "-3334FU,T12".to_owned()),
+            (Some("%TORYO"), "%TORYO,T1".to_owned()),
+            (None, "#RESIGN".to_owned()),
+            (None, "#LOSE".to_owned()),
+            (Some("LOGOUT"), "LOGOUT:completed".to_owned()),
+        ]);
+
+        let mut client = CsaClient::connect(addr).unwrap();
+        client.set_timeout(Some(Duration::from_secs(10))).unwrap();
+        client.login("alice", "pass").unwrap();
+
+        let summary = client.recv_game_summary().unwrap();
+        assert_eq!(summary, self::summary());
+        client.agree().unwrap();
+
+        client.send_move(Move::from_str("7g7f").unwrap()).unwrap();
+        assert_eq!(
+            client.recv_event().unwrap(),
+            CsaEvent::Move {
+                side: SENTE,
+                mv: Move::from_str("7g7f").unwrap(),
+                time: Some(Duration::from_secs(3)),
+            }
+        );
+        assert_eq!(
+            client.recv_event().unwrap(),
+            CsaEvent::Move {
+                side: GOTE,
+                mv: Move::from_str("3c3d").unwrap(),
+                time: Some(Duration::from_secs(12)),
+            }
+        );
+        assert_eq!(
+            client.position().unwrap(),
+            &Position::from_str(
+                "lnsgkgsnl/1r5b1/pppppp1pp/6p2/9/2P6/PP1PPPPPP/1B5R1/LNSGKGSNL b - 3"
+            )
+            .unwrap()
+        );
+        assert!(matches!(
+            client.send_move(Move::from_str("7g7f").unwrap()),
+            Err(CsaClientError::IllegalMove(_))
+        ));
+
+        client.send_special(CsaSpecialMove::Resign).unwrap();
+        assert_eq!(
+            client.recv_event().unwrap(),
+            CsaEvent::Special {
+                special: CsaSpecialMove::Resign,
+                time: Some(Duration::from_secs(1)),
+            }
+        );
+        assert_eq!(
+            client.recv_event().unwrap(),
+            CsaEvent::GameEnd {
+                reason: Some(CsaEndReason::Resign),
+                result: CsaGameResult::Lose,
+            }
+        );
+        assert!(client.position().is_none());
+
+        client.logout().unwrap();
+    }
+
+    #[test]
+    fn test_csa_client_error() {
+        let addr = spawn_server(vec![
+            (Some("LOGIN alice wrong"), "LOGIN:incorrect".to_owned()),
+            (Some("LOGIN alice pass"), "LOGIN:alice OK".to_owned()),
+            (None, summary().to_csa().unwrap()),
+            (
+                Some("AGREE test-game"),
+                "REJECT:test-game by bob".to_owned(),
+            ),
+        ]);
+
+        let mut client = CsaClient::connect(addr).unwrap();
+        assert!(matches!(
+            client.login("alice", "wrong"),
+            Err(CsaClientError::LoginFailed)
+        ));
+        client.login("alice", "pass").unwrap();
+        client.recv_game_summary().unwrap();
+        assert!(matches!(client.agree(), Err(CsaClientError::Rejected)));
+        assert!(matches!(
+            client.recv_event(),
+            Err(CsaClientError::NotInGame)
+        ));
+
+        // サーバが切断した。
+        assert!(matches!(
+            client.recv_line(),
+            Err(CsaClientError::Disconnected)
+        ));
+        // 消費時間が `Duration` で表せない。
+        let addr = spawn_server(vec![
+            (Some("LOGIN alice pass"), "LOGIN:alice OK".to_owned()),
+            (None, summary().to_csa().unwrap()),
+            (Some("AGREE test-game"), "START:test-game".to_owned()),
+            (None, "+7776FU,T99999999999".to_owned()),
+        ]);
+        let mut client = CsaClient::connect(addr).unwrap();
+        client.login("alice", "pass").unwrap();
+        client.recv_game_summary().unwrap();
+        client.agree().unwrap();
+        assert!(matches!(
+            client.recv_event(),
+            Err(CsaClientError::InvalidMessage { .. })
+        ));
+    }
+}
@@ -0,0 +1,726 @@
+//! 組み込み用の小さな CSA 対局サーバ。
+
+use std::io::{BufRead as _, BufReader, Write as _};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+use crate::csa::*;
+use crate::kifu::*;
+use crate::move_::*;
+use crate::position::*;
+use crate::record::*;
+use crate::referee::*;
+use crate::side::*;
+
+/// 対局サーバの設定。
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct CsaServerConfig {
+    /// 持ち時間。
+    pub time_control: TimeControl,
+
+    /// `Time_Unit`: 時間の単位。消費時間はこの単位で切り捨てて計上する。
+    pub time_unit: Duration,
+
+    /// 最大手数。棋譜の手数(開始棋譜の手順を含む)がこれに達したら打ち切りとする。
+    pub max_moves: u32,
+
+    /// 時間切れの判定で持ち時間に加える猶予(通信遅延などのため)。
+    pub time_margin: Duration,
+
+    /// ログイン、対局条件への同意などの応答を待つ時間。
+    pub response_timeout: Duration,
+
+    /// ログインのパスワード。`None` なら任意のパスワードを受け付ける。
+    pub password: Option<String>,
+}
+
+impl Default for CsaServerConfig {
+    /// 持ち時間 10 分、秒読み 10 秒、時間単位 1 秒、最大手数 256、猶予 1 秒、応答待ち 60 秒。
+    fn default() -> Self {
+        Self {
+            time_control: TimeControl {
+                main: Duration::from_secs(600),
+                byoyomi: Duration::from_secs(10),
+                increment: Duration::ZERO,
+            },
+            time_unit: Duration::from_secs(1),
+            max_moves: 256,
+            time_margin: Duration::from_secs(1),
+            response_timeout: Duration::from_secs(60),
+            password: None,
+        }
+    }
+}
+
+/// 対局サーバのエラー。
+#[non_exhaustive]
+#[derive(Debug)]
+pub enum CsaServerError {
+    /// 入出力エラー。
+    Io(std::io::Error),
+
+    /// 対局条件が拒否された。`side` は拒否した(または応答しなかった)側。
+    Rejected { side: Side },
+
+    /// 対局中に接続が切れた。`side` は切断した側。
+    Disconnected { side: Side },
+
+    /// 開始棋譜の手順を適用できない。
+    InvalidStart(MoveError),
+}
+
+impl std::fmt::Display for CsaServerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "I/O error: {e}"),
+            Self::Rejected { side } => write!(f, "game was rejected by {side:?}"),
+            Self::Disconnected { side } => write!(f, "{side:?} disconnected"),
+            Self::InvalidStart(e) => write!(f, "invalid start kifu: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for CsaServerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::InvalidStart(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for CsaServerError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+pub type CsaServerResult<T> = Result<T, CsaServerError>;
+
+/// ログイン済みのクライアント。
+#[derive(Debug)]
+pub struct CsaPlayer {
+    name: String,
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    timeout: Duration,
+}
+
+impl CsaPlayer {
+    /// ログイン名を返す。
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// クライアントの `LOGOUT` を待ち、`LOGOUT:completed` を返して接続を閉じる。
+    pub fn logout(mut self) -> std::io::Result<()> {
+        let deadline = Instant::now() + self.timeout;
+        while self.recv_line(Some(deadline))? != "LOGOUT" {}
+
+        self.send_line("LOGOUT:completed")
+    }
+
+    fn send_line(&mut self, line: &str) -> std::io::Result<()> {
+        writeln!(self.writer, "{line}")?;
+        self.writer.flush()
+    }
+
+    /// 空行(キープアライブ)を除いて 1 行受け取る。`deadline` を過ぎたら `TimedOut` エラーを返す。
+    fn recv_line(&mut self, deadline: Option<Instant>) -> std::io::Result<String> {
+        recv_line(&mut self.reader, deadline)
+    }
+}
+
+fn recv_line(
+    reader: &mut BufReader<TcpStream>,
+    deadline: Option<Instant>,
+) -> std::io::Result<String> {
+    loop {
+        let timeout = match deadline {
+            Some(deadline) => {
+                let timeout = deadline.saturating_duration_since(Instant::now());
+                if timeout.is_zero() {
+                    return Err(std::io::ErrorKind::TimedOut.into());
+                }
+                Some(timeout)
+            }
+            None => None,
+        };
+        reader.get_ref().set_read_timeout(timeout)?;
+
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        let line = line.trim_end_matches(['\r', '\n']);
+        if !line.is_empty() {
+            return Ok(line.to_owned());
+        }
+    }
+}
+
+fn is_timeout(e: &std::io::Error) -> bool {
+    matches!(
+        e.kind(),
+        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+    )
+}
+
+/// 終局した 1 局。
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CsaServerGame {
+    game_id: String,
+    record: Record,
+    winner: Option<Side>,
+    reason: GameEndReason,
+}
+
+impl CsaServerGame {
+    /// `Game_ID` を返す。
+    pub fn game_id(&self) -> &str {
+        &self.game_id
+    }
+
+    /// 棋譜を返す。
+    ///
+    /// ヘッダ "先手", "後手" にログイン名を記録し、末尾には終局を表す特殊な指し手を置く。
+    /// 手順のみが必要なら `Record::kifu` で `Kifu` に変換できる。
+    pub const fn record(&self) -> &Record {
+        &self.record
+    }
+
+    /// 勝った側を返す。引き分け、打ち切りなら `None` を返す。
+    pub const fn winner(&self) -> Option<Side> {
+        self.winner
+    }
+
+    /// 終局理由を返す。
+    pub const fn reason(&self) -> GameEndReason {
+        self.reason
+    }
+}
+
+/// 組み込み用の小さな CSA 対局サーバ。
+///
+/// ログインしたクライアントを 2 人ずつ組み合わせて対局させ、指し手の合法性、持ち時間、終局を判定する。
+/// 詰みは判定しない(詰まされた側が投了するか、時間切れになるまで対局が続く)。
+#[derive(Debug)]
+pub struct CsaServer {
+    listener: TcpListener,
+    config: CsaServerConfig,
+    game_count: u32,
+}
+
+impl CsaServer {
+    /// アドレス `addr` で待ち受ける。
+    pub fn bind(addr: impl ToSocketAddrs, config: CsaServerConfig) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+
+        Ok(Self {
+            listener,
+            config,
+            game_count: 0,
+        })
+    }
+
+    /// 待ち受けているアドレスを返す。
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// 設定を返す。
+    pub const fn config(&self) -> &CsaServerConfig {
+        &self.config
+    }
+
+    /// 接続を受け付け、ログインに成功したクライアントを返す。
+    ///
+    /// ログインに失敗した接続は `LOGIN:incorrect` を返して閉じ、次の接続を待つ。
+    pub fn accept_player(&mut self) -> CsaServerResult<CsaPlayer> {
+        loop {
+            let (stream, _) = self.listener.accept()?;
+            // 個々の接続の失敗はサーバのエラーとはしない。
+            if let Ok(Some(player)) = self.login(stream) {
+                return Ok(player);
+            }
+        }
+    }
+
+    fn login(&self, stream: TcpStream) -> std::io::Result<Option<CsaPlayer>> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = stream;
+
+        let deadline = Instant::now() + self.config.response_timeout;
+        let line = recv_line(&mut reader, Some(deadline))?;
+
+        let mut words = line.split_ascii_whitespace();
+        let login = match (words.next(), words.next(), words.next(), words.next()) {
+            (Some("LOGIN"), Some(name), Some(password), None)
+                if self
+                    .config
+                    .password
+                    .as_ref()
+                    .is_none_or(|expected| expected == password) =>
+            {
+                Some(name.to_owned())
+            }
+            _ => None,
+        };
+
+        let Some(name) = login else {
+            writeln!(writer, "LOGIN:incorrect")?;
+            return Ok(None);
+        };
+
+        let mut player = CsaPlayer {
+            name,
+            reader,
+            writer,
+            timeout: self.config.response_timeout,
+        };
+        player.send_line(&format!("LOGIN:{} OK", player.name))?;
+
+        Ok(Some(player))
+    }
+
+    /// ログインした順に 2 人のクライアントを受け付ける。先にログインした方を先手とする。
+    pub fn accept_match(&mut self) -> CsaServerResult<(CsaPlayer, CsaPlayer)> {
+        let sente = self.accept_player()?;
+        let gote = self.accept_player()?;
+
+        Ok((sente, gote))
+    }
+
+    /// 棋譜 `start` の末尾の局面から 1 局対局させる。
+    ///
+    /// 棋譜の手順は開始局面から記録される。
+    /// 対局後もクライアントはログインしたままなので、続けて次の対局に用いることができる。
+    pub fn play_game(
+        &mut self,
+        sente: &mut CsaPlayer,
+        gote: &mut CsaPlayer,
+        start: &Kifu,
+    ) -> CsaServerResult<CsaServerGame> {
+        let tc = self.config.time_control;
+        let unit = self.config.time_unit;
+
+        let mut record = Record::new(start.position().clone());
+        for (key, player) in [("先手", &*sente), ("後手", &*gote)] {
+            record
+                .headers_mut()
+                .push((key.to_owned(), player.name.clone()));
+        }
+
+        let mut pos = start.position().clone();
+        for &mv in start.moves() {
+            pos.do_move(mv).map_err(CsaServerError::InvalidStart)?;
+            record
+                .entries_mut()
+                .push(RecordEntry::new(RecordMove::Move(mv)));
+        }
+        let mut n_moves = start.moves().len();
+
+        self.game_count += 1;
+        let game_id = format!("{}-{}-{}", sente.name, gote.name, self.game_count);
+
+        let mut players = ArraySide::new([sente, gote]);
+        self.agree(&mut players, &game_id, start)?;
+
+        let mut history = vec![(pos.without_ply(), pos.is_in_check())];
+        let mut remaining = ArraySide::from_elem(tc.main);
+        let mut total = ArraySide::from_elem(Duration::ZERO);
+        let byoyomi = if tc.increment.is_zero() {
+            tc.byoyomi
+        } else {
+            Duration::ZERO
+        };
+
+        let (winner, reason, time) = loop {
+            let us = pos.side_to_move();
+
+            if n_moves >= self.config.max_moves as usize {
+                break (None, GameEndReason::MaxMoves, None);
+            }
+
+            let start_time = Instant::now();
+            let deadline = start_time + remaining[us] + byoyomi + unit + self.config.time_margin;
+            let line = match players[us].recv_line(Some(deadline)) {
+                Ok(line) => line,
+                Err(e) if is_timeout(&e) => break (Some(us.flip()), GameEndReason::TimeUp, None),
+                Err(_) => {
+                    let _ = players[us.flip()].send_line(CsaGameResult::Interrupted.to_csa());
+                    return Err(CsaServerError::Disconnected { side: us });
+                }
+            };
+
+            let consumed = unit * duration_to_units(start_time.elapsed(), unit) as u32;
+            total[us] += consumed;
+            let time = Some(MoveTime::new(consumed, total[us]));
+            if consumed > remaining[us] + byoyomi + self.config.time_margin {
+                break (Some(us.flip()), GameEndReason::TimeUp, time);
+            }
+            remaining[us] = remaining[us].saturating_sub(consumed) + tc.increment;
+
+            // 指し手の後ろのコメント (`,'...`) は無視する。
+            let body = line.split(',').next().unwrap();
+            let echo = format!("{body},T{}", duration_to_units(consumed, unit));
+
+            match CsaSpecialMove::from_csa(body) {
+                Some(CsaSpecialMove::Resign) => {
+                    self.broadcast(&mut players, &echo)?;
+                    break (Some(us.flip()), GameEndReason::Resign, time);
+                }
+                Some(CsaSpecialMove::DeclareWin) if pos.can_declare_win() => {
+                    self.broadcast(&mut players, &echo)?;
+                    break (Some(us), GameEndReason::DeclareWin, time);
+                }
+                Some(CsaSpecialMove::DeclareWin) => {
+                    break (Some(us.flip()), GameEndReason::IllegalDeclareWin, time);
+                }
+                None => {}
+            }
+
+            let mv = match Move::from_csa(body, &pos) {
+                Ok(mv) if pos.is_legal_move(mv) => mv,
+                _ => break (Some(us.flip()), GameEndReason::IllegalMove, time),
+            };
+
+            self.broadcast(&mut players, &echo)?;
+            pos.do_move(mv).unwrap();
+            n_moves += 1;
+            let mut entry = RecordEntry::new(RecordMove::Move(mv));
+            entry.set_time(time);
+            record.entries_mut().push(entry);
+
+            history.push((pos.without_ply(), pos.is_in_check()));
+            if let Some((winner, reason)) = judge_repetition(&history) {
+                break (winner, reason, None);
+            }
+        };
+
+        self.broadcast(&mut players, end_reason_to_csa(reason).to_csa())?;
+        for side in [SENTE, GOTE] {
+            let result = match winner {
+                None if reason == GameEndReason::MaxMoves => CsaGameResult::Censored,
+                None => CsaGameResult::Draw,
+                Some(winner) if winner == side => CsaGameResult::Win,
+                Some(_) => CsaGameResult::Lose,
+            };
+            // 勝敗は既に決まっているので、通知の失敗は無視する。
+            let _ = players[side].send_line(result.to_csa());
+        }
+
+        let loser_to_move = winner.is_some_and(|winner| winner != pos.side_to_move());
+        let mut entry = RecordEntry::new(RecordMove::Special(reason.to_special(loser_to_move)));
+        entry.set_time(time);
+        record.entries_mut().push(entry);
+
+        Ok(CsaServerGame {
+            game_id,
+            record,
+            winner,
+            reason,
+        })
+    }
+
+    /// 対局条件を送り、両者の同意を待つ。
+    ///
+    /// 一方が拒否するか、応答がなければ両者に `REJECT` を送る。
+    fn agree(
+        &self,
+        players: &mut ArraySide<&mut CsaPlayer>,
+        game_id: &str,
+        start: &Kifu,
+    ) -> CsaServerResult<()> {
+        for side in [SENTE, GOTE] {
+            let summary = CsaGameSummary {
+                protocol_version: Some("1.2".to_owned()),
+                game_id: game_id.to_owned(),
+                sente_name: players[SENTE].name.clone(),
+                gote_name: players[GOTE].name.clone(),
+                your_turn: side,
+                rematch_on_draw: false,
+                max_moves: Some(self.config.max_moves),
+                time_unit: self.config.time_unit,
+                time_control: self.config.time_control,
+                least_time_per_move: Duration::ZERO,
+                kifu: start.clone(),
+            };
+            let csa = summary.to_csa().map_err(CsaServerError::InvalidStart)?;
+            players[side]
+                .send_line(&csa)
+                .map_err(|_| CsaServerError::Disconnected { side })?;
+        }
+
+        let deadline = Instant::now() + self.config.response_timeout;
+        for side in [SENTE, GOTE] {
+            let agreed = match players[side].recv_line(Some(deadline)) {
+                Ok(line) => line == format!("AGREE {game_id}"),
+                Err(e) if is_timeout(&e) => false,
+                Err(_) => return Err(CsaServerError::Disconnected { side }),
+            };
+            if !agreed {
+                let name = players[side].name.clone();
+                for side in [SENTE, GOTE] {
+                    let _ = players[side].send_line(&format!("REJECT:{game_id} by {name}"));
+                }
+                return Err(CsaServerError::Rejected { side });
+            }
+        }
+
+        self.broadcast(players, &format!("START:{game_id}"))
+    }
+
+    /// 両者に 1 行送る。
+    fn broadcast(
+        &self,
+        players: &mut ArraySide<&mut CsaPlayer>,
+        line: &str,
+    ) -> CsaServerResult<()> {
+        for side in [SENTE, GOTE] {
+            players[side]
+                .send_line(line)
+                .map_err(|_| CsaServerError::Disconnected { side })?;
+        }
+
+        Ok(())
+    }
+}
+
+/// 終局理由を CSA サーバプロトコルの終局理由に変換する。
+///
+/// サーバは詰みを判定しないので、`Checkmate` は投了扱いとする。
+const fn end_reason_to_csa(reason: GameEndReason) -> CsaEndReason {
+    match reason {
+        GameEndReason::Checkmate | GameEndReason::Resign => CsaEndReason::Resign,
+        GameEndReason::IllegalMove | GameEndReason::IllegalDeclareWin => CsaEndReason::IllegalMove,
+        GameEndReason::Repetition => CsaEndReason::Sennichite,
+        GameEndReason::PerpetualCheck => CsaEndReason::OuteSennichite,
+        GameEndReason::DeclareWin => CsaEndReason::Jishogi,
+        GameEndReason::MaxMoves => CsaEndReason::MaxMoves,
+        GameEndReason::TimeUp => CsaEndReason::TimeUp,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr as _;
+    use std::thread::JoinHandle;
+
+    use crate::csa_client::*;
+
+    use super::*;
+
+    /// 指定した手を順に指すクライアント。"resign" は投了。手が尽きたら終局まで待つ。
+    /// "sleep <ms>" は次の手を指す前に指定したミリ秒だけ待つ。
+    fn spawn_client(
+        addr: SocketAddr,
+        name: &'static str,
+        mvs: &'static [&'static str],
+    ) -> JoinHandle<(CsaGameSummary, Vec<CsaEvent>)> {
+        let mut client = CsaClient::connect(addr).unwrap();
+        client.set_timeout(Some(Duration::from_secs(10))).unwrap();
+        client.login(name, "pass").unwrap();
+
+        std::thread::spawn(move || {
+            let summary = client.recv_game_summary().unwrap();
+            client.agree().unwrap();
+
+            let mut mvs = mvs.iter();
+            let mut events = vec![];
+            let mut waiting = false;
+            loop {
+                let our_turn = client.position().unwrap().side_to_move() == summary.your_turn;
+                if our_turn && !waiting {
+                    let mut next = mvs.next();
+                    while let Some(ms) = next.and_then(|mv| mv.strip_prefix("sleep ")) {
+                        std::thread::sleep(Duration::from_millis(ms.parse().unwrap()));
+                        next = mvs.next();
+                    }
+                    match next {
+                        Some(&"resign") => client.send_special(CsaSpecialMove::Resign).unwrap(),
+                        Some(mv) => client.send_move(Move::from_str(mv).unwrap()).unwrap(),
+                        None => {}
+                    }
+                    waiting = true;
+                }
+
+                let event = client.recv_event().unwrap();
+                if matches!(event, CsaEvent::Move { side, .. } if side == summary.your_turn) {
+                    waiting = false;
+                }
+                let end = matches!(event, CsaEvent::GameEnd { .. });
+                events.push(event);
+                if end {
+                    break;
+                }
+            }
+
+            client.logout().unwrap();
+            (summary, events)
+        })
+    }
+
+    fn play(
+        config: CsaServerConfig,
+        sente: &'static [&'static str],
+        gote: &'static [&'static str],
+    ) -> (CsaServerGame, Vec<CsaEvent>, Vec<CsaEvent>) {
+        let mut server = CsaServer::bind("127.0.0.1:0", config).unwrap();
+        let addr = server.local_addr().unwrap();
+
+        let server = std::thread::spawn(move || {
+            let (mut sente, mut gote) = server.accept_match().unwrap();
+            let game = server
+                .play_game(&mut sente, &mut gote, &Kifu::startpos())
+                .unwrap();
+            sente.logout().unwrap();
+            gote.logout().unwrap();
+            game
+        });
+
+        let sente = spawn_client(addr, "alice", sente);
+        let gote = spawn_client(addr, "bob", gote);
+
+        let game = server.join().unwrap();
+        let (summary, sente_events) = sente.join().unwrap();
+        assert_eq!(summary.sente_name, "alice");
+        assert_eq!(summary.gote_name, "bob");
+        assert_eq!(summary.game_id, game.game_id());
+        let (_, gote_events) = gote.join().unwrap();
+
+        (game, sente_events, gote_events)
+    }
+
+    fn game_end(events: &[CsaEvent]) -> &CsaEvent {
+        events.last().unwrap()
+    }
+
+    #[test]
+    fn test_csa_server_resign() {
+        let (game, sente, gote) = play(CsaServerConfig::default(), &["7g7f", "resign"], &["3c3d"]);
+
+        assert_eq!(game.winner(), Some(GOTE));
+        assert_eq!(game.reason(), GameEndReason::Resign);
+        assert_eq!(game.record().special(), Some(SpecialMove::Resign));
+        assert_eq!(game.record().header("先手"), Some("alice"));
+        assert_eq!(game.record().header("後手"), Some("bob"));
+        assert_eq!(
+            game.record().kifu(),
+            Kifu::from_str("startpos moves 7g7f 3c3d").unwrap()
+        );
+
+        assert_eq!(
+            sente[0],
+            CsaEvent::Move {
+                side: SENTE,
+                mv: Move::from_str("7g7f").unwrap(),
+                time: Some(Duration::ZERO),
+            }
+        );
+        assert_eq!(
+            game_end(&sente),
+            &CsaEvent::GameEnd {
+                reason: Some(CsaEndReason::Resign),
+                result: CsaGameResult::Lose,
+            }
+        );
+        assert_eq!(
+            game_end(&gote),
+            &CsaEvent::GameEnd {
+                reason: Some(CsaEndReason::Resign),
+                result: CsaGameResult::Win,
+            }
+        );
+        assert_eq!(sente.len(), gote.len());
+    }
+
+    #[test]
+    fn test_csa_server_illegal_move() {
+        // 歩が 2 マス進む手。
+        let (game, sente, _) = play(CsaServerConfig::default(), &["7g7f"], &["3c3e"]);
+
+        assert_eq!(game.winner(), Some(SENTE));
+        assert_eq!(game.reason(), GameEndReason::IllegalMove);
+        assert_eq!(game.record().kifu().moves().len(), 1);
+        assert_eq!(
+            game_end(&sente),
+            &CsaEvent::GameEnd {
+                reason: Some(CsaEndReason::IllegalMove),
+                result: CsaGameResult::Win,
+            }
+        );
+    }
+
+    #[test]
+    fn test_csa_server_time_up() {
+        let config = CsaServerConfig {
+            time_control: TimeControl {
+                main: Duration::ZERO,
+                byoyomi: Duration::from_millis(200),
+                increment: Duration::ZERO,
+            },
+            time_unit: Duration::from_millis(100),
+            time_margin: Duration::from_millis(100),
+            ..Default::default()
+        };
+        let (game, _, gote) = play(config, &["7g7f"], &[]);
+
+        assert_eq!(game.winner(), Some(SENTE));
+        assert_eq!(game.reason(), GameEndReason::TimeUp);
+        assert_eq!(game.record().special(), Some(SpecialMove::TimeUp));
+        assert_eq!(
+            game_end(&gote),
+            &CsaEvent::GameEnd {
+                reason: Some(CsaEndReason::TimeUp),
+                result: CsaGameResult::Lose,
+            }
+        );
+    }
+
+    #[test]
+    fn test_csa_server_time_margin() {
+        // 秒読みは超えているが猶予内に指した手は受け付ける。
+        let config = CsaServerConfig {
+            time_control: TimeControl {
+                main: Duration::ZERO,
+                byoyomi: Duration::from_millis(200),
+                increment: Duration::ZERO,
+            },
+            time_unit: Duration::from_millis(100),
+            time_margin: Duration::from_millis(500),
+            ..Default::default()
+        };
+        let (game, _, _) = play(config, &["7g7f", "resign"], &["sleep 350", "3c3d"]);
+
+        assert_eq!(game.winner(), Some(GOTE));
+        assert_eq!(game.reason(), GameEndReason::Resign);
+        assert_eq!(
+            game.record().kifu(),
+            Kifu::from_str("startpos moves 7g7f 3c3d").unwrap()
+        );
+        assert!(game.record().entries()[1].time().unwrap().elapsed() > Duration::from_millis(200));
+    }
+
+    #[test]
+    fn test_csa_server_login() {
+        let config = CsaServerConfig {
+            password: Some("pass".to_owned()),
+            ..Default::default()
+        };
+        let mut server = CsaServer::bind("127.0.0.1:0", config).unwrap();
+        let addr = server.local_addr().unwrap();
+        let server = std::thread::spawn(move || server.accept_player().unwrap().name().to_owned());
+
+        let mut client = CsaClient::connect(addr).unwrap();
+        assert!(matches!(
+            client.login("mallory", "wrong"),
+            Err(CsaClientError::LoginFailed)
+        ));
+        let mut client = CsaClient::connect(addr).unwrap();
+        client.login("alice", "pass").unwrap();
+
+        assert_eq!(server.join().unwrap(), "alice");
+    }
+}
@@ -27,7 +27,7 @@ pub const HAND_ROOK: HandPieceKind = HandPieceKind::Rook;
 pub const HAND_GOLD: HandPieceKind = HandPieceKind::Gold;
 
 impl HandPieceKind {
-    const NUM: usize = 7;
+    pub(crate) const NUM: usize = 7;
 
     const fn to_index(self) -> usize {
         self as usize
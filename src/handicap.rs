@@ -0,0 +1,151 @@
+use crate::board::*;
+use crate::hand::*;
+use crate::position::*;
+use crate::side::*;
+use crate::square::*;
+
+/// 手合割。駒落ちの場合、上手(後手)が駒を落とし、上手から指す。
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Handicap {
+    /// 平手。
+    Even,
+    /// 香落ち(1 一の香を落とす)。
+    Lance,
+    /// 右香落ち(9 一の香を落とす)。
+    RightLance,
+    /// 角落ち。
+    Bishop,
+    /// 飛車落ち。
+    Rook,
+    /// 飛香落ち。
+    RookLance,
+    /// 二枚落ち。
+    TwoPieces,
+    /// 三枚落ち。
+    ThreePieces,
+    /// 四枚落ち。
+    FourPieces,
+    /// 五枚落ち(8 一の桂を落とす)。
+    FivePieces,
+    /// 左五枚落ち(2 一の桂を落とす)。
+    LeftFivePieces,
+    /// 六枚落ち。
+    SixPieces,
+    /// 左七枚落ち(3 一の銀を落とす)。
+    LeftSevenPieces,
+    /// 右七枚落ち(7 一の銀を落とす)。
+    RightSevenPieces,
+    /// 八枚落ち。
+    EightPieces,
+    /// 十枚落ち。
+    TenPieces,
+}
+
+impl Handicap {
+    const NUM: usize = 16;
+
+    /// 全ての手合割を返す。順序は未規定。
+    pub const fn all() -> [Self; Self::NUM] {
+        [
+            Self::Even,
+            Self::Lance,
+            Self::RightLance,
+            Self::Bishop,
+            Self::Rook,
+            Self::RookLance,
+            Self::TwoPieces,
+            Self::ThreePieces,
+            Self::FourPieces,
+            Self::FivePieces,
+            Self::LeftFivePieces,
+            Self::SixPieces,
+            Self::LeftSevenPieces,
+            Self::RightSevenPieces,
+            Self::EightPieces,
+            Self::TenPieces,
+        ]
+    }
+
+    /// 上手が落とす駒のマスを返す。
+    const fn removed_squares(self) -> &'static [Square] {
+        match self {
+            Self::Even => &[],
+            Self::Lance => &[SQ_11],
+            Self::RightLance => &[SQ_91],
+            Self::Bishop => &[SQ_22],
+            Self::Rook => &[SQ_82],
+            Self::RookLance => &[SQ_82, SQ_11],
+            Self::TwoPieces => &[SQ_82, SQ_22],
+            Self::ThreePieces => &[SQ_82, SQ_22, SQ_11],
+            Self::FourPieces => &[SQ_82, SQ_22, SQ_11, SQ_91],
+            Self::FivePieces => &[SQ_82, SQ_22, SQ_11, SQ_91, SQ_81],
+            Self::LeftFivePieces => &[SQ_82, SQ_22, SQ_11, SQ_91, SQ_21],
+            Self::SixPieces => &[SQ_82, SQ_22, SQ_11, SQ_91, SQ_21, SQ_81],
+            Self::LeftSevenPieces => &[SQ_82, SQ_22, SQ_11, SQ_91, SQ_21, SQ_81, SQ_31],
+            Self::RightSevenPieces => &[SQ_82, SQ_22, SQ_11, SQ_91, SQ_21, SQ_81, SQ_71],
+            Self::EightPieces => &[SQ_82, SQ_22, SQ_11, SQ_91, SQ_21, SQ_81, SQ_31, SQ_71],
+            Self::TenPieces => &[
+                SQ_82, SQ_22, SQ_11, SQ_91, SQ_21, SQ_81, SQ_31, SQ_71, SQ_41, SQ_61,
+            ],
+        }
+    }
+
+    /// 手合割に対応する開始局面を返す。
+    pub fn position(self) -> Position {
+        if self == Self::Even {
+            return Position::startpos();
+        }
+
+        let mut board = Board::startpos();
+        for &sq in self.removed_squares() {
+            board[sq] = None;
+        }
+
+        Position::new(GOTE, board, Hands::empty(), PLY_1)
+    }
+
+    /// 局面に対応する手合割を返す。該当するものがなければ `None` を返す。
+    ///
+    /// 手数は無視する。
+    pub fn from_position(pos: &Position) -> Option<Self> {
+        Self::all().into_iter().find(|handicap| {
+            let expected = handicap.position();
+            expected.side_to_move() == pos.side_to_move()
+                && expected.board() == pos.board()
+                && expected.hands() == pos.hands()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr as _;
+
+    use super::*;
+
+    #[test]
+    fn test_handicap_position() {
+        assert_eq!(Handicap::Even.position(), Position::startpos());
+        assert_eq!(
+            Handicap::Lance.position(),
+            Position::from_str("lnsgkgsn1/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1")
+                .unwrap()
+        );
+        assert_eq!(
+            Handicap::LeftFivePieces.position(),
+            Position::from_str("1nsgkgs2/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1")
+                .unwrap()
+        );
+        assert_eq!(
+            Handicap::TenPieces.position(),
+            Position::from_str("4k4/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1").unwrap()
+        );
+
+        for handicap in Handicap::all() {
+            assert_eq!(
+                Handicap::from_position(&handicap.position()),
+                Some(handicap)
+            );
+        }
+    }
+}
@@ -0,0 +1,245 @@
+//! dlshogi の学習用データ (hcpe) の読み書き。
+
+use std::io::{Read, Write};
+
+use crate::move_::*;
+use crate::packed_sfen::*;
+use crate::packed_sfen_value::*;
+use crate::position::*;
+use crate::side::*;
+
+/// dlshogi の学習用データの 1 レコード (HuffmanCodedPosAndEval)。
+///
+/// ファイル上では 38 バイトで、HuffmanCodedPos (32 バイト)、評価値 (i16)、
+/// 指し手 (Apery の 16 ビット表現)、勝敗 (u8)、パディング (1 バイト) をリトルエンディアンで並べたもの。
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Hcpe {
+    /// 局面。HuffmanCodedPos は手数を含まないので、手数は 1 とする。
+    pub position: Position,
+
+    /// 局面の評価値(手番側から見た値)。
+    pub eval: i16,
+
+    /// 局面での最善手。
+    pub best_move: Move,
+
+    /// 対局の勝者。引き分けなら `None`。
+    pub winner: Option<Side>,
+}
+
+/// hcpe のデコードエラー。
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum HcpeDecodeError {
+    /// 局面が不正。
+    InvalidPosition(PackedSfenError),
+
+    /// 指し手が不正。
+    InvalidMove(MoveDecodeError),
+
+    /// 勝敗の値が不正(0: 引き分け, 1: 先手勝ち, 2: 後手勝ち のいずれでもない)。
+    InvalidResult(u8),
+}
+
+impl std::fmt::Display for HcpeDecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::InvalidPosition(e) => write!(f, "invalid position: {e}"),
+            Self::InvalidMove(e) => write!(f, "invalid move: {e}"),
+            Self::InvalidResult(x) => write!(f, "invalid game result: {x}"),
+        }
+    }
+}
+
+impl std::error::Error for HcpeDecodeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::InvalidPosition(e) => Some(e),
+            Self::InvalidMove(e) => Some(e),
+            Self::InvalidResult(_) => None,
+        }
+    }
+}
+
+/// 勝者を dlshogi の勝敗の値 (0: 引き分け, 1: 先手勝ち, 2: 後手勝ち) に変換する。
+pub(crate) fn winner_to_u8(winner: Option<Side>) -> u8 {
+    match winner {
+        None => 0,
+        Some(SENTE) => 1,
+        Some(GOTE) => 2,
+    }
+}
+
+/// dlshogi の勝敗の値を勝者に変換する。
+pub(crate) fn winner_from_u8(value: u8) -> Option<Option<Side>> {
+    match value {
+        0 => Some(None),
+        1 => Some(Some(SENTE)),
+        2 => Some(Some(GOTE)),
+        _ => None,
+    }
+}
+
+impl Hcpe {
+    /// 1 レコードのバイト数。
+    pub const SIZE: usize = 38;
+
+    /// 1 レコード分のバイト列を返す。
+    ///
+    /// # Panics
+    ///
+    /// 局面を HuffmanCodedPos で表現できない場合 (`Position::to_hcp` を参照)、panic する。
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        bytes[0..32].copy_from_slice(&self.position.to_hcp());
+        bytes[32..34].copy_from_slice(&self.eval.to_le_bytes());
+        bytes[34..36].copy_from_slice(&self.best_move.to_apery_u16().to_le_bytes());
+        bytes[36] = winner_to_u8(self.winner);
+
+        bytes
+    }
+
+    /// 1 レコード分のバイト列をデコードする。パディングは無視する。
+    pub fn from_bytes(bytes: &[u8; Self::SIZE]) -> Result<Self, HcpeDecodeError> {
+        let hcp: &[u8; 32] = bytes[0..32].try_into().unwrap();
+        let eval = i16::from_le_bytes([bytes[32], bytes[33]]);
+        let mv = u16::from_le_bytes([bytes[34], bytes[35]]);
+        let result = bytes[36];
+
+        let position = Position::from_hcp(hcp).map_err(HcpeDecodeError::InvalidPosition)?;
+        let best_move = Move::from_apery_u16(mv).map_err(HcpeDecodeError::InvalidMove)?;
+        let winner = winner_from_u8(result).ok_or(HcpeDecodeError::InvalidResult(result))?;
+
+        Ok(Self {
+            position,
+            eval,
+            best_move,
+            winner,
+        })
+    }
+}
+
+/// hcpe の読み込みエラー。
+pub type HcpeReadError = TrainingDataReadError<HcpeDecodeError>;
+
+/// hcpe をストリームから 1 レコードずつ読み込むイテレータ。
+///
+/// エラー時の振る舞いは `PackedSfenValueReader` と同じ。
+#[derive(Debug)]
+pub struct HcpeReader<R>(TrainingDataReader<R, Hcpe, HcpeDecodeError>);
+
+impl<R: Read> HcpeReader<R> {
+    /// ストリームから読み込むリーダーを作る。
+    pub fn new(inner: R) -> Self {
+        Self(TrainingDataReader::fixed::<{ Hcpe::SIZE }>(
+            inner,
+            |bytes| Hcpe::from_bytes(bytes.try_into().unwrap()),
+        ))
+    }
+
+    /// 内部のストリームを返す。
+    pub fn into_inner(self) -> R {
+        self.0.into_inner()
+    }
+}
+
+impl<R: Read> Iterator for HcpeReader<R> {
+    type Item = Result<Hcpe, HcpeReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+}
+
+/// hcpe をストリームに 1 レコードずつ書き込む。
+#[derive(Debug)]
+pub struct HcpeWriter<W> {
+    inner: W,
+}
+
+impl<W: Write> HcpeWriter<W> {
+    /// ストリームに書き込むライターを作る。
+    pub fn new(inner: W) -> Self {
+        Self { inner }
+    }
+
+    /// 1 レコード書き込む。
+    ///
+    /// # Panics
+    ///
+    /// 局面を HuffmanCodedPos で表現できない場合、panic する。
+    pub fn write(&mut self, hcpe: &Hcpe) -> std::io::Result<()> {
+        self.inner.write_all(&hcpe.to_bytes())
+    }
+
+    /// 内部のストリームをフラッシュする。
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+
+    /// 内部のストリームを返す。
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr as _;
+
+    use super::*;
+
+    fn hcpe(sfen: &str, eval: i16, mv: &str, winner: Option<Side>) -> Hcpe {
+        Hcpe {
+            position: Position::from_str(sfen).unwrap(),
+            eval,
+            best_move: Move::from_str(mv).unwrap(),
+            winner,
+        }
+    }
+
+    #[test]
+    fn test_hcpe() {
+        let records = [
+            hcpe("startpos", 50, "7g7f", Some(SENTE)),
+            hcpe(
+                "sfen lnsgkgsnl/1r5b1/ppppppppp/9/9/2P6/PP1PPPPPP/1B5R1/LNSGKGSNL w - 1",
+                -40,
+                "3c3d",
+                Some(GOTE),
+            ),
+            hcpe(
+                "sfen ln1g3nl/1r1s1kgs1/p1pppp1pp/6p2/1p7/2P6/PPSPPPPPP/7R1/LN1GKGSNL w Bb 1",
+                0,
+                "B*5e",
+                None,
+            ),
+        ];
+
+        let bytes = records[0].to_bytes();
+        assert_eq!(&bytes[32..34], 50_i16.to_le_bytes());
+        // 7七 (= 60) → 7六 (= 59)。
+        assert_eq!(&bytes[34..36], (59_u16 | (60 << 7)).to_le_bytes());
+        assert_eq!(bytes[36], 1);
+        assert_eq!(bytes[37], 0);
+
+        let mut writer = HcpeWriter::new(vec![]);
+        for record in &records {
+            writer.write(record).unwrap();
+        }
+        let data = writer.into_inner();
+        assert_eq!(data.len(), 3 * Hcpe::SIZE);
+
+        let read: Vec<_> = HcpeReader::new(data.as_slice())
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(read, records);
+
+        let mut bad = records[1].to_bytes();
+        bad[36] = 3;
+        assert_eq!(
+            Hcpe::from_bytes(&bad),
+            Err(HcpeDecodeError::InvalidResult(3))
+        );
+    }
+}
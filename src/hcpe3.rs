@@ -0,0 +1,410 @@
+//! dlshogi の対局単位の学習用データ (hcpe3) の読み書き。
+
+use std::io::{Read, Write};
+
+use crate::hcpe::*;
+use crate::kifu::*;
+use crate::move_::*;
+use crate::packed_sfen::*;
+use crate::packed_sfen_value::*;
+use crate::position::*;
+use crate::referee::*;
+use crate::side::*;
+
+/// 勝敗の値に付加される千日手フラグ。
+const FLAG_SENNICHITE: u8 = 0x04;
+
+/// 勝敗の値に付加される入玉宣言フラグ。
+const FLAG_NYUGYOKU: u8 = 0x08;
+
+/// 勝敗の値に付加される最大手数フラグ。
+const FLAG_MAXMOVE: u8 = 0x10;
+
+/// ヘッダ (HuffmanCodedPosAndEval3) のバイト数。
+const HEADER_SIZE: usize = 36;
+
+/// 指し手ごとの情報 (MoveInfo) のバイト数。
+const MOVE_INFO_SIZE: usize = 6;
+
+/// 候補手ごとの情報 (MoveVisits) のバイト数。
+const MOVE_VISITS_SIZE: usize = 4;
+
+/// hcpe3 の 1 局分のデータ。
+///
+/// ファイル上では、ヘッダ (HuffmanCodedPos、手数 (u16)、勝敗 (u8)、対戦相手 (u8))に続き、
+/// 指し手ごとに MoveInfo (指し手 (u16)、評価値 (i16)、候補手の数 (u16)) と
+/// 候補手の数だけの MoveVisits (指し手 (u16)、訪問回数 (u16)) をリトルエンディアンで並べたもの。
+/// 指し手は Apery の 16 ビット表現。
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Hcpe3Game {
+    /// 棋譜。開始局面の手数は 1 とする。指し手の合法性は検査しない。
+    pub kifu: Kifu,
+
+    /// 対局の勝者。引き分けなら `None`。
+    pub winner: Option<Side>,
+
+    /// 終局理由。
+    ///
+    /// 千日手 (`Repetition`)、入玉宣言 (`DeclareWin`)、最大手数 (`MaxMoves`) 以外は記録されないので、
+    /// 読み込んだデータではそれ以外は `None` となる。
+    pub reason: Option<GameEndReason>,
+
+    /// USI エンジンが指した側。自己対局なら `None`。
+    pub opponent: Option<Side>,
+
+    /// 指し手ごとの情報。`kifu.moves()` と同じ長さ。
+    pub move_infos: Vec<Hcpe3MoveInfo>,
+}
+
+/// hcpe3 の指し手ごとの情報。
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Hcpe3MoveInfo {
+    /// 指す前の局面の評価値(手番側から見た値)。
+    pub eval: i16,
+
+    /// 探索での候補手と訪問回数。学習に使わない指し手では空。
+    pub candidates: Vec<Hcpe3Candidate>,
+}
+
+/// hcpe3 の候補手。
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Hcpe3Candidate {
+    /// 指し手。
+    pub mv: Move,
+
+    /// 訪問回数。
+    pub visits: u16,
+}
+
+/// hcpe3 のデコードエラー。
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Hcpe3DecodeError {
+    /// 開始局面が不正。
+    InvalidPosition(PackedSfenError),
+
+    /// `index` 手目(0 始まり)の指し手または候補手が不正。
+    InvalidMove {
+        index: usize,
+        error: MoveDecodeError,
+    },
+
+    /// 勝敗の値が不正。
+    InvalidResult(u8),
+
+    /// 対戦相手の値が不正(0: 自己対局, 1: 先手が USI エンジン, 2: 後手が USI エンジン のいずれでもない)。
+    InvalidOpponent(u8),
+}
+
+impl std::fmt::Display for Hcpe3DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::InvalidPosition(e) => write!(f, "invalid position: {e}"),
+            Self::InvalidMove { index, error } => write!(f, "invalid move {index}: {error}"),
+            Self::InvalidResult(x) => write!(f, "invalid game result: {x}"),
+            Self::InvalidOpponent(x) => write!(f, "invalid opponent: {x}"),
+        }
+    }
+}
+
+impl std::error::Error for Hcpe3DecodeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::InvalidPosition(e) => Some(e),
+            Self::InvalidMove { error, .. } => Some(error),
+            Self::InvalidResult(_) | Self::InvalidOpponent(_) => None,
+        }
+    }
+}
+
+impl Hcpe3Game {
+    /// 1 局分のバイト列を返す。
+    ///
+    /// 千日手、入玉宣言、最大手数以外の終局理由は記録されない。連続王手の千日手は千日手として記録する。
+    ///
+    /// # Panics
+    ///
+    /// 以下の場合、panic する:
+    ///
+    /// * 開始局面を HuffmanCodedPos で表現できない (`Position::to_hcp` を参照)。
+    /// * `move_infos` の長さが棋譜の手数と異なる。
+    /// * 手数または候補手の数が `u16::MAX` を超える。
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mvs = self.kifu.moves();
+        assert_eq!(
+            mvs.len(),
+            self.move_infos.len(),
+            "move_infos must have the same length as the moves"
+        );
+
+        let flag = match self.reason {
+            Some(GameEndReason::Repetition | GameEndReason::PerpetualCheck) => FLAG_SENNICHITE,
+            Some(GameEndReason::DeclareWin) => FLAG_NYUGYOKU,
+            Some(GameEndReason::MaxMoves) => FLAG_MAXMOVE,
+            _ => 0,
+        };
+        let opponent = match self.opponent {
+            None => 0,
+            Some(SENTE) => 1,
+            Some(GOTE) => 2,
+        };
+
+        let mut bytes = vec![];
+        bytes.extend_from_slice(&self.kifu.position().to_hcp());
+        bytes.extend_from_slice(&u16::try_from(mvs.len()).unwrap().to_le_bytes());
+        bytes.push(winner_to_u8(self.winner) | flag);
+        bytes.push(opponent);
+
+        for (mv, info) in mvs.iter().zip(&self.move_infos) {
+            bytes.extend_from_slice(&mv.to_apery_u16().to_le_bytes());
+            bytes.extend_from_slice(&info.eval.to_le_bytes());
+            bytes.extend_from_slice(&u16::try_from(info.candidates.len()).unwrap().to_le_bytes());
+            for cand in &info.candidates {
+                bytes.extend_from_slice(&cand.mv.to_apery_u16().to_le_bytes());
+                bytes.extend_from_slice(&cand.visits.to_le_bytes());
+            }
+        }
+
+        bytes
+    }
+
+    /// 1 局分のバイト列(長さは検査済み)をデコードする。
+    fn decode(bytes: &[u8]) -> Result<Self, Hcpe3DecodeError> {
+        let u16_at = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+
+        let hcp: &[u8; 32] = bytes[0..32].try_into().unwrap();
+        let move_num = usize::from(u16_at(32));
+        let result = bytes[34];
+        let opponent = bytes[35];
+
+        let pos = Position::from_hcp(hcp).map_err(Hcpe3DecodeError::InvalidPosition)?;
+
+        let winner =
+            winner_from_u8(result & 0x03).ok_or(Hcpe3DecodeError::InvalidResult(result))?;
+        let reason = match result & !0x03 {
+            0 => None,
+            FLAG_SENNICHITE => Some(GameEndReason::Repetition),
+            FLAG_NYUGYOKU => Some(GameEndReason::DeclareWin),
+            FLAG_MAXMOVE => Some(GameEndReason::MaxMoves),
+            _ => return Err(Hcpe3DecodeError::InvalidResult(result)),
+        };
+        let opponent = match opponent {
+            0 => None,
+            1 => Some(SENTE),
+            2 => Some(GOTE),
+            _ => return Err(Hcpe3DecodeError::InvalidOpponent(opponent)),
+        };
+
+        let decode_move = |index: usize, value: u16| {
+            Move::from_apery_u16(value)
+                .map_err(|error| Hcpe3DecodeError::InvalidMove { index, error })
+        };
+
+        let mut mvs = Vec::with_capacity(move_num);
+        let mut move_infos = Vec::with_capacity(move_num);
+        let mut i = HEADER_SIZE;
+        for index in 0..move_num {
+            mvs.push(decode_move(index, u16_at(i))?);
+            let eval = i16::from_le_bytes([bytes[i + 2], bytes[i + 3]]);
+            let candidate_num = usize::from(u16_at(i + 4));
+            i += MOVE_INFO_SIZE;
+
+            let mut candidates = Vec::with_capacity(candidate_num);
+            for _ in 0..candidate_num {
+                candidates.push(Hcpe3Candidate {
+                    mv: decode_move(index, u16_at(i))?,
+                    visits: u16_at(i + 2),
+                });
+                i += MOVE_VISITS_SIZE;
+            }
+
+            move_infos.push(Hcpe3MoveInfo { eval, candidates });
+        }
+        debug_assert_eq!(i, bytes.len());
+
+        Ok(Self {
+            kifu: Kifu::new(pos, mvs),
+            winner,
+            reason,
+            opponent,
+            move_infos,
+        })
+    }
+}
+
+/// hcpe3 の読み込みエラー。1 局分の対局データを 1 レコードとして扱う。
+pub type Hcpe3ReadError = TrainingDataReadError<Hcpe3DecodeError>;
+
+/// hcpe3 をストリームから 1 局ずつ読み込むイテレータ。
+///
+/// エラー時の振る舞いは `PackedSfenValueReader` と同じ。
+#[derive(Debug)]
+pub struct Hcpe3Reader<R>(TrainingDataReader<R, Hcpe3Game, Hcpe3DecodeError>);
+
+impl<R: Read> Hcpe3Reader<R> {
+    /// ストリームから読み込むリーダーを作る。
+    pub fn new(inner: R) -> Self {
+        Self(TrainingDataReader::new(inner, read_game, Hcpe3Game::decode))
+    }
+
+    /// 内部のストリームを返す。
+    pub fn into_inner(self) -> R {
+        self.0.into_inner()
+    }
+}
+
+impl<R: Read> Iterator for Hcpe3Reader<R> {
+    type Item = Result<Hcpe3Game, Hcpe3ReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+}
+
+/// 1 局分のバイト列を `buf` に読み込む。途切れていたら `false` を返す。
+fn read_game(inner: &mut impl Read, buf: &mut Vec<u8>) -> std::io::Result<bool> {
+    if !read_bytes(inner, buf, HEADER_SIZE)? {
+        return Ok(false);
+    }
+    let move_num = u16::from_le_bytes([buf[32], buf[33]]);
+
+    for _ in 0..move_num {
+        let start = buf.len();
+        if !read_bytes(inner, buf, MOVE_INFO_SIZE)? {
+            return Ok(false);
+        }
+        let candidate_num = usize::from(u16::from_le_bytes([buf[start + 4], buf[start + 5]]));
+        if !read_bytes(inner, buf, MOVE_VISITS_SIZE * candidate_num)? {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+/// hcpe3 をストリームに 1 局ずつ書き込む。
+#[derive(Debug)]
+pub struct Hcpe3Writer<W> {
+    inner: W,
+}
+
+impl<W: Write> Hcpe3Writer<W> {
+    /// ストリームに書き込むライターを作る。
+    pub fn new(inner: W) -> Self {
+        Self { inner }
+    }
+
+    /// 1 局書き込む。
+    ///
+    /// # Panics
+    ///
+    /// `Hcpe3Game::to_bytes` が panic する場合、panic する。
+    pub fn write(&mut self, game: &Hcpe3Game) -> std::io::Result<()> {
+        self.inner.write_all(&game.to_bytes())
+    }
+
+    /// 内部のストリームをフラッシュする。
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+
+    /// 内部のストリームを返す。
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr as _;
+
+    use super::*;
+
+    fn mv(s: &str) -> Move {
+        Move::from_str(s).unwrap()
+    }
+
+    fn info(eval: i16, candidates: &[(&str, u16)]) -> Hcpe3MoveInfo {
+        Hcpe3MoveInfo {
+            eval,
+            candidates: candidates
+                .iter()
+                .map(|&(s, visits)| Hcpe3Candidate { mv: mv(s), visits })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_hcpe3() {
+        let games = [
+            Hcpe3Game {
+                kifu: Kifu::from_str("startpos moves 7g7f 3c3d 8h2b+").unwrap(),
+                winner: Some(SENTE),
+                reason: None,
+                opponent: None,
+                move_infos: vec![
+                    info(30, &[("7g7f", 600), ("2g2f", 400)]),
+                    info(-20, &[]),
+                    info(100, &[("8h2b+", 1000)]),
+                ],
+            },
+            Hcpe3Game {
+                kifu: Kifu::from_str(
+                    "sfen ln1g3nl/1r1s1kgs1/p1pppp1pp/6p2/1p7/2P6/PPSPPPPPP/7R1/LN1GKGSNL w Bb 1 moves B*5e",
+                )
+                .unwrap(),
+                winner: None,
+                reason: Some(GameEndReason::MaxMoves),
+                opponent: Some(GOTE),
+                move_infos: vec![info(0, &[("B*5e", 7)])],
+            },
+        ];
+
+        let bytes = games[0].to_bytes();
+        assert_eq!(
+            bytes.len(),
+            HEADER_SIZE + 3 * MOVE_INFO_SIZE + 3 * MOVE_VISITS_SIZE
+        );
+        assert_eq!(&bytes[32..34], 3_u16.to_le_bytes());
+        assert_eq!(bytes[34], 1);
+        assert_eq!(bytes[35], 0);
+        // 7七 (= 60) → 7六 (= 59)、評価値 30、候補手 2 つ。
+        assert_eq!(&bytes[36..38], (59_u16 | (60 << 7)).to_le_bytes());
+        assert_eq!(&bytes[38..40], 30_i16.to_le_bytes());
+        assert_eq!(&bytes[40..42], 2_u16.to_le_bytes());
+        assert_eq!(&bytes[44..46], 600_u16.to_le_bytes());
+
+        let bytes = games[1].to_bytes();
+        assert_eq!(bytes[34], FLAG_MAXMOVE);
+        assert_eq!(bytes[35], 2);
+
+        let mut writer = Hcpe3Writer::new(vec![]);
+        for game in &games {
+            writer.write(game).unwrap();
+        }
+        let mut data = writer.into_inner();
+
+        let read: Vec<_> = Hcpe3Reader::new(data.as_slice())
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(read, games);
+
+        // 指し手ごとの情報の途中で途切れている。
+        let len0 = games[0].to_bytes().len();
+        data.truncate(len0 + HEADER_SIZE + 2);
+        let mut reader = Hcpe3Reader::new(data.as_slice());
+        assert_eq!(reader.next().unwrap().unwrap(), games[0]);
+        assert!(matches!(
+            reader.next(),
+            Some(Err(Hcpe3ReadError::Truncated { len })) if len == HEADER_SIZE + 2
+        ));
+        assert!(reader.next().is_none());
+
+        let mut bad = games[0].to_bytes();
+        bad[35] = 3;
+        assert_eq!(
+            Hcpe3Game::decode(&bad),
+            Err(Hcpe3DecodeError::InvalidOpponent(3))
+        );
+    }
+}
@@ -0,0 +1,609 @@
+//! 日本語の指し手表記と、その他の日本語表記(KIF, KI2 など)の共通部品。
+
+use crate::hand::*;
+use crate::move_::*;
+use crate::piece::*;
+use crate::position::*;
+use crate::side::*;
+use crate::square::*;
+
+/// 筋を全角数字で返す。
+pub(crate) const fn col_to_zenkaku(col: Col) -> char {
+    match col {
+        COL_1 => '１',
+        COL_2 => '２',
+        COL_3 => '３',
+        COL_4 => '４',
+        COL_5 => '５',
+        COL_6 => '６',
+        COL_7 => '７',
+        COL_8 => '８',
+        COL_9 => '９',
+    }
+}
+
+/// 段を漢数字で返す。
+pub(crate) const fn row_to_kanji(row: Row) -> char {
+    match row {
+        ROW_1 => '一',
+        ROW_2 => '二',
+        ROW_3 => '三',
+        ROW_4 => '四',
+        ROW_5 => '五',
+        ROW_6 => '六',
+        ROW_7 => '七',
+        ROW_8 => '八',
+        ROW_9 => '九',
+    }
+}
+
+/// 指し手表記で使う駒種名を返す。成香、成桂、成銀は 2 文字になる。
+pub(crate) const fn piece_kind_to_name(pk: PieceKind) -> &'static str {
+    match pk {
+        PAWN => "歩",
+        LANCE => "香",
+        KNIGHT => "桂",
+        SILVER => "銀",
+        GOLD => "金",
+        BISHOP => "角",
+        ROOK => "飛",
+        KING => "玉",
+        PRO_PAWN => "と",
+        PRO_LANCE => "成香",
+        PRO_KNIGHT => "成桂",
+        PRO_SILVER => "成銀",
+        HORSE => "馬",
+        DRAGON => "龍",
+    }
+}
+
+/// 盤面図で使う 1 文字の駒種名を返す。
+pub(crate) const fn piece_kind_to_kanji(pk: PieceKind) -> char {
+    match pk {
+        PAWN => '歩',
+        LANCE => '香',
+        KNIGHT => '桂',
+        SILVER => '銀',
+        GOLD => '金',
+        BISHOP => '角',
+        ROOK => '飛',
+        KING => '玉',
+        PRO_PAWN => 'と',
+        PRO_LANCE => '杏',
+        PRO_KNIGHT => '圭',
+        PRO_SILVER => '全',
+        HORSE => '馬',
+        DRAGON => '龍',
+    }
+}
+
+/// 表示幅を返す。ASCII 文字を幅 1、それ以外を幅 2 とみなす。
+pub(crate) fn display_width(s: &str) -> usize {
+    s.chars().map(|c| if c.is_ascii() { 1 } else { 2 }).sum()
+}
+
+/// 先頭の数字(全角/半角)を読む。
+pub(crate) fn parse_digit(s: &str) -> Option<(&str, u8)> {
+    let c = s.chars().next()?;
+    let n = match c {
+        '1'..='9' => c as u8 - b'0',
+        '１'..='９' => (c as u32 - '０' as u32) as u8,
+        _ => return None,
+    };
+
+    Some((&s[c.len_utf8()..], n))
+}
+
+/// 先頭の漢数字(一〜九)を読む。
+pub(crate) fn parse_kanji_digit(s: &str) -> Option<(&str, u8)> {
+    const TABLE: [char; 9] = ['一', '二', '三', '四', '五', '六', '七', '八', '九'];
+
+    let c = s.chars().next()?;
+    let n = TABLE.iter().position(|&k| k == c)? as u8 + 1;
+
+    Some((&s[c.len_utf8()..], n))
+}
+
+/// 先頭のマス(`７六`, `76` など)を読む。
+///
+/// 筋は全角/半角数字、段は漢数字/全角/半角数字を受け付ける。
+pub(crate) fn parse_square(s: &str) -> Option<(&str, Square)> {
+    let (s, col) = parse_digit(s)?;
+    let (s, row) = parse_kanji_digit(s).or_else(|| parse_digit(s))?;
+
+    let sq = Square::new(Col::from_num(col)?, Row::from_num(row)?);
+
+    Some((s, sq))
+}
+
+/// 先頭の駒種名を読む。`王`, `竜` などの異表記も受け付ける。
+pub(crate) fn parse_piece_kind(s: &str) -> Option<(&str, PieceKind)> {
+    const TABLE: &[(&str, PieceKind)] = &[
+        ("成香", PRO_LANCE),
+        ("成桂", PRO_KNIGHT),
+        ("成銀", PRO_SILVER),
+        ("歩", PAWN),
+        ("香", LANCE),
+        ("桂", KNIGHT),
+        ("銀", SILVER),
+        ("金", GOLD),
+        ("角", BISHOP),
+        ("飛", ROOK),
+        ("玉", KING),
+        ("王", KING),
+        ("と", PRO_PAWN),
+        ("杏", PRO_LANCE),
+        ("圭", PRO_KNIGHT),
+        ("全", PRO_SILVER),
+        ("馬", HORSE),
+        ("龍", DRAGON),
+        ("竜", DRAGON),
+    ];
+
+    TABLE
+        .iter()
+        .find_map(|&(name, pk)| s.strip_prefix(name).map(|remain| (remain, pk)))
+}
+
+/// 盤上の駒を動かす指し手で成れるかどうかを返す。
+pub(crate) fn can_promote(side: Side, pk: PieceKind, walk: MoveWalk) -> bool {
+    pk.is_promotable()
+        && (walk.src().row().is_promotion_zone(side) || walk.dst().row().is_promotion_zone(side))
+}
+
+/// `side` 側から見て `src` から `dst` へ前進する段数を返す。後退なら負。
+fn advance(side: Side, src: Square, dst: Square) -> i8 {
+    let d = src.row().to_num() as i8 - dst.row().to_num() as i8;
+    match side {
+        SENTE => d,
+        GOTE => -d,
+    }
+}
+
+/// `side` 側から見たマスの右からの順位を返す。小さいほど右。
+fn rightness(side: Side, sq: Square) -> u8 {
+    match side {
+        SENTE => sq.col().to_num(),
+        GOTE => 10 - sq.col().to_num(),
+    }
+}
+
+/// 動作を表す修飾語 (`上`, `引`, `寄`) を返す。
+fn vertical_modifier(side: Side, src: Square, dst: Square) -> char {
+    match advance(side, src, dst) {
+        1.. => '上',
+        0 => '寄',
+        _ => '引',
+    }
+}
+
+/// 移動元の候補 `srcs` を修飾語 `modifiers` (`右上` など) で絞り込む。
+fn filter_sources(side: Side, dst: Square, srcs: &[Square], modifiers: &str) -> Vec<Square> {
+    let mut srcs = srcs.to_vec();
+
+    for m in modifiers.chars() {
+        match m {
+            '上' | '引' | '寄' => srcs.retain(|&src| vertical_modifier(side, src, dst) == m),
+            '直' => srcs.retain(|&src| src.col() == dst.col() && advance(side, src, dst) > 0),
+            _ => {}
+        }
+    }
+
+    for m in modifiers.chars() {
+        let extreme = match m {
+            '右' => srcs.iter().map(|&src| rightness(side, src)).min(),
+            '左' => srcs.iter().map(|&src| rightness(side, src)).max(),
+            _ => continue,
+        };
+        srcs.retain(|&src| Some(rightness(side, src)) == extreme);
+    }
+
+    srcs
+}
+
+/// 移動元を一意に特定するのに必要な最小限の修飾語を返す。
+fn relative_modifiers(
+    side: Side,
+    pk: PieceKind,
+    src: Square,
+    dst: Square,
+    srcs: &[Square],
+) -> String {
+    if srcs.len() <= 1 {
+        return String::new();
+    }
+
+    let vertical = vertical_modifier(side, src, dst);
+    let horizontals: &[char] = if matches!(pk, HORSE | DRAGON) {
+        &['右', '左']
+    } else {
+        &['直', '右', '左']
+    };
+
+    let mut options = vec![vertical.to_string()];
+    options.extend(horizontals.iter().map(|h| h.to_string()));
+    options.extend(horizontals.iter().map(|h| format!("{h}{vertical}")));
+
+    options
+        .into_iter()
+        .find(|modifiers| filter_sources(side, dst, srcs, modifiers) == [src])
+        .unwrap_or_else(|| vertical.to_string())
+}
+
+impl Move {
+    /// 局面 `pos` における指し手を日本語表記 (`７六歩`, `同　銀`, `５八金右`, `５五角打` など) で返す。
+    ///
+    /// `prev` は直前の指し手で、移動先が同じなら `同` を用いる。
+    /// 移動元を特定するための修飾語(右, 左, 直, 上, 引, 寄)および `打` は必要な場合のみ付ける。
+    /// 成れる指し手で成らない場合は `不成` を付ける。
+    pub fn to_japanese(self, pos: &Position, prev: Option<Move>) -> Result<String, MoveError> {
+        fmt_ki2_move(pos, self, prev.map(Move::dst))
+    }
+}
+
+/// 指し手を KI2 形式 (`７六歩`, `同　銀`, `５八金右`, `５五角打` など) で返す。
+///
+/// 移動元を特定するための修飾語は必要な場合のみ付ける。
+/// `打` は盤上の同じ駒種の駒も移動先に動ける場合のみ付ける。
+pub(crate) fn fmt_ki2_move(
+    pos: &Position,
+    mv: Move,
+    last_dst: Option<Square>,
+) -> Result<String, MoveError> {
+    let us = pos.side_to_move();
+    let dst = mv.dst();
+
+    let pk = match mv {
+        Move::Walk(walk) => pos.board()[walk.src()]
+            .filter(|pc| pc.side() == us)
+            .ok_or(MoveError::NoOwnPieceAtSrc)?
+            .kind(),
+        Move::Drop(drop) => drop.piece_kind().into(),
+    };
+    let name = piece_kind_to_name(pk);
+
+    let mut s = String::new();
+    if last_dst == Some(dst) {
+        s.push('同');
+        if name.chars().count() == 1 {
+            s.push('　');
+        }
+    } else {
+        s.push(col_to_zenkaku(dst.col()));
+        s.push(row_to_kanji(dst.row()));
+    }
+    s.push_str(name);
+
+    s.push_str(&ki2_modifiers(pos, pk, mv));
+    if let Move::Walk(walk) = mv {
+        if walk.is_promotion() {
+            s.push('成');
+        } else if can_promote(us, pk, walk) {
+            s.push_str("不成");
+        }
+    }
+
+    Ok(s)
+}
+
+/// KI2 形式で移動元を特定するための修飾語 (`右上`, `打` など) を返す。不要なら空文字列を返す。
+///
+/// `pk` は動かす駒(駒打ちなら打つ駒)の駒種。
+pub(crate) fn ki2_modifiers(pos: &Position, pk: PieceKind, mv: Move) -> String {
+    let us = pos.side_to_move();
+    let srcs = pos.legal_attackers(pk, mv.dst());
+
+    match mv {
+        Move::Walk(walk) => relative_modifiers(us, pk, walk.src(), walk.dst(), &srcs),
+        Move::Drop(_) if srcs.is_empty() => String::new(),
+        Move::Drop(_) => "打".to_owned(),
+    }
+}
+
+impl Move {
+    /// 局面 `pos` における日本語表記の指し手 (`７六歩`, `76歩`, `同銀`, `5五角打`, `５八金右` など) をパースする。
+    ///
+    /// `prev` は直前の指し手で、`同` の解決に用いる。
+    /// 筋は全角/半角数字、段は漢数字/全角/半角数字を受け付ける。
+    /// 先頭の手番記号 (`▲`, `△`) と、末尾の KIF 形式の移動元 (`(77)`) も受け付ける。
+    /// 動いても自玉を取られない駒のみを移動元の候補とし、
+    /// 表記が複数の指し手に該当する場合は、候補を列挙したエラーを返す。
+    pub fn from_japanese(
+        s: &str,
+        pos: &Position,
+        prev: Option<Move>,
+    ) -> Result<Self, MoveNotationParseError> {
+        let s = s.trim();
+
+        let mut chars = s.chars();
+        let s = match chars.next() {
+            Some('▲' | '☗') if pos.side_to_move() == SENTE => chars.as_str(),
+            Some('△' | '☖') if pos.side_to_move() == GOTE => chars.as_str(),
+            Some('▲' | '☗' | '△' | '☖') => {
+                return Err(invalid_notation("side mark does not match side to move"))
+            }
+            _ => s,
+        };
+
+        let (remain, mv) = parse_ki2_move(s, pos, prev.map(Move::dst))?;
+        if !remain.trim().is_empty() {
+            return Err(invalid_notation("extra input after move"));
+        }
+
+        pos.clone()
+            .do_move(mv)
+            .map_err(MoveNotationParseError::InvalidMove)?;
+
+        Ok(mv)
+    }
+}
+
+/// KI2 形式の指し手 (`７六歩`, `同銀`, `５八金右` など) をパースし、局面に照らして `Move` を特定する。
+///
+/// `last_dst` は直前の指し手の移動先で、`同` の解決に用いる。
+/// KIF 形式の移動元 (`(77)`) が続く場合はそれも用いる。
+/// 移動元の候補は動いても自玉を取られない駒に限る。それ以外の合法性は検査しない。
+pub(crate) fn parse_ki2_move<'a>(
+    s: &'a str,
+    pos: &Position,
+    last_dst: Option<Square>,
+) -> Result<(&'a str, Move), MoveNotationParseError> {
+    let us = pos.side_to_move();
+
+    let (s, dst) = if let Some(s) = s.strip_prefix('同') {
+        let s = s.trim_start_matches(['　', ' ']);
+        (
+            s,
+            last_dst.ok_or(invalid_notation("no previous move for '同'"))?,
+        )
+    } else {
+        parse_square(s).ok_or(invalid_notation("destination square expected"))?
+    };
+
+    let (mut s, pk) = parse_piece_kind(s).ok_or(invalid_notation("piece kind expected"))?;
+
+    let mut modifiers = String::new();
+    let mut is_drop = false;
+    while let Some(c) = s.chars().next() {
+        match c {
+            '右' | '左' | '直' | '上' | '引' | '寄' => modifiers.push(c),
+            '打' => is_drop = true,
+            _ => break,
+        }
+        s = &s[c.len_utf8()..];
+    }
+
+    let (s, promo) = if let Some(s) = s.strip_prefix("不成").or_else(|| s.strip_prefix('生')) {
+        (s, false)
+    } else if let Some(s) = s.strip_prefix('成') {
+        (s, true)
+    } else {
+        (s, false)
+    };
+
+    let (s, explicit_src) = match parse_kif_source(s) {
+        Some((s, src)) => (s, Some(src)),
+        None => (s, None),
+    };
+
+    let drop = |s| {
+        let hpk = HandPieceKind::try_from(pk)
+            .map_err(|_| invalid_notation("piece kind cannot be dropped"))?;
+        Ok((s, Move::drop(hpk, dst)))
+    };
+
+    if is_drop {
+        if !modifiers.is_empty() || promo || explicit_src.is_some() {
+            return Err(invalid_notation("drop with modifiers"));
+        }
+        return drop(s);
+    }
+
+    let mut srcs = filter_sources(us, dst, &pos.legal_attackers(pk, dst), &modifiers);
+    if let Some(src) = explicit_src {
+        srcs.retain(|&sq| sq == src);
+    }
+    match srcs[..] {
+        [src] => Ok((s, Move::walk(src, dst, promo))),
+        [] if modifiers.is_empty() && explicit_src.is_none() && !promo => drop(s),
+        [] => Err(MoveNotationParseError::NoCandidate),
+        _ => Err(MoveNotationParseError::Ambiguous {
+            candidates: srcs
+                .into_iter()
+                .map(|src| Move::walk(src, dst, promo))
+                .collect(),
+        }),
+    }
+}
+
+/// KIF 形式の移動元 (`(77)`) を読む。
+fn parse_kif_source(s: &str) -> Option<(&str, Square)> {
+    let s = s.strip_prefix('(')?;
+    let (s, col) = parse_digit(s)?;
+    let (s, row) = parse_digit(s)?;
+    let s = s.strip_prefix(')')?;
+
+    Some((s, Square::new(Col::from_num(col)?, Row::from_num(row)?)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr as _;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_square() {
+        assert_eq!(parse_square("７六歩"), Some(("歩", SQ_76)));
+        assert_eq!(parse_square("76歩"), Some(("歩", SQ_76)));
+        assert_eq!(parse_square("1九"), Some(("", SQ_19)));
+        assert_eq!(parse_square("０一"), None);
+        assert_eq!(parse_square("同　歩"), None);
+    }
+
+    #[test]
+    fn test_ki2_move() {
+        let pos = Position::from_str("4k4/9/9/9/9/9/9/9/3GGG3 b G 1").unwrap();
+        let cases = [
+            ("５八金直", "5i5h"),
+            ("５八金右", "4i5h"),
+            ("５八金左", "6i5h"),
+            ("５八金打", "G*5h"),
+            ("３八金", "4i3h"),
+        ];
+        for (ki2, usi) in cases {
+            let mv = Move::from_str(usi).unwrap();
+            assert_eq!(fmt_ki2_move(&pos, mv, None).unwrap(), ki2);
+            assert_eq!(parse_ki2_move(ki2, &pos, None), Ok(("", mv)));
+        }
+        assert_eq!(
+            parse_ki2_move("５八金", &pos, None),
+            Err(MoveNotationParseError::Ambiguous {
+                candidates: vec![
+                    Move::from_str("6i5h").unwrap(),
+                    Move::from_str("5i5h").unwrap(),
+                    Move::from_str("4i5h").unwrap(),
+                ]
+            })
+        );
+
+        // 竜 2 枚: 上下の動作で区別できなければ左右で区別する。
+        let pos = Position::from_str("+R7+R/9/9/9/9/9/9/9/4k4 b - 1").unwrap();
+        let mv = Move::from_str("9a8a").unwrap();
+        assert_eq!(fmt_ki2_move(&pos, mv, None).unwrap(), "８一龍左");
+        assert_eq!(parse_ki2_move("８一竜左", &pos, None), Ok(("", mv)));
+
+        // 後手の右左は後手から見た向き。
+        let pos = Position::from_str("3gkg3/9/9/9/9/9/9/9/4K4 w - 1").unwrap();
+        let mv = Move::from_str("6a5b").unwrap();
+        assert_eq!(fmt_ki2_move(&pos, mv, None).unwrap(), "５二金右");
+        assert_eq!(fmt_ki2_move(&pos, mv, Some(SQ_52)).unwrap(), "同　金右");
+
+        // 釘付けにされた駒は移動元の候補にしない。
+        let pos = Position::from_str("4r3k/9/9/9/9/9/5G3/4G4/4K4 b - 1").unwrap();
+        let mv = Move::from_str("4g4h").unwrap();
+        assert_eq!(fmt_ki2_move(&pos, mv, None).unwrap(), "４八金");
+        assert_eq!(parse_ki2_move("４八金", &pos, None), Ok(("", mv)));
+
+        // 動ける駒がなければ駒打ちとみなす。
+        let pos = Position::from_str("4k4/9/9/9/9/9/2S6/9/4K4 b S 1").unwrap();
+        let mv = Move::from_str("S*7c").unwrap();
+        assert_eq!(fmt_ki2_move(&pos, mv, None).unwrap(), "７三銀");
+        assert_eq!(parse_ki2_move("７三銀", &pos, None), Ok(("", mv)));
+        assert_eq!(
+            parse_ki2_move("７三銀上", &pos, None),
+            Err(MoveNotationParseError::NoCandidate)
+        );
+    }
+
+    #[test]
+    fn test_move_to_japanese() {
+        let pos = Position::startpos();
+        let mv = Move::from_str("7g7f").unwrap();
+        assert_eq!(mv.to_japanese(&pos, None).unwrap(), "７六歩");
+
+        let pos = Position::from_str(
+            "lnsgkgsnl/1r5+B1/pppppp1pp/6p2/9/2P6/PP1PPPPPP/7R1/LNSGKGSNL w b 4",
+        )
+        .unwrap();
+        let prev = Move::from_str("8h2b+").unwrap();
+        let mv = Move::from_str("3a2b").unwrap();
+        assert_eq!(mv.to_japanese(&pos, Some(prev)).unwrap(), "同　銀");
+        assert_eq!(mv.to_japanese(&pos, None).unwrap(), "２二銀");
+
+        let pos = Position::from_str("4k4/9/9/9/4b4/9/9/9/4K4 w - 1").unwrap();
+        let mv = Move::from_str("5e3g+").unwrap();
+        assert_eq!(mv.to_japanese(&pos, None).unwrap(), "３七角成");
+        let mv = Move::from_str("5e7g").unwrap();
+        assert_eq!(mv.to_japanese(&pos, None).unwrap(), "７七角不成");
+
+        let pos = Position::from_str("4k4/9/9/9/9/9/9/9/4K4 b B 1").unwrap();
+        let mv = Move::from_str("B*5e").unwrap();
+        assert_eq!(mv.to_japanese(&pos, None).unwrap(), "５五角");
+        let pos = Position::from_str("4k4/9/9/9/9/9/9/1B7/4K4 b B 1").unwrap();
+        assert_eq!(mv.to_japanese(&pos, None).unwrap(), "５五角打");
+
+        let mv = Move::from_str("1a1b").unwrap();
+        assert_eq!(mv.to_japanese(&pos, None), Err(MoveError::NoOwnPieceAtSrc));
+    }
+
+    #[test]
+    fn test_move_from_japanese() {
+        let pos = Position::startpos();
+        let mv = Move::from_str("7g7f").unwrap();
+        assert_eq!(Move::from_japanese("７六歩", &pos, None), Ok(mv));
+        assert_eq!(Move::from_japanese("76歩", &pos, None), Ok(mv));
+        assert_eq!(Move::from_japanese("▲7六歩(77)", &pos, None), Ok(mv));
+        assert!(matches!(
+            Move::from_japanese("△７六歩", &pos, None),
+            Err(MoveNotationParseError::InvalidNotation { .. })
+        ));
+        assert!(matches!(
+            Move::from_japanese("７六歩成", &pos, None),
+            Err(MoveNotationParseError::InvalidMove(
+                MoveError::CannotPromote
+            ))
+        ));
+        assert_eq!(
+            Move::from_japanese("５八金", &pos, None),
+            Err(MoveNotationParseError::Ambiguous {
+                candidates: vec![
+                    Move::from_str("6i5h").unwrap(),
+                    Move::from_str("4i5h").unwrap(),
+                ]
+            })
+        );
+        assert_eq!(
+            Move::from_japanese("５八金左", &pos, None),
+            Ok(Move::from_str("6i5h").unwrap())
+        );
+
+        let pos = Position::from_str(
+            "lnsgkgsnl/1r5+B1/pppppp1pp/6p2/9/2P6/PP1PPPPPP/7R1/LNSGKGSNL w b 4",
+        )
+        .unwrap();
+        let prev = Move::from_str("8h2b+").unwrap();
+        assert_eq!(
+            Move::from_japanese("同銀", &pos, Some(prev)),
+            Ok(Move::from_str("3a2b").unwrap())
+        );
+        assert!(Move::from_japanese("同銀", &pos, None).is_err());
+
+        let pos =
+            Position::from_str("lnsgkg1nl/1r5s1/pppppp1pp/6p2/9/2P6/PP1PPPPPP/7R1/LNSGKGSNL b B 5")
+                .unwrap();
+        assert_eq!(
+            Move::from_japanese("5五角打", &pos, None),
+            Ok(Move::from_str("B*5e").unwrap())
+        );
+        assert_eq!(
+            Move::from_japanese("5五金", &pos, None),
+            Err(MoveNotationParseError::InvalidMove(MoveError::NotInHand))
+        );
+    }
+
+    #[test]
+    fn test_move_from_japanese_pinned() {
+        // 5八金は 5 筋の飛車に対して釘付けにされている。
+        let pos = Position::from_str("4r3k/9/9/9/9/9/5G3/4G4/4K4 b - 1").unwrap();
+        assert_eq!(
+            Move::from_japanese("４八金", &pos, None),
+            Ok(Move::from_str("4g4h").unwrap())
+        );
+        assert_eq!(
+            Move::from_japanese("４八金寄", &pos, None),
+            Err(MoveNotationParseError::NoCandidate)
+        );
+
+        // 唯一の候補が釘付けにされていれば、王手放置となる指し手は返さない。
+        let pos = Position::from_str("4r3k/9/9/9/9/9/9/4G4/4K4 b - 1").unwrap();
+        assert!(Move::from_japanese("４八金", &pos, None).is_err());
+    }
+
+    #[test]
+    fn test_parse_piece_kind() {
+        assert_eq!(parse_piece_kind("成香(12)"), Some(("(12)", PRO_LANCE)));
+        assert_eq!(parse_piece_kind("竜"), Some(("", DRAGON)));
+        assert_eq!(parse_piece_kind("成"), None);
+    }
+}
@@ -0,0 +1,804 @@
+use std::time::Duration;
+
+use crate::board::*;
+use crate::hand::*;
+use crate::handicap::*;
+use crate::japanese::*;
+use crate::json::*;
+use crate::kifu::*;
+use crate::move_::*;
+use crate::piece::*;
+use crate::position::*;
+use crate::record::*;
+use crate::side::*;
+use crate::square::*;
+
+/// JKF (JSON Kifu Format) のパースエラー。
+#[non_exhaustive]
+#[derive(Clone, Debug)]
+pub enum JkfParseError {
+    /// JSON として不正 (`offset` は入力先頭からのバイト位置)。
+    InvalidJson {
+        offset: usize,
+        description: &'static str,
+    },
+
+    /// JKF の構造として不正。
+    InvalidStructure { description: &'static str },
+
+    /// 指し手を局面に適用できない。
+    InvalidMove(MoveError),
+}
+
+impl std::fmt::Display for JkfParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::InvalidJson {
+                offset,
+                description,
+            } => write!(f, "invalid JSON at offset {offset}: {description}"),
+            Self::InvalidStructure { description } => write!(f, "invalid JKF: {description}"),
+            Self::InvalidMove(e) => write!(f, "invalid move: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for JkfParseError {}
+
+impl From<MoveError> for JkfParseError {
+    fn from(e: MoveError) -> Self {
+        Self::InvalidMove(e)
+    }
+}
+
+pub type JkfParseResult<T> = Result<T, JkfParseError>;
+
+/// JKF 形式での棋譜出力エラー。
+#[non_exhaustive]
+#[derive(Clone, Debug)]
+pub enum JkfWriteError {
+    /// 手順中の指し手を局面に適用できない。
+    InvalidMove(MoveError),
+}
+
+impl std::fmt::Display for JkfWriteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::InvalidMove(e) => write!(f, "invalid move: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for JkfWriteError {}
+
+impl From<MoveError> for JkfWriteError {
+    fn from(e: MoveError) -> Self {
+        Self::InvalidMove(e)
+    }
+}
+
+const fn invalid(description: &'static str) -> JkfParseError {
+    JkfParseError::InvalidStructure { description }
+}
+
+const PRESETS: [(Handicap, &str); 16] = [
+    (Handicap::Even, "HIRATE"),
+    (Handicap::Lance, "KY"),
+    (Handicap::RightLance, "KY_R"),
+    (Handicap::Bishop, "KA"),
+    (Handicap::Rook, "HI"),
+    (Handicap::RookLance, "HIKY"),
+    (Handicap::TwoPieces, "2"),
+    (Handicap::ThreePieces, "3"),
+    (Handicap::FourPieces, "4"),
+    (Handicap::FivePieces, "5"),
+    (Handicap::LeftFivePieces, "5_L"),
+    (Handicap::SixPieces, "6"),
+    (Handicap::LeftSevenPieces, "7_L"),
+    (Handicap::RightSevenPieces, "7_R"),
+    (Handicap::EightPieces, "8"),
+    (Handicap::TenPieces, "10"),
+];
+
+/// KI2 の修飾語と JKF の `relative` の文字の対応。
+const RELATIVES: [(char, char); 7] = [
+    ('左', 'L'),
+    ('直', 'C'),
+    ('右', 'R'),
+    ('上', 'U'),
+    ('寄', 'M'),
+    ('引', 'D'),
+    ('打', 'H'),
+];
+
+const fn side_to_color(side: Side) -> u64 {
+    match side {
+        SENTE => 0,
+        GOTE => 1,
+    }
+}
+
+fn color_to_side(json: &Json) -> JkfParseResult<Side> {
+    match json.as_u64() {
+        Some(0) => Ok(SENTE),
+        Some(1) => Ok(GOTE),
+        _ => Err(invalid("color must be 0 or 1")),
+    }
+}
+
+impl Record {
+    /// JKF (JSON Kifu Format) の棋譜文字列をパースする。
+    ///
+    /// `header` はヘッダ、`initial` は開始局面、`moves` の各要素は指し手として解釈する。
+    /// 指し手に `from` がない場合は `relative` などから移動元を特定する。
+    pub fn from_jkf(s: &str) -> JkfParseResult<Self> {
+        let json = Json::parse(s).map_err(|e| JkfParseError::InvalidJson {
+            offset: e.offset,
+            description: e.description,
+        })?;
+        if json.as_object().is_none() {
+            return Err(invalid("root must be an object"));
+        }
+
+        let pos = match json.get("initial") {
+            Some(initial) => read_initial(initial)?,
+            None => Position::startpos(),
+        };
+        let mut record = Record::new(pos);
+
+        if let Some(header) = json.get("header") {
+            let header = header
+                .as_object()
+                .ok_or(invalid("header must be an object"))?;
+            for (key, value) in header {
+                let value = value
+                    .as_str()
+                    .ok_or(invalid("header value must be a string"))?;
+                record.headers_mut().push((key.clone(), value.to_owned()));
+            }
+        }
+
+        let moves = match json.get("moves") {
+            Some(moves) => moves.as_array().ok_or(invalid("moves must be an array"))?,
+            None => &[],
+        };
+        // 先頭要素は開始局面に対するもので、指し手を持たない。
+        let moves = match moves.split_first() {
+            Some((first, rest))
+                if first.get("move").is_none() && first.get("special").is_none() =>
+            {
+                *record.comments_mut() = read_comments(first)?;
+                rest
+            }
+            _ => moves,
+        };
+
+        let entries = read_line(moves, record.position(), None)?;
+        *record.entries_mut() = entries;
+
+        Ok(record)
+    }
+
+    /// JKF (JSON Kifu Format) の棋譜文字列を返す。
+    ///
+    /// 開始局面が手合割に該当すれば `preset` で、そうでなければ `data` で表す。
+    pub fn to_jkf(&self) -> Result<String, JkfWriteError> {
+        let mut root = vec![];
+
+        root.push((
+            "header".to_owned(),
+            Json::Object(
+                self.headers()
+                    .iter()
+                    .map(|(k, v)| (k.clone(), Json::from(v.as_str())))
+                    .collect(),
+            ),
+        ));
+
+        root.push(("initial".to_owned(), write_initial(self.position())));
+
+        let mut first = vec![];
+        if !self.comments().is_empty() {
+            first.push(("comments".to_owned(), write_comments(self.comments())));
+        }
+        let mut moves = vec![Json::Object(first)];
+        moves.extend(write_line(self.entries(), self.position(), None)?);
+        root.push(("moves".to_owned(), Json::Array(moves)));
+
+        Ok(Json::Object(root).to_string())
+    }
+}
+
+impl Kifu {
+    /// JKF の棋譜文字列をパースし、本譜の手順を返す。
+    pub fn from_jkf(s: &str) -> JkfParseResult<Self> {
+        Record::from_jkf(s).map(|record| record.kifu())
+    }
+
+    /// JKF の棋譜文字列を返す。
+    pub fn to_jkf(&self) -> Result<String, JkfWriteError> {
+        Record::from(self.clone()).to_jkf()
+    }
+}
+
+fn read_initial(initial: &Json) -> JkfParseResult<Position> {
+    let preset = initial
+        .get("preset")
+        .and_then(Json::as_str)
+        .ok_or(invalid("initial.preset must be a string"))?;
+
+    if let Some(&(handicap, _)) = PRESETS.iter().find(|&&(_, name)| name == preset) {
+        return Ok(handicap.position());
+    }
+    if preset != "OTHER" {
+        return Err(invalid("unknown preset"));
+    }
+
+    let data = initial
+        .get("data")
+        .ok_or(invalid("initial.data is required for OTHER preset"))?;
+
+    let side_to_move = color_to_side(data.get("color").ok_or(invalid("data.color expected"))?)?;
+
+    let mut board = Board::empty();
+    let columns = data
+        .get("board")
+        .and_then(Json::as_array)
+        .filter(|columns| columns.len() == 9)
+        .ok_or(invalid("data.board must be a 9x9 array"))?;
+    for (x, column) in (1..).zip(columns) {
+        let cells = column
+            .as_array()
+            .filter(|cells| cells.len() == 9)
+            .ok_or(invalid("data.board must be a 9x9 array"))?;
+        for (y, cell) in (1..).zip(cells) {
+            if cell.as_object().is_some_and(|members| members.is_empty()) {
+                continue;
+            }
+            let side = color_to_side(cell.get("color").ok_or(invalid("cell color expected"))?)?;
+            let pk = cell
+                .get("kind")
+                .and_then(Json::as_str)
+                .and_then(PieceKind::from_csa)
+                .ok_or(invalid("invalid piece kind"))?;
+            let sq = Square::new(Col::from_num(x).unwrap(), Row::from_num(y).unwrap());
+            board[sq] = Some(Piece::new(side, pk));
+        }
+    }
+
+    let mut hands = Hands::empty();
+    let hands_json = data
+        .get("hands")
+        .and_then(Json::as_array)
+        .filter(|hands| hands.len() == 2)
+        .ok_or(invalid("data.hands must be an array of 2 objects"))?;
+    for (side, hand) in [SENTE, GOTE].into_iter().zip(hands_json) {
+        let hand = hand
+            .as_object()
+            .ok_or(invalid("data.hands must be an array of 2 objects"))?;
+        for (kind, count) in hand {
+            let hpk = PieceKind::from_csa(kind)
+                .and_then(|pk| HandPieceKind::try_from(pk).ok())
+                .ok_or(invalid("invalid hand piece kind"))?;
+            let count = count
+                .as_u64()
+                .and_then(|n| u8::try_from(n).ok())
+                .ok_or(invalid("invalid hand piece count"))?;
+            hands[side][hpk] = count;
+        }
+    }
+
+    Ok(Position::new(side_to_move, board, hands, PLY_1))
+}
+
+fn write_initial(pos: &Position) -> Json {
+    if let Some(handicap) = Handicap::from_position(pos) {
+        let name = PRESETS.iter().find(|&&(h, _)| h == handicap).unwrap().1;
+        return Json::Object(vec![("preset".to_owned(), Json::from(name))]);
+    }
+
+    let columns = (1..=9)
+        .map(|x| {
+            let cells = (1..=9)
+                .map(|y| {
+                    let sq = Square::new(Col::from_num(x).unwrap(), Row::from_num(y).unwrap());
+                    match pos.board()[sq] {
+                        Some(pc) => Json::Object(vec![
+                            ("color".to_owned(), Json::from(side_to_color(pc.side()))),
+                            ("kind".to_owned(), Json::from(pc.kind().to_csa())),
+                        ]),
+                        None => Json::Object(vec![]),
+                    }
+                })
+                .collect();
+            Json::Array(cells)
+        })
+        .collect();
+
+    let hands = [SENTE, GOTE]
+        .into_iter()
+        .map(|side| {
+            Json::Object(
+                HandPieceKind::all()
+                    .into_iter()
+                    .map(|hpk| {
+                        let kind = PieceKind::from(hpk).to_csa().to_owned();
+                        (kind, Json::from(u64::from(pos.hands()[side][hpk])))
+                    })
+                    .collect(),
+            )
+        })
+        .collect();
+
+    let data = Json::Object(vec![
+        (
+            "color".to_owned(),
+            Json::from(side_to_color(pos.side_to_move())),
+        ),
+        ("board".to_owned(), Json::Array(columns)),
+        ("hands".to_owned(), Json::Array(hands)),
+    ]);
+
+    Json::Object(vec![
+        ("preset".to_owned(), Json::from("OTHER")),
+        ("data".to_owned(), data),
+    ])
+}
+
+fn read_comments(item: &Json) -> JkfParseResult<Vec<String>> {
+    let Some(comments) = item.get("comments") else {
+        return Ok(vec![]);
+    };
+
+    comments
+        .as_array()
+        .ok_or(invalid("comments must be an array"))?
+        .iter()
+        .map(|c| {
+            c.as_str()
+                .map(str::to_owned)
+                .ok_or(invalid("comment must be a string"))
+        })
+        .collect()
+}
+
+fn write_comments(comments: &[String]) -> Json {
+    Json::Array(comments.iter().map(|c| Json::from(c.as_str())).collect())
+}
+
+/// 手順を読む。`pos` は手順の開始局面、`last_dst` は直前の指し手の移動先。
+fn read_line(
+    items: &[Json],
+    pos: &Position,
+    mut last_dst: Option<Square>,
+) -> JkfParseResult<Vec<RecordEntry>> {
+    let mut pos = pos.clone();
+    let mut entries = Vec::with_capacity(items.len());
+
+    for item in items {
+        if matches!(
+            entries.last().map(RecordEntry::mv),
+            Some(RecordMove::Special(_))
+        ) {
+            return Err(invalid("move after special move"));
+        }
+
+        let mv = if let Some(mv) = item.get("move") {
+            RecordMove::Move(read_move(mv, &pos, last_dst)?)
+        } else if let Some(special) = item.get("special") {
+            let special = special
+                .as_str()
+                .ok_or(invalid("special must be a string"))?;
+            RecordMove::Special(read_special(special, pos.side_to_move())?)
+        } else {
+            return Err(invalid("move or special expected"));
+        };
+
+        let mut entry = RecordEntry::new(mv);
+        if let Some(time) = item.get("time") {
+            entry.set_time(Some(read_time(time)?));
+        }
+        *entry.comments_mut() = read_comments(item)?;
+
+        if let Some(forks) = item.get("forks") {
+            let forks = forks.as_array().ok_or(invalid("forks must be an array"))?;
+            for fork in forks {
+                let fork = fork.as_array().ok_or(invalid("fork must be an array"))?;
+                let variation = read_line(fork, &pos, last_dst)?;
+                entry.variations_mut().push(variation);
+            }
+        }
+
+        if let RecordMove::Move(mv) = mv {
+            pos.do_move(mv)?;
+            last_dst = Some(mv.dst());
+        }
+        entries.push(entry);
+    }
+
+    Ok(entries)
+}
+
+fn write_line(
+    entries: &[RecordEntry],
+    pos: &Position,
+    mut last_dst: Option<Square>,
+) -> Result<Vec<Json>, MoveError> {
+    let mut pos = pos.clone();
+    let mut items = Vec::with_capacity(entries.len());
+
+    for entry in entries {
+        let mut members = vec![];
+
+        match entry.mv() {
+            RecordMove::Move(mv) => {
+                members.push(("move".to_owned(), write_move(mv, &pos, last_dst)?));
+            }
+            RecordMove::Special(special) => {
+                let name = write_special(special, pos.side_to_move());
+                members.push(("special".to_owned(), Json::from(name)));
+            }
+        }
+        if let Some(time) = entry.time() {
+            members.push(("time".to_owned(), write_time(time)));
+        }
+        if !entry.comments().is_empty() {
+            members.push(("comments".to_owned(), write_comments(entry.comments())));
+        }
+        if !entry.variations().is_empty() {
+            let forks = entry
+                .variations()
+                .iter()
+                .map(|variation| write_line(variation, &pos, last_dst).map(Json::Array))
+                .collect::<Result<_, _>>()?;
+            members.push(("forks".to_owned(), Json::Array(forks)));
+        }
+        items.push(Json::Object(members));
+
+        if let RecordMove::Move(mv) = entry.mv() {
+            pos.do_move(mv)?;
+            last_dst = Some(mv.dst());
+        }
+    }
+
+    Ok(items)
+}
+
+fn read_square(json: &Json) -> JkfParseResult<Square> {
+    let coord = |key| {
+        json.get(key)
+            .and_then(Json::as_u64)
+            .and_then(|n| u8::try_from(n).ok())
+    };
+    let col = coord("x").and_then(Col::from_num);
+    let row = coord("y").and_then(Row::from_num);
+
+    match (col, row) {
+        (Some(col), Some(row)) => Ok(Square::new(col, row)),
+        _ => Err(invalid("invalid square")),
+    }
+}
+
+fn write_square(sq: Square) -> Json {
+    Json::Object(vec![
+        ("x".to_owned(), Json::from(u64::from(sq.col().to_num()))),
+        ("y".to_owned(), Json::from(u64::from(sq.row().to_num()))),
+    ])
+}
+
+fn read_move(json: &Json, pos: &Position, last_dst: Option<Square>) -> JkfParseResult<Move> {
+    let pk = json
+        .get("piece")
+        .and_then(Json::as_str)
+        .and_then(PieceKind::from_csa)
+        .ok_or(invalid("invalid move piece"))?;
+    let promote = match json.get("promote") {
+        Some(promote) => Some(
+            promote
+                .as_bool()
+                .ok_or(invalid("promote must be a boolean"))?,
+        ),
+        None => None,
+    };
+    let same = json.get("same").and_then(Json::as_bool) == Some(true);
+
+    let dst = match json.get("to") {
+        Some(to) => read_square(to)?,
+        None if same => last_dst.ok_or(invalid("no previous move for same"))?,
+        None => return Err(invalid("move.to expected")),
+    };
+
+    if let Some(from) = json.get("from") {
+        let src = read_square(from)?;
+        if pos.board()[src].map(|pc| pc.kind()) != Some(pk) {
+            return Err(invalid("move piece does not match board"));
+        }
+        return Ok(Move::walk(src, dst, promote == Some(true)));
+    }
+
+    // 移動元がなければ KI2 表記を組み立てて局面から特定する。
+    let mut ki2 = String::new();
+    ki2.push(col_to_zenkaku(dst.col()));
+    ki2.push(row_to_kanji(dst.row()));
+    ki2.push_str(piece_kind_to_name(pk));
+    if let Some(relative) = json.get("relative") {
+        let relative = relative
+            .as_str()
+            .ok_or(invalid("relative must be a string"))?;
+        for c in relative.chars() {
+            let &(m, _) = RELATIVES
+                .iter()
+                .find(|&&(_, r)| r == c)
+                .ok_or(invalid("invalid relative"))?;
+            ki2.push(m);
+        }
+    }
+    match promote {
+        Some(true) => ki2.push('成'),
+        Some(false) => ki2.push_str("不成"),
+        None => {}
+    }
+
+    let (_, mv) = parse_ki2_move(&ki2, pos, last_dst).map_err(|e| invalid(e.description()))?;
+    Ok(mv)
+}
+
+fn write_move(mv: Move, pos: &Position, last_dst: Option<Square>) -> Result<Json, MoveError> {
+    let us = pos.side_to_move();
+    let mut members = vec![];
+
+    let pk = match mv {
+        Move::Walk(walk) => {
+            let pc = pos.board()[walk.src()]
+                .filter(|pc| pc.side() == us)
+                .ok_or(MoveError::NoOwnPieceAtSrc)?;
+            members.push(("from".to_owned(), write_square(walk.src())));
+            pc.kind()
+        }
+        Move::Drop(drop) => drop.piece_kind().into(),
+    };
+    members.push(("to".to_owned(), write_square(mv.dst())));
+    members.push(("piece".to_owned(), Json::from(pk.to_csa())));
+    members.push(("color".to_owned(), Json::from(side_to_color(us))));
+    if last_dst == Some(mv.dst()) {
+        members.push(("same".to_owned(), Json::Bool(true)));
+    }
+    if let Move::Walk(walk) = mv {
+        if walk.is_promotion() || can_promote(us, pk, walk) {
+            members.push(("promote".to_owned(), Json::Bool(walk.is_promotion())));
+        }
+        if let Some(captured) = pos.board()[walk.dst()] {
+            members.push(("capture".to_owned(), Json::from(captured.kind().to_csa())));
+        }
+    }
+    let relative: String = ki2_modifiers(pos, pk, mv)
+        .chars()
+        .filter_map(|m| RELATIVES.iter().find(|&&(k, _)| k == m).map(|&(_, r)| r))
+        .collect();
+    if !relative.is_empty() {
+        members.push(("relative".to_owned(), Json::from(relative.as_str())));
+    }
+
+    Ok(Json::Object(members))
+}
+
+/// `side_to_move` は特殊な指し手の時点での手番。
+fn read_special(name: &str, side_to_move: Side) -> JkfParseResult<SpecialMove> {
+    let special = match name {
+        "TORYO" => SpecialMove::Resign,
+        "CHUDAN" => SpecialMove::Interrupt,
+        "SENNICHITE" => SpecialMove::Repetition,
+        "TIME_UP" => SpecialMove::TimeUp,
+        "ILLEGAL_MOVE" => SpecialMove::IllegalWin,
+        "+ILLEGAL_ACTION" if side_to_move == SENTE => SpecialMove::IllegalLose,
+        "+ILLEGAL_ACTION" => SpecialMove::IllegalWin,
+        "-ILLEGAL_ACTION" if side_to_move == GOTE => SpecialMove::IllegalLose,
+        "-ILLEGAL_ACTION" => SpecialMove::IllegalWin,
+        "JISHOGI" => SpecialMove::Impasse,
+        "KACHI" => SpecialMove::DeclareWin,
+        "HIKIWAKE" => SpecialMove::Draw,
+        "MATTA" => SpecialMove::Matta,
+        "TSUMI" => SpecialMove::Mate,
+        "FUZUMI" => SpecialMove::NoMate,
+        _ => return Err(invalid("unsupported special")),
+    };
+
+    Ok(special)
+}
+
+fn write_special(special: SpecialMove, side_to_move: Side) -> &'static str {
+    match special {
+        SpecialMove::Resign => "TORYO",
+        SpecialMove::Interrupt => "CHUDAN",
+        SpecialMove::Repetition => "SENNICHITE",
+        SpecialMove::TimeUp => "TIME_UP",
+        SpecialMove::IllegalWin => "ILLEGAL_MOVE",
+        SpecialMove::IllegalLose => match side_to_move {
+            SENTE => "+ILLEGAL_ACTION",
+            GOTE => "-ILLEGAL_ACTION",
+        },
+        SpecialMove::Impasse => "JISHOGI",
+        SpecialMove::DeclareWin => "KACHI",
+        SpecialMove::Draw => "HIKIWAKE",
+        SpecialMove::Matta => "MATTA",
+        SpecialMove::Mate => "TSUMI",
+        SpecialMove::NoMate => "FUZUMI",
+    }
+}
+
+fn read_time(json: &Json) -> JkfParseResult<MoveTime> {
+    let secs = |key| -> JkfParseResult<Duration> {
+        let t = json
+            .get(key)
+            .ok_or(invalid("time.now and time.total expected"))?;
+        let field = |name| match t.get(name) {
+            Some(n) => n.as_u64().ok_or(invalid("invalid time")),
+            None => Ok(0),
+        };
+        let (h, m, s) = (field("h")?, field("m")?, field("s")?);
+        let secs = h
+            .checked_mul(3600)
+            .and_then(|x| x.checked_add(m.checked_mul(60)?))
+            .and_then(|x| x.checked_add(s))
+            .ok_or(invalid("invalid time"))?;
+        Ok(Duration::from_secs(secs))
+    };
+
+    Ok(MoveTime::new(secs("now")?, secs("total")?))
+}
+
+fn write_time(time: MoveTime) -> Json {
+    let now = time.elapsed().as_secs();
+    let total = time.total().as_secs();
+
+    Json::Object(vec![
+        (
+            "now".to_owned(),
+            Json::Object(vec![
+                ("m".to_owned(), Json::from(now / 60)),
+                ("s".to_owned(), Json::from(now % 60)),
+            ]),
+        ),
+        (
+            "total".to_owned(),
+            Json::Object(vec![
+                ("h".to_owned(), Json::from(total / 3600)),
+                ("m".to_owned(), Json::from(total / 60 % 60)),
+                ("s".to_owned(), Json::from(total % 60)),
+            ]),
+        ),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr as _;
+
+    use super::*;
+
+    const SAMPLE: &str = r#"{
+  "header": {"先手": "先手太郎", "後手": "後手花子"},
+  "initial": {"preset": "HIRATE"},
+  "moves": [
+    {"comments": ["開始"]},
+    {"move": {"from": {"x": 7, "y": 7}, "to": {"x": 7, "y": 6}, "color": 0, "piece": "FU"},
+     "time": {"now": {"m": 0, "s": 1}, "total": {"h": 0, "m": 0, "s": 1}}},
+    {"move": {"from": {"x": 3, "y": 3}, "to": {"x": 3, "y": 4}, "color": 1, "piece": "FU"}},
+    {"move": {"from": {"x": 8, "y": 8}, "to": {"x": 2, "y": 2}, "color": 0, "piece": "KA",
+              "promote": true, "capture": "KA"},
+     "comments": ["角交換"],
+     "forks": [[
+       {"move": {"to": {"x": 6, "y": 6}, "color": 0, "piece": "FU"}},
+       {"move": {"to": {"x": 5, "y": 2}, "color": 1, "piece": "KI", "relative": "R"}}
+     ]]},
+    {"move": {"from": {"x": 3, "y": 1}, "same": true, "color": 1, "piece": "GI", "capture": "UM"}},
+    {"move": {"to": {"x": 4, "y": 5}, "color": 0, "piece": "KA"}},
+    {"special": "TORYO"}
+  ]
+}"#;
+
+    #[test]
+    fn test_jkf_parse() {
+        let record = Record::from_jkf(SAMPLE).unwrap();
+
+        assert_eq!(record.header("先手"), Some("先手太郎"));
+        assert_eq!(record.comments(), ["開始"]);
+        assert_eq!(
+            record.kifu(),
+            Kifu::from_str("position startpos moves 7g7f 3c3d 8h2b+ 3a2b B*4e").unwrap()
+        );
+        assert_eq!(record.special(), Some(SpecialMove::Resign));
+
+        let entries = record.entries();
+        assert_eq!(
+            entries[0].time(),
+            Some(MoveTime::new(
+                Duration::from_secs(1),
+                Duration::from_secs(1)
+            ))
+        );
+        assert_eq!(entries[2].comments(), ["角交換"]);
+        let fork = &entries[2].variations()[0];
+        assert_eq!(
+            fork[0].mv(),
+            RecordMove::Move(Move::from_str("6g6f").unwrap())
+        );
+        assert_eq!(
+            fork[1].mv(),
+            RecordMove::Move(Move::from_str("6a5b").unwrap())
+        );
+    }
+
+    #[test]
+    fn test_jkf_parse_error() {
+        assert!(matches!(
+            Record::from_jkf("{"),
+            Err(JkfParseError::InvalidJson { .. })
+        ));
+        assert!(matches!(
+            Record::from_jkf(&"[".repeat(200_000)),
+            Err(JkfParseError::InvalidJson {
+                description: "nesting too deep",
+                ..
+            })
+        ));
+        assert!(matches!(
+            Record::from_jkf(r#"{"initial": {"preset": "XX"}}"#),
+            Err(JkfParseError::InvalidStructure { .. })
+        ));
+        assert!(matches!(
+            Record::from_jkf(
+                r#"{"moves": [{}, {"move": {"to": {"x": 5, "y": 5}, "color": 0, "piece": "KA"}}]}"#
+            ),
+            Err(JkfParseError::InvalidMove(MoveError::NotInHand))
+        ));
+
+        // 消費時間が大きすぎる。
+        assert!(matches!(
+            Record::from_jkf(
+                r#"{"moves": [{}, {"move": {"from": {"x": 7, "y": 7}, "to": {"x": 7, "y": 6}, "piece": "FU", "color": 0}, "time": {"now": {"h": 1e19}, "total": {"s": 1}}}]}"#
+            ),
+            Err(JkfParseError::InvalidStructure {
+                description: "invalid time"
+            })
+        ));
+    }
+
+    #[test]
+    fn test_jkf_fmt() {
+        let record = Record::from_jkf(SAMPLE).unwrap();
+        let jkf = record.to_jkf().unwrap();
+        assert_eq!(Record::from_jkf(&jkf).unwrap(), record);
+
+        let json = Json::parse(&jkf).unwrap();
+        let moves = json.get("moves").unwrap().as_array().unwrap();
+        assert_eq!(
+            moves[3].get("move").unwrap().to_string(),
+            r#"{"from":{"x":8,"y":8},"to":{"x":2,"y":2},"piece":"KA","color":0,"promote":true,"capture":"KA"}"#
+        );
+        assert_eq!(
+            moves[3].get("forks").unwrap().as_array().unwrap()[0]
+                .as_array()
+                .unwrap()[1]
+                .get("move")
+                .unwrap()
+                .get("relative"),
+            Some(&Json::from("R"))
+        );
+        assert_eq!(moves[6].to_string(), r#"{"special":"TORYO"}"#);
+
+        // 手合割に該当しない開始局面は data で表す。
+        let kifu =
+            Kifu::from_str("position sfen 4k4/9/9/9/9/9/9/9/4K4 b G2p 1 moves G*5b").unwrap();
+        let jkf = kifu.to_jkf().unwrap();
+        assert!(jkf.contains(r#""preset":"OTHER""#));
+        assert_eq!(Kifu::from_jkf(&jkf).unwrap(), kifu);
+
+        // 手順中の指し手を適用できない。
+        let kifu = Kifu::from_str("position startpos moves B*5e").unwrap();
+        assert!(matches!(
+            kifu.to_jkf(),
+            Err(JkfWriteError::InvalidMove(MoveError::NotInHand))
+        ));
+    }
+}
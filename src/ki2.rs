@@ -0,0 +1,280 @@
+use crate::japanese::*;
+use crate::kif::*;
+use crate::kifu::*;
+use crate::position::*;
+use crate::record::*;
+use crate::side::*;
+use crate::square::*;
+
+/// 指し手の手番を表す記号。
+pub(crate) const KI2_SIDE_MARKS: [char; 4] = ['▲', '△', '☗', '☖'];
+
+/// KI2 の 1 手分の表示幅。
+const MOVE_WIDTH: usize = 12;
+
+/// KI2 の 1 行に並べる手数。
+const MOVES_PER_LINE: usize = 6;
+
+const fn side_mark(side: Side) -> char {
+    match side {
+        SENTE => '▲',
+        GOTE => '△',
+    }
+}
+
+/// 指し手の並んだ行 (`▲７六歩    △３四歩` など) を手番と指し手文字列の組に分割する。
+pub(crate) fn split_ki2_moves(line: &str) -> Vec<(Side, &str)> {
+    let mut moves = vec![];
+    let mut cur: Option<(Side, usize)> = None;
+
+    for (i, c) in line.char_indices() {
+        let side = match c {
+            '▲' | '☗' => SENTE,
+            '△' | '☖' => GOTE,
+            _ => continue,
+        };
+        if let Some((side, start)) = cur {
+            moves.push((side, trim_kif(&line[start..i])));
+        }
+        cur = Some((side, i + c.len_utf8()));
+    }
+    if let Some((side, start)) = cur {
+        moves.push((side, trim_kif(&line[start..])));
+    }
+
+    moves
+}
+
+/// 終局の要約行 (`まで64手で先手の勝ち` など) から特殊な指し手を読み取る。
+pub(crate) fn parse_summary(line: &str) -> Option<SpecialMove> {
+    let rest = line.strip_prefix("まで")?;
+    let rest = rest.trim_start_matches(|c: char| c.is_ascii_digit());
+    let rest = rest
+        .strip_prefix("手で")
+        .or_else(|| rest.strip_prefix('手'))?;
+
+    const TABLE: [(&str, SpecialMove); 11] = [
+        ("時間切れにより", SpecialMove::TimeUp),
+        ("反則勝ち", SpecialMove::IllegalWin),
+        ("反則負け", SpecialMove::IllegalLose),
+        ("入玉勝ち", SpecialMove::DeclareWin),
+        ("勝ち", SpecialMove::Resign),
+        ("千日手", SpecialMove::Repetition),
+        ("持将棋", SpecialMove::Impasse),
+        ("中断", SpecialMove::Interrupt),
+        ("不詰", SpecialMove::NoMate),
+        ("引き分け", SpecialMove::Draw),
+        ("待った", SpecialMove::Matta),
+    ];
+    if rest.ends_with("詰み") || rest == "詰" {
+        return Some(SpecialMove::Mate);
+    }
+
+    TABLE
+        .iter()
+        .find(|(word, _)| rest.contains(word))
+        .map(|&(_, special)| special)
+}
+
+impl Record {
+    /// KI2 形式の棋譜文字列をパースする。
+    ///
+    /// 指し手は各局面に照らして解決する。終局は `まで` で始まる要約行から読み取る。
+    /// その他の扱いは `Record::from_kif` と同様。
+    pub fn from_ki2(s: &str) -> KifParseResult<Self> {
+        read_kif_record(s, KifFormat::Ki2)
+    }
+
+    /// KI2 形式の棋譜文字列を返す。
+    ///
+    /// 移動元を特定するための修飾語(右, 左, 直, 上, 引, 寄, 打)は必要な場合のみ付ける。
+    /// 消費時間は出力されない。
+    pub fn to_ki2(&self) -> Result<String, KifWriteError> {
+        let mut s = String::new();
+        write_kif_header(&mut s, self);
+        s.push('\n');
+
+        let mut pos = self.position().clone();
+        write_ki2_line(&mut s, &mut pos, None, 1, self.entries())?;
+
+        Ok(s)
+    }
+}
+
+impl Kifu {
+    /// KI2 形式の棋譜文字列をパースし、本譜の手順を返す。
+    ///
+    /// コメント、変化などは捨てられる。これらが必要なら `Record::from_ki2` を使う。
+    pub fn from_ki2(s: &str) -> KifParseResult<Self> {
+        Record::from_ki2(s).map(|record| record.kifu())
+    }
+
+    /// KI2 形式の棋譜文字列を返す。
+    pub fn to_ki2(&self) -> Result<String, KifWriteError> {
+        Record::from(self.clone()).to_ki2()
+    }
+}
+
+/// 手順 `line` を出力し、続けてその変化を出力する。`pos` は手順の末尾の局面になる。
+fn write_ki2_line(
+    s: &mut String,
+    pos: &mut Position,
+    mut last_dst: Option<Square>,
+    first_ply: u32,
+    line: &[RecordEntry],
+) -> Result<(), KifWriteError> {
+    let mut history = Vec::with_capacity(line.len());
+    let mut buf = String::new();
+    let mut n_buf = 0;
+
+    let flush = |s: &mut String, buf: &mut String, n_buf: &mut usize| {
+        if *n_buf > 0 {
+            s.push_str(buf.trim_end());
+            s.push('\n');
+            buf.clear();
+            *n_buf = 0;
+        }
+    };
+
+    for (i, entry) in line.iter().enumerate() {
+        history.push((pos.clone(), last_dst));
+
+        match entry.mv() {
+            RecordMove::Move(mv) => {
+                let mut token = String::from(side_mark(pos.side_to_move()));
+                token.push_str(&fmt_ki2_move(pos, mv, last_dst)?);
+                pos.do_move(mv)?;
+                last_dst = Some(mv.dst());
+
+                let pad = MOVE_WIDTH.saturating_sub(display_width(&token)).max(1);
+                buf.push_str(&token);
+                buf.extend(std::iter::repeat_n(' ', pad));
+                n_buf += 1;
+                if n_buf == MOVES_PER_LINE || !entry.comments().is_empty() {
+                    flush(s, &mut buf, &mut n_buf);
+                }
+            }
+            RecordMove::Special(special) => {
+                flush(s, &mut buf, &mut n_buf);
+                let n_moves = first_ply as usize - 1 + i;
+                s.push_str(&fmt_summary(special, n_moves, pos.side_to_move()));
+                s.push('\n');
+            }
+        }
+
+        for comment in entry.comments() {
+            s.push('*');
+            s.push_str(comment);
+            s.push('\n');
+        }
+    }
+    flush(s, &mut buf, &mut n_buf);
+
+    // 後の手の変化から出力する(パース時は直近の手順から分岐元を探すため)。
+    for (i, entry) in line.iter().enumerate().rev() {
+        for variation in entry.variations() {
+            let ply = first_ply + i as u32;
+            let (mut pos, last_dst) = history[i].clone();
+            s.push_str(&format!("\n変化：{ply}手\n"));
+            write_ki2_line(s, &mut pos, last_dst, ply, variation)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr as _;
+
+    use crate::move_::*;
+
+    use super::*;
+
+    const SAMPLE: &str = "\
+手合割：平手
+先手：先手太郎
+後手：後手花子
+
+▲７六歩    △３四歩    ▲２二角成  △同　銀    ▲４五角    △５二金右
+*金が上がる
+▲５六歩
+まで7手で中断
+
+変化：6手
+△４二金    ▲５八金右
+";
+
+    #[test]
+    fn test_ki2_parse() {
+        let record = Record::from_ki2(SAMPLE).unwrap();
+
+        assert_eq!(record.header("後手"), Some("後手花子"));
+        assert_eq!(
+            record.kifu(),
+            Kifu::from_str("position startpos moves 7g7f 3c3d 8h2b+ 3a2b B*4e 6a5b 5g5f").unwrap()
+        );
+        assert_eq!(record.special(), Some(SpecialMove::Interrupt));
+
+        let entries = record.entries();
+        assert_eq!(entries[5].comments(), ["金が上がる"]);
+        let variation = &entries[5].variations()[0];
+        assert_eq!(
+            variation[0].mv(),
+            RecordMove::Move(Move::from_str("4a4b").unwrap())
+        );
+        assert_eq!(
+            variation[1].mv(),
+            RecordMove::Move(Move::from_str("4i5h").unwrap())
+        );
+    }
+
+    #[test]
+    fn test_ki2_parse_error() {
+        assert!(matches!(
+            Record::from_ki2("▲７六歩    ▲３四歩\n"),
+            Err(KifParseError::InvalidLine { line: 1, .. })
+        ));
+        assert!(matches!(
+            Record::from_ki2("▲７六歩    △３四歩    ▲５八金\n"),
+            Err(KifParseError::InvalidLine {
+                line: 1,
+                description: "ambiguous move"
+            })
+        ));
+        assert!(matches!(
+            Record::from_ki2("▲５五角\n"),
+            Err(KifParseError::InvalidMove { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_ki2_fmt() {
+        let record = Record::from_ki2(SAMPLE).unwrap();
+        assert_eq!(record.to_ki2().unwrap(), SAMPLE);
+
+        let kifu = Kifu::from_str("position startpos moves 7g7f 3c3d 8h2b+ 3a2b B*4e").unwrap();
+        assert_eq!(
+            kifu.to_ki2().unwrap(),
+            "手合割：平手\n\n▲７六歩    △３四歩    ▲２二角成  △同　銀    ▲４五角\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_summary() {
+        assert_eq!(
+            parse_summary("まで64手で先手の勝ち"),
+            Some(SpecialMove::Resign)
+        );
+        assert_eq!(
+            parse_summary("まで64手で時間切れにより後手の勝ち"),
+            Some(SpecialMove::TimeUp)
+        );
+        assert_eq!(
+            parse_summary("まで100手で千日手"),
+            Some(SpecialMove::Repetition)
+        );
+        assert_eq!(parse_summary("まで3手で詰み"), Some(SpecialMove::Mate));
+        assert_eq!(parse_summary("まで"), None);
+    }
+}
@@ -0,0 +1,898 @@
+use std::fmt::Write as _;
+use std::time::Duration;
+
+use crate::bod::*;
+use crate::hand::*;
+use crate::handicap::*;
+use crate::japanese::*;
+use crate::ki2::*;
+use crate::kifu::*;
+use crate::move_::*;
+use crate::position::*;
+use crate::record::*;
+use crate::side::*;
+use crate::square::*;
+
+/// KIF/KI2 形式の棋譜パースエラー。
+#[non_exhaustive]
+#[derive(Clone, Debug)]
+pub enum KifParseError {
+    /// `line` 行目(1 始まり)のパースに失敗した。
+    InvalidLine {
+        line: usize,
+        description: &'static str,
+    },
+
+    /// `line` 行目(1 始まり)の指し手を局面に適用できない。
+    InvalidMove { line: usize, error: MoveError },
+}
+
+impl std::fmt::Display for KifParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::InvalidLine { line, description } => {
+                write!(f, "invalid line {line}: {description}")
+            }
+            Self::InvalidMove { line, error } => write!(f, "invalid move at line {line}: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for KifParseError {}
+
+pub type KifParseResult<T> = Result<T, KifParseError>;
+
+/// KIF/KI2 形式での棋譜出力エラー。
+#[non_exhaustive]
+#[derive(Clone, Debug)]
+pub enum KifWriteError {
+    /// 手順中の指し手を局面に適用できない。
+    InvalidMove(MoveError),
+}
+
+impl std::fmt::Display for KifWriteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::InvalidMove(e) => write!(f, "invalid move: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for KifWriteError {}
+
+impl From<MoveError> for KifWriteError {
+    fn from(e: MoveError) -> Self {
+        Self::InvalidMove(e)
+    }
+}
+
+const MOVES_HEADER: &str = "手数----指手---------消費時間--";
+
+/// KIF の指し手欄の表示幅。消費時間はこの後に続く。
+const MOVE_COLUMN_WIDTH: usize = 13;
+
+const HANDICAP_NAMES: [(Handicap, &str); 16] = [
+    (Handicap::Even, "平手"),
+    (Handicap::Lance, "香落ち"),
+    (Handicap::RightLance, "右香落ち"),
+    (Handicap::Bishop, "角落ち"),
+    (Handicap::Rook, "飛車落ち"),
+    (Handicap::RookLance, "飛香落ち"),
+    (Handicap::TwoPieces, "二枚落ち"),
+    (Handicap::ThreePieces, "三枚落ち"),
+    (Handicap::FourPieces, "四枚落ち"),
+    (Handicap::FivePieces, "五枚落ち"),
+    (Handicap::LeftFivePieces, "左五枚落ち"),
+    (Handicap::SixPieces, "六枚落ち"),
+    (Handicap::LeftSevenPieces, "左七枚落ち"),
+    (Handicap::RightSevenPieces, "右七枚落ち"),
+    (Handicap::EightPieces, "八枚落ち"),
+    (Handicap::TenPieces, "十枚落ち"),
+];
+
+const SPECIAL_MOVE_NAMES: [(SpecialMove, &str); 12] = [
+    (SpecialMove::Interrupt, "中断"),
+    (SpecialMove::Resign, "投了"),
+    (SpecialMove::Impasse, "持将棋"),
+    (SpecialMove::Repetition, "千日手"),
+    (SpecialMove::Mate, "詰み"),
+    (SpecialMove::NoMate, "不詰"),
+    (SpecialMove::TimeUp, "切れ負け"),
+    (SpecialMove::IllegalWin, "反則勝ち"),
+    (SpecialMove::IllegalLose, "反則負け"),
+    (SpecialMove::DeclareWin, "入玉勝ち"),
+    (SpecialMove::Draw, "引き分け"),
+    (SpecialMove::Matta, "待った"),
+];
+
+pub(crate) fn handicap_from_name(name: &str) -> Option<Handicap> {
+    HANDICAP_NAMES
+        .iter()
+        .find(|&&(_, s)| s == name)
+        .map(|&(handicap, _)| handicap)
+}
+
+pub(crate) fn handicap_to_name(handicap: Handicap) -> &'static str {
+    HANDICAP_NAMES
+        .iter()
+        .find(|&&(h, _)| h == handicap)
+        .map(|&(_, s)| s)
+        .unwrap()
+}
+
+pub(crate) fn special_move_to_name(special: SpecialMove) -> &'static str {
+    SPECIAL_MOVE_NAMES
+        .iter()
+        .find(|&&(sp, _)| sp == special)
+        .map(|&(_, s)| s)
+        .unwrap()
+}
+
+pub(crate) fn parse_special_move(s: &str) -> Option<(&str, SpecialMove)> {
+    SPECIAL_MOVE_NAMES
+        .iter()
+        .find_map(|&(special, name)| s.strip_prefix(name).map(|remain| (remain, special)))
+}
+
+/// KIF 行頭/行末の空白(全角空白を含む)を除く。
+pub(crate) fn trim_kif(s: &str) -> &str {
+    s.trim_matches(|c: char| c.is_ascii_whitespace() || c == '　')
+}
+
+/// ヘッダ行 (`キー：値`) をパースする。
+pub(crate) fn parse_header_line(line: &str) -> Option<(&str, &str)> {
+    let (key, value) = line.split_once('：')?;
+    (!key.is_empty()).then(|| (trim_kif(key), trim_kif(value)))
+}
+
+/// 棋譜をパースする際の現在位置。
+///
+/// `path` は本譜から現在の手順に至る (手のインデックス, 変化のインデックス) の列。
+/// `starts` は本譜および各変化の最初の手の手数。
+struct Cursor {
+    path: Vec<(usize, usize)>,
+    starts: Vec<u32>,
+}
+
+impl Cursor {
+    fn new(first_ply: u32) -> Self {
+        Self {
+            path: vec![],
+            starts: vec![first_ply],
+        }
+    }
+
+    fn start(&self) -> u32 {
+        *self.starts.last().unwrap()
+    }
+
+    fn line_mut<'a>(&self, entries: &'a mut Vec<RecordEntry>) -> &'a mut Vec<RecordEntry> {
+        let mut line = entries;
+        for &(i, j) in &self.path {
+            line = &mut line[i].variations_mut()[j];
+        }
+        line
+    }
+
+    /// 現在の手順を再生し、末尾の局面と直前の指し手の移動先を返す。
+    fn replay(
+        &self,
+        pos: &Position,
+        entries: &[RecordEntry],
+    ) -> Result<(Position, Option<Square>), MoveError> {
+        let mut pos = pos.clone();
+        let mut last_dst = None;
+
+        let mut line = entries;
+        let mut apply = |line: &[RecordEntry]| -> Result<(), MoveError> {
+            for entry in line {
+                if let RecordMove::Move(mv) = entry.mv() {
+                    pos.do_move(mv)?;
+                    last_dst = Some(mv.dst());
+                }
+            }
+            Ok(())
+        };
+        for &(i, j) in &self.path {
+            apply(&line[..i])?;
+            line = &line[i].variations()[j];
+        }
+        apply(line)?;
+
+        Ok((pos, last_dst))
+    }
+
+    /// `ply` 手目の変化を開始する。
+    fn branch(&mut self, entries: &mut Vec<RecordEntry>, ply: u32) -> Option<()> {
+        while self.start() >= ply && !self.path.is_empty() {
+            self.path.pop();
+            self.starts.pop();
+        }
+
+        let idx = usize::try_from(ply.checked_sub(self.start())?).ok()?;
+        let line = self.line_mut(entries);
+        let entry = line.get_mut(idx)?;
+        entry.variations_mut().push(vec![]);
+        let var_idx = entry.variations().len() - 1;
+
+        self.path.push((idx, var_idx));
+        self.starts.push(ply);
+
+        Some(())
+    }
+}
+
+/// KIF 系の棋譜形式。
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum KifFormat {
+    Kif,
+    Ki2,
+}
+
+/// KIF/KI2 形式の棋譜の読み取り状態。
+struct KifReader {
+    record: Record,
+    cursor: Cursor,
+    pos: Position,
+    last_dst: Option<Square>,
+    bod: Option<BodReader>,
+}
+
+impl KifReader {
+    fn new() -> Self {
+        let record = Record::new(Position::startpos());
+        let pos = record.position().clone();
+        Self {
+            record,
+            cursor: Cursor::new(1),
+            pos,
+            last_dst: None,
+            bod: None,
+        }
+    }
+
+    /// 読み取り途中の局面図があれば、それを開始局面とする。
+    fn finish_bod(&mut self, line: usize) -> KifParseResult<()> {
+        let Some(bod) = self.bod.take() else {
+            return Ok(());
+        };
+        let pos = bod
+            .finish()
+            .map_err(|description| KifParseError::InvalidLine { line, description })?;
+        if let Some(pos) = pos {
+            self.record.set_position(pos);
+            self.pos = self.record.position().clone();
+        }
+
+        Ok(())
+    }
+
+    /// 現在の手順に次に追加される手の手数を返す。
+    fn next_ply(&mut self) -> u32 {
+        self.cursor.start() + self.cursor.line_mut(self.record.entries_mut()).len() as u32
+    }
+
+    /// 現在の手順に 1 手追加する。
+    fn push(&mut self, entry: RecordEntry, line: usize) -> KifParseResult<()> {
+        let entries = self.cursor.line_mut(self.record.entries_mut());
+        if matches!(
+            entries.last().map(RecordEntry::mv),
+            Some(RecordMove::Special(_))
+        ) {
+            return Err(KifParseError::InvalidLine {
+                line,
+                description: "move after special move",
+            });
+        }
+
+        if let RecordMove::Move(mv) = entry.mv() {
+            self.pos
+                .do_move(mv)
+                .map_err(|error| KifParseError::InvalidMove { line, error })?;
+            self.last_dst = Some(mv.dst());
+        }
+        entries.push(entry);
+
+        Ok(())
+    }
+
+    fn read_line(&mut self, format: KifFormat, line: &str, line_no: usize) -> KifParseResult<()> {
+        let invalid = |description| KifParseError::InvalidLine {
+            line: line_no,
+            description,
+        };
+
+        if line.is_empty()
+            || line.starts_with('#')
+            || line.starts_with('&')
+            || line.starts_with("手数----")
+        {
+            return Ok(());
+        }
+
+        // 局面図は最初の指し手より前にのみ現れる。
+        if self.record.entries().is_empty() {
+            let bod = self.bod.get_or_insert_with(BodReader::new);
+            if bod.read_line(line).map_err(invalid)? {
+                return Ok(());
+            }
+            if parse_header_line(line).is_none() {
+                self.finish_bod(line_no)?;
+            }
+        }
+
+        if line.starts_with("まで") {
+            // KIF では終局を指し手行で表すので、要約行は読み捨てる。
+            if format == KifFormat::Ki2 {
+                let special = parse_summary(line).ok_or_else(|| invalid("invalid summary line"))?;
+                self.push(RecordEntry::new(RecordMove::Special(special)), line_no)?;
+            }
+            return Ok(());
+        }
+
+        if let Some(comment) = line.strip_prefix('*') {
+            let comments = match self.cursor.line_mut(self.record.entries_mut()).last_mut() {
+                Some(entry) => entry.comments_mut(),
+                None if self.cursor.path.is_empty() => self.record.comments_mut(),
+                None => return Err(invalid("comment before first move of variation")),
+            };
+            comments.push(comment.to_owned());
+            return Ok(());
+        }
+
+        if let Some(rest) = line.strip_prefix("変化：") {
+            let ply: u32 = rest
+                .strip_suffix('手')
+                .and_then(|n| n.parse().ok())
+                .ok_or_else(|| invalid("invalid variation line"))?;
+            self.cursor
+                .branch(self.record.entries_mut(), ply)
+                .ok_or_else(|| invalid("no move to branch from"))?;
+            (self.pos, self.last_dst) = self
+                .cursor
+                .replay(self.record.position(), self.record.entries())
+                .map_err(|error| KifParseError::InvalidMove {
+                    line: line_no,
+                    error,
+                })?;
+            return Ok(());
+        }
+
+        match format {
+            KifFormat::Kif if line.starts_with(|c: char| c.is_ascii_digit()) => {
+                let ply = self.next_ply();
+                let entry =
+                    parse_move_line(line, ply, &self.pos, self.last_dst).map_err(invalid)?;
+                return self.push(entry, line_no);
+            }
+            KifFormat::Ki2 if line.starts_with(KI2_SIDE_MARKS) => {
+                for (side, text) in split_ki2_moves(line) {
+                    if side != self.pos.side_to_move() {
+                        return Err(invalid("unexpected side to move"));
+                    }
+                    let (remain, mv) = parse_ki2_move(text, &self.pos, self.last_dst)
+                        .map_err(|e| invalid(e.description()))?;
+                    if !trim_kif(remain).is_empty() {
+                        return Err(invalid("extra input after move"));
+                    }
+                    self.push(RecordEntry::new(RecordMove::Move(mv)), line_no)?;
+                }
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        if let Some((key, value)) = parse_header_line(line) {
+            if !self.record.entries().is_empty() {
+                return Err(invalid("header after moves"));
+            }
+            if key == "手合割" {
+                let handicap =
+                    handicap_from_name(value).ok_or_else(|| invalid("unknown handicap"))?;
+                self.record.set_position(handicap.position());
+                self.pos = self.record.position().clone();
+            }
+            self.record
+                .headers_mut()
+                .push((key.to_owned(), value.to_owned()));
+            return Ok(());
+        }
+
+        Err(invalid("unknown line"))
+    }
+}
+
+/// KIF/KI2 形式の棋譜文字列をパースする。
+pub(crate) fn read_kif_record(s: &str, format: KifFormat) -> KifParseResult<Record> {
+    let mut reader = KifReader::new();
+
+    for (i, line) in s.lines().enumerate() {
+        reader.read_line(format, trim_kif(line), i + 1)?;
+    }
+    reader.finish_bod(s.lines().count())?;
+
+    Ok(reader.record)
+}
+
+/// KIF/KI2 形式の棋譜のヘッダ部(手合割または局面図、ヘッダ、開始局面へのコメント)を出力する。
+pub(crate) fn write_kif_header(s: &mut String, record: &Record) {
+    let handicap = Handicap::from_position(record.position());
+
+    match handicap {
+        Some(handicap) if record.header("手合割").is_none() => {
+            writeln!(s, "手合割：{}", handicap_to_name(handicap)).unwrap();
+        }
+        Some(_) => {}
+        None => write_bod(s, record.position()),
+    }
+    for (key, value) in record.headers() {
+        let value = match (key.as_str(), handicap) {
+            ("手合割", Some(handicap)) => handicap_to_name(handicap),
+            ("手合割", None) => continue,
+            _ => value,
+        };
+        writeln!(s, "{key}：{value}").unwrap();
+    }
+
+    for comment in record.comments() {
+        writeln!(s, "*{comment}").unwrap();
+    }
+}
+
+impl Record {
+    /// KIF 形式の棋譜文字列をパースする。
+    ///
+    /// 文字コードの変換は呼び出し側で行うこと。
+    /// `手合割` ヘッダおよび局面図は開始局面として解釈する。`手合割` ヘッダはヘッダにも残す。
+    /// `#` で始まる行、`&` で始まる行、`まで` で始まる行は無視する。
+    pub fn from_kif(s: &str) -> KifParseResult<Self> {
+        read_kif_record(s, KifFormat::Kif)
+    }
+
+    /// KIF 形式の棋譜文字列を返す。
+    ///
+    /// `手合割` ヘッダは開始局面から生成する。
+    /// 開始局面がどの手合割にも該当しなければ、代わりに局面図を出力する。
+    pub fn to_kif(&self) -> Result<String, KifWriteError> {
+        let mut s = String::new();
+        write_kif_header(&mut s, self);
+
+        s.push_str(MOVES_HEADER);
+        s.push('\n');
+
+        let mut pos = self.position().clone();
+        write_kif_line(&mut s, &mut pos, None, 1, self.entries(), true)?;
+
+        Ok(s)
+    }
+}
+
+impl Kifu {
+    /// KIF 形式の棋譜文字列をパースし、本譜の手順を返す。
+    ///
+    /// コメント、消費時間、変化などは捨てられる。これらが必要なら `Record::from_kif` を使う。
+    pub fn from_kif(s: &str) -> KifParseResult<Self> {
+        Record::from_kif(s).map(|record| record.kifu())
+    }
+
+    /// KIF 形式の棋譜文字列を返す。
+    pub fn to_kif(&self) -> Result<String, KifWriteError> {
+        Record::from(self.clone()).to_kif()
+    }
+}
+
+/// 手順 `line` を出力し、続けてその変化を出力する。`pos` は手順の末尾の局面になる。
+fn write_kif_line(
+    s: &mut String,
+    pos: &mut Position,
+    mut last_dst: Option<Square>,
+    first_ply: u32,
+    line: &[RecordEntry],
+    is_main: bool,
+) -> Result<(), KifWriteError> {
+    // 変化の出力のため、各手の直前の局面を記録しておく。
+    let mut history = Vec::with_capacity(line.len());
+
+    for (ply, entry) in (first_ply..).zip(line) {
+        history.push((pos.clone(), last_dst));
+
+        let text = match entry.mv() {
+            RecordMove::Move(mv) => {
+                let text = fmt_kif_move(pos, mv, last_dst)?;
+                pos.do_move(mv)?;
+                last_dst = Some(mv.dst());
+                text
+            }
+            RecordMove::Special(special) => special_move_to_name(special).to_owned(),
+        };
+
+        write!(s, "{ply:>4} {text}").unwrap();
+        if let Some(time) = entry.time() {
+            let pad = MOVE_COLUMN_WIDTH.saturating_sub(display_width(&text));
+            s.extend(std::iter::repeat_n(' ', pad));
+            fmt_kif_time(s, time);
+        }
+        if !entry.variations().is_empty() {
+            s.push('+');
+        }
+        s.push('\n');
+
+        for comment in entry.comments() {
+            writeln!(s, "*{comment}").unwrap();
+        }
+    }
+
+    if is_main {
+        if let Some(RecordMove::Special(special)) = line.last().map(RecordEntry::mv) {
+            let n_moves = line.len() - 1;
+            writeln!(s, "{}", fmt_summary(special, n_moves, pos.side_to_move())).unwrap();
+        }
+    }
+
+    // 後の手の変化から出力する(パース時は直近の手順から分岐元を探すため)。
+    for (i, entry) in line.iter().enumerate().rev() {
+        for variation in entry.variations() {
+            let ply = first_ply + i as u32;
+            let (mut pos, last_dst) = history[i].clone();
+            write!(s, "\n変化：{ply}手\n").unwrap();
+            write_kif_line(s, &mut pos, last_dst, ply, variation, false)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// 終局の要約行 (`まで64手で先手の勝ち` など) を返す。`side_to_move` は終局時の手番。
+pub(crate) fn fmt_summary(special: SpecialMove, n_moves: usize, side_to_move: Side) -> String {
+    let side_name = |side| match side {
+        SENTE => "先手",
+        GOTE => "後手",
+    };
+    let next = side_name(side_to_move);
+    let last = side_name(side_to_move.flip());
+
+    match special {
+        SpecialMove::Interrupt => format!("まで{n_moves}手で中断"),
+        SpecialMove::Resign => format!("まで{n_moves}手で{last}の勝ち"),
+        SpecialMove::Impasse => format!("まで{n_moves}手で持将棋"),
+        SpecialMove::Repetition => format!("まで{n_moves}手で千日手"),
+        SpecialMove::Mate => format!("まで{n_moves}手で詰み"),
+        SpecialMove::NoMate => format!("まで{n_moves}手で不詰"),
+        SpecialMove::TimeUp => format!("まで{n_moves}手で時間切れにより{last}の勝ち"),
+        SpecialMove::IllegalWin => format!("まで{n_moves}手で{next}の反則勝ち"),
+        SpecialMove::IllegalLose => format!("まで{n_moves}手で{next}の反則負け"),
+        SpecialMove::DeclareWin => format!("まで{n_moves}手で{next}の入玉勝ち"),
+        SpecialMove::Draw => format!("まで{n_moves}手で引き分け"),
+        SpecialMove::Matta => format!("まで{n_moves}手で待った"),
+    }
+}
+
+/// 消費時間を `( 0:01/00:00:01)` の形式で出力する。
+fn fmt_kif_time(s: &mut String, time: MoveTime) {
+    let elapsed = time.elapsed().as_secs();
+    let total = time.total().as_secs();
+    write!(
+        s,
+        "({:>2}:{:02}/{:02}:{:02}:{:02})",
+        elapsed / 60,
+        elapsed % 60,
+        total / 3600,
+        total / 60 % 60,
+        total % 60
+    )
+    .unwrap();
+}
+
+/// 指し手を KIF 形式 (`７六歩(77)`, `同　銀(31)`, `５五角打` など) で返す。
+pub(crate) fn fmt_kif_move(
+    pos: &Position,
+    mv: Move,
+    last_dst: Option<Square>,
+) -> Result<String, MoveError> {
+    let mut s = String::new();
+
+    let fmt_dst = |s: &mut String| {
+        if last_dst == Some(mv.dst()) {
+            s.push_str("同　");
+        } else {
+            s.push(col_to_zenkaku(mv.dst().col()));
+            s.push(row_to_kanji(mv.dst().row()));
+        }
+    };
+
+    match mv {
+        Move::Walk(walk) => {
+            let pc = pos.board()[walk.src()]
+                .filter(|pc| pc.side() == pos.side_to_move())
+                .ok_or(MoveError::NoOwnPieceAtSrc)?;
+            fmt_dst(&mut s);
+            s.push_str(piece_kind_to_name(pc.kind()));
+            if walk.is_promotion() {
+                s.push('成');
+            } else if can_promote(pos.side_to_move(), pc.kind(), walk) {
+                s.push_str("不成");
+            }
+            write!(
+                s,
+                "({}{})",
+                walk.src().col().to_num(),
+                walk.src().row().to_num()
+            )
+            .unwrap();
+        }
+        Move::Drop(drop) => {
+            fmt_dst(&mut s);
+            s.push_str(piece_kind_to_name(drop.piece_kind().into()));
+            s.push('打');
+        }
+    }
+
+    Ok(s)
+}
+
+/// 指し手行 (`   1 ７六歩(77)   ( 0:01/00:00:01)` など) をパースする。
+fn parse_move_line(
+    line: &str,
+    ply: u32,
+    pos: &Position,
+    last_dst: Option<Square>,
+) -> Result<RecordEntry, &'static str> {
+    let digits_end = line
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(line.len());
+    let (num, rest) = line.split_at(digits_end);
+    if num.parse() != Ok(ply) {
+        return Err("unexpected move number");
+    }
+    let rest = trim_kif(rest);
+
+    let (rest, mv) = if let Some((rest, special)) = parse_special_move(rest) {
+        (rest, RecordMove::Special(special))
+    } else {
+        let (rest, mv) = parse_kif_move(rest, pos, last_dst)?;
+        (rest, RecordMove::Move(mv))
+    };
+
+    let mut rest = trim_kif(rest);
+    let mut time = None;
+    if rest.starts_with('(') {
+        let end = rest.find(')').ok_or("unterminated time")?;
+        time = Some(parse_kif_time(&rest[1..end]).ok_or("invalid time")?);
+        rest = trim_kif(&rest[end + 1..]);
+    }
+
+    // 変化があることを示す '+' は無視する。
+    let rest = rest.strip_prefix('+').unwrap_or(rest);
+    if !trim_kif(rest).is_empty() {
+        return Err("extra input after move");
+    }
+
+    let mut entry = RecordEntry::new(mv);
+    entry.set_time(time);
+    Ok(entry)
+}
+
+/// 指し手 (`７六歩(77)` など) をパースする。駒種が局面と一致するかも検査する。
+fn parse_kif_move<'a>(
+    s: &'a str,
+    pos: &Position,
+    last_dst: Option<Square>,
+) -> Result<(&'a str, Move), &'static str> {
+    let (s, dst) = if let Some(s) = s.strip_prefix('同') {
+        let s = s.trim_start_matches(['　', ' ']);
+        (s, last_dst.ok_or("no previous move for '同'")?)
+    } else {
+        parse_square(s).ok_or("destination square expected")?
+    };
+
+    let (s, pk) = parse_piece_kind(s).ok_or("piece kind expected")?;
+
+    if let Some(s) = s.strip_prefix('打') {
+        let hpk = HandPieceKind::try_from(pk).map_err(|_| "piece kind cannot be dropped")?;
+        return Ok((s, Move::drop(hpk, dst)));
+    }
+
+    let (s, promo) = if let Some(s) = s.strip_prefix("不成") {
+        (s, false)
+    } else if let Some(s) = s.strip_prefix('成') {
+        (s, true)
+    } else {
+        (s, false)
+    };
+
+    let s = s.strip_prefix('(').ok_or("source square expected")?;
+    let (s, col) = parse_digit(s).ok_or("source square expected")?;
+    let (s, row) = parse_digit(s).ok_or("source square expected")?;
+    let s = s.strip_prefix(')').ok_or("')' expected")?;
+    let src = Square::new(
+        Col::from_num(col).ok_or("invalid source square")?,
+        Row::from_num(row).ok_or("invalid source square")?,
+    );
+
+    if pos.board()[src].map(|pc| pc.kind()) != Some(pk) {
+        return Err("piece kind does not match board");
+    }
+
+    Ok((s, Move::walk(src, dst, promo)))
+}
+
+/// 消費時間 (` 0:01/00:00:01`) をパースする。
+fn parse_kif_time(s: &str) -> Option<MoveTime> {
+    let (elapsed, total) = s.split_once('/')?;
+
+    let parse_secs = |s: &str| -> Option<u64> {
+        s.split(':').try_fold(0_u64, |acc, field| {
+            let n: u64 = trim_kif(field).parse().ok()?;
+            acc.checked_mul(60)?.checked_add(n)
+        })
+    };
+
+    Some(MoveTime::new(
+        Duration::from_secs(parse_secs(elapsed)?),
+        Duration::from_secs(parse_secs(total)?),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr as _;
+
+    use super::*;
+
+    const SAMPLE: &str = "\
+開始日時：2024/01/01 10:00:00
+手合割：平手
+先手：先手太郎
+後手：後手花子
+*開始局面へのコメント
+手数----指手---------消費時間--
+   1 ７六歩(77)   ( 0:01/00:00:01)
+   2 ３四歩(33)   ( 0:02/00:00:02)
+   3 ２二角成(88) ( 0:03/00:00:04)+
+*角交換
+   4 同　銀(31)   ( 0:04/00:00:06)
+   5 ４五角打     ( 1:05/00:01:09)
+   6 投了         ( 0:06/00:00:12)
+まで5手で先手の勝ち
+
+変化：3手
+   3 ６六歩(67)   ( 0:03/00:00:04)
+   4 ８四歩(83)   ( 0:04/00:00:06)+
+
+変化：4手
+   4 ４二飛(82)   ( 0:04/00:00:06)
+";
+
+    fn sample_kifu() -> Kifu {
+        Kifu::from_str("position startpos moves 7g7f 3c3d 8h2b+ 3a2b B*4e").unwrap()
+    }
+
+    fn time(elapsed: u64, total: u64) -> Option<MoveTime> {
+        Some(MoveTime::new(
+            Duration::from_secs(elapsed),
+            Duration::from_secs(total),
+        ))
+    }
+
+    #[test]
+    fn test_kif_parse() {
+        let record = Record::from_kif(SAMPLE).unwrap();
+
+        assert_eq!(record.header("先手"), Some("先手太郎"));
+        assert_eq!(record.header("手合割"), Some("平手"));
+        assert_eq!(record.comments(), ["開始局面へのコメント"]);
+        assert_eq!(record.kifu(), sample_kifu());
+        assert_eq!(record.special(), Some(SpecialMove::Resign));
+
+        let entries = record.entries();
+        assert_eq!(entries[0].time(), time(1, 1));
+        assert_eq!(entries[4].time(), time(65, 69));
+        assert_eq!(entries[2].comments(), ["角交換"]);
+
+        let variations = entries[2].variations();
+        assert_eq!(variations.len(), 1);
+        assert_eq!(
+            variations[0][0].mv(),
+            RecordMove::Move(Move::from_str("6g6f").unwrap())
+        );
+        assert_eq!(
+            variations[0][1].variations()[0][0].mv(),
+            RecordMove::Move(Move::from_str("8b4b").unwrap())
+        );
+    }
+
+    #[test]
+    fn test_kif_parse_handicap() {
+        let kifu = Kifu::from_kif(
+            "手合割：香落ち\n手数----指手---------消費時間--\n   1 ３四歩(33)\n   2 ７六歩(77)\n",
+        )
+        .unwrap();
+        assert_eq!(
+            kifu,
+            Kifu::new(
+                Handicap::Lance.position(),
+                [
+                    Move::from_str("3c3d").unwrap(),
+                    Move::from_str("7g7f").unwrap()
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn test_kif_parse_error() {
+        assert!(matches!(
+            Record::from_kif("   1 ７六歩(77)\n   3 ３四歩(33)\n"),
+            Err(KifParseError::InvalidLine { line: 2, .. })
+        ));
+        assert!(matches!(
+            Record::from_kif("   1 ７六飛(77)\n"),
+            Err(KifParseError::InvalidLine { line: 1, .. })
+        ));
+        assert!(matches!(
+            Record::from_kif("   1 ７七歩(76)\n"),
+            Err(KifParseError::InvalidLine { line: 1, .. })
+        ));
+        assert!(matches!(
+            Record::from_kif("   1 ５五角打\n"),
+            Err(KifParseError::InvalidMove {
+                line: 1,
+                error: MoveError::NotInHand
+            })
+        ));
+
+        // 消費時間が大きすぎる。
+        assert!(matches!(
+            Record::from_kif("   1 ７六歩(77)   ( 0:01/18446744073709551615:59:00)\n"),
+            Err(KifParseError::InvalidLine {
+                line: 1,
+                description: "invalid time"
+            })
+        ));
+    }
+
+    #[test]
+    fn test_kif_fmt() {
+        let record = Record::from_kif(SAMPLE).unwrap();
+        let kif = record.to_kif().unwrap();
+
+        assert!(kif.contains("   1 ７六歩(77)   ( 0:01/00:00:01)\n"));
+        assert!(kif.contains("   3 ２二角成(88) ( 0:03/00:00:04)+\n"));
+        assert!(kif.contains("   4 同　銀(31)   ( 0:04/00:00:06)\n"));
+        assert!(kif.contains("   5 ４五角打     ( 1:05/00:01:09)\n"));
+        assert!(kif.contains("まで5手で先手の勝ち\n"));
+
+        assert_eq!(Record::from_kif(&kif).unwrap(), record);
+
+        assert_eq!(
+            sample_kifu().to_kif().unwrap(),
+            "\
+手合割：平手
+手数----指手---------消費時間--
+   1 ７六歩(77)
+   2 ３四歩(33)
+   3 ２二角成(88)
+   4 同　銀(31)
+   5 ４五角打
+"
+        );
+    }
+
+    #[test]
+    fn test_kif_bod() {
+        let pos = Position::from_str("4k4/9/4G4/9/9/9/9/9/4K4 b G2r2b2g4s4n4l18p 1").unwrap();
+        let kifu = Kifu::new(pos.clone(), vec![Move::from_str("G*5b").unwrap()]);
+        let kif = kifu.to_kif().unwrap();
+
+        assert!(kif.starts_with("後手の持駒：飛二　角二　金二　銀四　桂四　香四　歩十八\n"));
+        assert!(kif.contains("先手の持駒：金\n先手番\n手数----"));
+        assert!(!kif.contains("手合割"));
+        assert_eq!(Kifu::from_kif(&kif).unwrap(), kifu);
+        assert_eq!(Kifu::from_ki2(&kifu.to_ki2().unwrap()).unwrap(), kifu);
+
+        assert!(matches!(
+            Record::from_kif("後手の持駒：なし\n| ・ ・ ・ ・ ・ ・ ・ ・ ・|一\n先手番\n"),
+            Err(KifParseError::InvalidLine { line: 3, .. })
+        ));
+    }
+}
@@ -68,6 +68,13 @@ impl Kifu {
     }
 }
 
+impl From<Position> for Kifu {
+    /// 手順が空の棋譜を作る。
+    fn from(pos: Position) -> Self {
+        Self::new(pos, [])
+    }
+}
+
 impl std::str::FromStr for Kifu {
     type Err = SfenParseError;
 
@@ -90,8 +97,8 @@ impl std::fmt::Display for Kifu {
     /// 開始局面が平手初期局面でなければ "sfen" を付ける。
     /// 手順が空の場合、"moves" は付けない。
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        f.write_str("position ")?;
-        self.pos.fmt(f)?;
+        // `{:#}` は局面の端末向け表示なので、局面には書式指定を引き継がない。
+        write!(f, "position {}", self.pos)?;
 
         if !self.mvs.is_empty() {
             f.write_str(" moves")?;
@@ -177,5 +184,9 @@ mod tests {
             .to_string(),
             "position startpos moves 7g7f 3c3d 8h2b+ 8b2b B*6e"
         );
+
+        // `{:#}` でも局面は SFEN で出力する。
+        let kifu = Kifu::from_str("sfen 4k4/9/9/9/9/9/9/9/4K4 b G 1 moves G*5b").unwrap();
+        assert_eq!(format!("{kifu:#}"), kifu.to_string());
     }
 }
@@ -1,20 +1,72 @@
+mod analysis;
+mod apery;
 mod board;
+mod bod;
+mod book;
 mod bytes;
+mod csa;
+mod csa_client;
+mod csa_server;
 mod hand;
+mod handicap;
+mod hcpe;
+mod hcpe3;
+mod japanese;
+mod jkf;
+mod json;
+mod ki2;
+mod kif;
 mod kifu;
 mod move_;
+mod movegen;
+mod packed_sfen;
+mod packed_sfen_value;
 mod parse;
 mod piece;
 mod position;
+mod pretty;
+mod record;
+mod referee;
 mod side;
 mod square;
+mod svg;
+mod usi_command;
+mod usi_engine;
+mod usi_option;
+mod usi_process;
+mod usi_response;
+mod western;
 
+pub use self::analysis::*;
+pub use self::apery::*;
 pub use self::board::*;
+pub use self::bod::*;
+pub use self::book::*;
+pub use self::csa::*;
+pub use self::csa_client::*;
+pub use self::csa_server::*;
 pub use self::hand::*;
+pub use self::handicap::*;
+pub use self::hcpe::*;
+pub use self::hcpe3::*;
+pub use self::jkf::*;
+pub use self::kif::*;
 pub use self::kifu::*;
 pub use self::move_::*;
+pub use self::packed_sfen::*;
+pub use self::packed_sfen_value::*;
 pub use self::parse::*;
 pub use self::piece::*;
 pub use self::position::*;
+pub use self::pretty::*;
+pub use self::record::*;
+pub use self::referee::*;
 pub use self::side::*;
 pub use self::square::*;
+pub use self::svg::*;
+pub use self::usi_command::*;
+pub use self::usi_engine::*;
+pub use self::usi_option::*;
+pub use self::usi_process::*;
+pub use self::usi_response::*;
+pub use self::western::*;
@@ -3,6 +3,7 @@ use std::fmt::Write as _;
 use crate::bytes::Bytes;
 use crate::hand::*;
 use crate::parse::*;
+use crate::position::MoveError;
 use crate::square::*;
 
 /// 指し手。
@@ -34,6 +35,56 @@ impl Move {
         }
     }
 
+    /// やねうら王の 16 ビット表現を返す。
+    ///
+    /// ビット 0-6 が移動先、ビット 7-13 が移動元、ビット 14 が駒打ちフラグ、ビット 15 が成りフラグ。
+    /// マスは 1一 = 0, 1二 = 1, ..., 9九 = 80 の番号で表す。
+    /// 駒打ちの場合、移動元は駒種番号 (`Pawn` = 1, `Lance` = 2, `Knight` = 3, `Silver` = 4,
+    /// `Bishop` = 5, `Rook` = 6, `Gold` = 7)。
+    pub fn to_u16(self) -> u16 {
+        match self {
+            Self::Walk(walk) => {
+                u16::from(walk.dst().to_col_major_index())
+                    | (u16::from(walk.src().to_col_major_index()) << 7)
+                    | (u16::from(walk.is_promotion()) << 15)
+            }
+            Self::Drop(drop) => {
+                u16::from(drop.dst().to_col_major_index())
+                    | (drop_piece_number(drop.piece_kind()) << 7)
+                    | (1 << 14)
+            }
+        }
+    }
+
+    /// やねうら王の 16 ビット表現から指し手を作る。
+    ///
+    /// 移動元と移動先が同じ値 (`MOVE_NONE`, `MOVE_NULL` など) は `MoveDecodeError::InvalidSrc` となる。
+    pub fn from_u16(value: u16) -> Result<Self, MoveDecodeError> {
+        let dst = Square::from_col_major_index((value & 0x7F) as u8)
+            .ok_or(MoveDecodeError::InvalidDst)?;
+        let src = ((value >> 7) & 0x7F) as u8;
+        let is_drop = value & (1 << 14) != 0;
+        let promo = value & (1 << 15) != 0;
+
+        if is_drop {
+            if promo {
+                return Err(MoveDecodeError::InvalidFlags);
+            }
+            let hpk = HandPieceKind::all()
+                .into_iter()
+                .find(|&hpk| drop_piece_number(hpk) == u16::from(src))
+                .ok_or(MoveDecodeError::InvalidSrc)?;
+            return Ok(Self::drop(hpk, dst));
+        }
+
+        let src = Square::from_col_major_index(src).ok_or(MoveDecodeError::InvalidSrc)?;
+        if src == dst {
+            return Err(MoveDecodeError::InvalidSrc);
+        }
+
+        Ok(Self::walk(src, dst, promo))
+    }
+
     pub(crate) fn parse(bytes: Bytes) -> SfenParseResult<(Bytes, Self)> {
         // MoveWalk | MoveDrop
 
@@ -66,6 +117,19 @@ impl std::fmt::Display for Move {
     }
 }
 
+/// やねうら王の 16 ビット表現で駒打ちの移動元に入れる駒種番号 (`Pawn` = 1, ..., `Gold` = 7)。
+const fn drop_piece_number(hpk: HandPieceKind) -> u16 {
+    match hpk {
+        HAND_PAWN => 1,
+        HAND_LANCE => 2,
+        HAND_KNIGHT => 3,
+        HAND_SILVER => 4,
+        HAND_BISHOP => 5,
+        HAND_ROOK => 6,
+        HAND_GOLD => 7,
+    }
+}
+
 /// 盤上の駒を動かす指し手。
 #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct MoveWalk {
@@ -193,6 +257,86 @@ impl std::fmt::Display for MoveDrop {
     }
 }
 
+/// 指し手表記(日本語表記、西洋式の表記)のパースエラー。
+#[non_exhaustive]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MoveNotationParseError {
+    /// 表記として不正。
+    InvalidNotation { description: &'static str },
+
+    /// 表記に該当する指し手がない。
+    NoCandidate,
+
+    /// 表記に該当する指し手が複数ある。`candidates` はその全て。
+    Ambiguous { candidates: Vec<Move> },
+
+    /// 表記から特定した指し手を局面に適用できない。
+    InvalidMove(MoveError),
+}
+
+impl MoveNotationParseError {
+    /// エラーの簡潔な説明を返す。
+    pub(crate) fn description(&self) -> &'static str {
+        match self {
+            Self::InvalidNotation { description } => description,
+            Self::NoCandidate => "no piece can move to the square",
+            Self::Ambiguous { .. } => "ambiguous move",
+            Self::InvalidMove(_) => "invalid move",
+        }
+    }
+}
+
+impl std::fmt::Display for MoveNotationParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::InvalidNotation { description } => {
+                write!(f, "invalid move notation: {description}")
+            }
+            Self::NoCandidate => f.write_str("no move matches the notation"),
+            Self::Ambiguous { candidates } => {
+                f.write_str("ambiguous move notation; candidates:")?;
+                for mv in candidates {
+                    write!(f, " {mv}")?;
+                }
+                Ok(())
+            }
+            Self::InvalidMove(e) => write!(f, "invalid move: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for MoveNotationParseError {}
+
+pub(crate) const fn invalid_notation(description: &'static str) -> MoveNotationParseError {
+    MoveNotationParseError::InvalidNotation { description }
+}
+
+/// 16 ビット表現の指し手のデコードエラー。
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MoveDecodeError {
+    /// 移動先が盤外。
+    InvalidDst,
+
+    /// 移動元が盤外、または移動先と同じ。駒打ちの場合、駒種が不正。
+    InvalidSrc,
+
+    /// 駒打ちに成りフラグが立っているなど、フラグの組み合わせが不正。
+    InvalidFlags,
+}
+
+impl std::fmt::Display for MoveDecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::InvalidDst => f.write_str("invalid destination square"),
+            Self::InvalidSrc => f.write_str("invalid source square or drop piece"),
+            Self::InvalidFlags => f.write_str("invalid flags"),
+        }
+    }
+}
+
+impl std::error::Error for MoveDecodeError {}
+
 #[cfg(test)]
 mod tests {
     use std::str::FromStr as _;
@@ -222,6 +366,52 @@ mod tests {
         assert!(Move::from_str("G+1a").is_err()); // 駒打ちの 2 バイト目が不正
     }
 
+    #[test]
+    fn test_move_u16() {
+        let mv = |s| Move::from_str(s).unwrap();
+
+        // 7七 → 7六: 移動元 = 9*6+6 = 60, 移動先 = 9*6+5 = 59。
+        assert_eq!(mv("7g7f").to_u16(), 59 | (60 << 7));
+        assert_eq!(mv("8h2b+").to_u16(), 10 | (70 << 7) | (1 << 15));
+        assert_eq!(mv("P*5e").to_u16(), 40 | (1 << 7) | (1 << 14));
+        assert_eq!(mv("G*1a").to_u16(), (7 << 7) | (1 << 14));
+
+        for s in [
+            "7g7f", "8h2b+", "2b8h", "1a9i", "9i1a+", "P*5e", "L*1i", "N*9a", "S*2c", "B*5e",
+            "R*9a", "G*1a",
+        ] {
+            assert_eq!(Move::from_u16(mv(s).to_u16()), Ok(mv(s)), "{s}");
+        }
+        assert_eq!(
+            Move::from_u16(Move::walk(SQ_77, SQ_76, false).to_u16()),
+            Ok(Move::Walk(MoveWalk::new(SQ_77, SQ_76, false)))
+        );
+        assert_eq!(
+            Move::from_u16(Move::drop(HAND_PAWN, SQ_55).to_u16()),
+            Ok(Move::Drop(MoveDrop::new(HAND_PAWN, SQ_55)))
+        );
+
+        // MOVE_NONE, MOVE_NULL。
+        assert_eq!(Move::from_u16(0), Err(MoveDecodeError::InvalidSrc));
+        assert_eq!(
+            Move::from_u16(1 | (1 << 7)),
+            Err(MoveDecodeError::InvalidSrc)
+        );
+        assert_eq!(Move::from_u16(81), Err(MoveDecodeError::InvalidDst));
+        assert_eq!(
+            Move::from_u16(40 | (81 << 7)),
+            Err(MoveDecodeError::InvalidSrc)
+        );
+        assert_eq!(
+            Move::from_u16(40 | (8 << 7) | (1 << 14)),
+            Err(MoveDecodeError::InvalidSrc)
+        );
+        assert_eq!(
+            Move::from_u16(40 | (1 << 7) | (1 << 14) | (1 << 15)),
+            Err(MoveDecodeError::InvalidFlags)
+        );
+    }
+
     #[test]
     fn test_move_fmt() {
         assert_eq!(Move::walk(SQ_77, SQ_76, false).to_string(), "7g7f");
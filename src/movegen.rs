@@ -0,0 +1,403 @@
+//! 駒の利きと合法手。
+
+use crate::board::*;
+use crate::hand::*;
+use crate::move_::*;
+use crate::piece::*;
+use crate::position::*;
+use crate::side::*;
+use crate::square::*;
+
+/// 駒が動ける方向 (筋番号の変化, 段番号の変化)。先手から見た向きで表す。
+type Dir = (i8, i8);
+
+const DIRS_PAWN: &[Dir] = &[(0, -1)];
+const DIRS_KNIGHT: &[Dir] = &[(1, -2), (-1, -2)];
+const DIRS_SILVER: &[Dir] = &[(1, -1), (0, -1), (-1, -1), (1, 1), (-1, 1)];
+const DIRS_GOLD: &[Dir] = &[(1, -1), (0, -1), (-1, -1), (1, 0), (-1, 0), (0, 1)];
+const DIRS_KING: &[Dir] = &[
+    (1, -1),
+    (0, -1),
+    (-1, -1),
+    (1, 0),
+    (-1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+];
+const DIRS_DIAGONAL: &[Dir] = &[(1, -1), (-1, -1), (1, 1), (-1, 1)];
+const DIRS_ORTHOGONAL: &[Dir] = &[(0, -1), (1, 0), (-1, 0), (0, 1)];
+
+/// 駒種の (1 マスだけ動ける方向, どこまでも動ける方向) を返す。
+const fn dirs(pk: PieceKind) -> (&'static [Dir], &'static [Dir]) {
+    match pk {
+        PAWN => (DIRS_PAWN, &[]),
+        LANCE => (&[], DIRS_PAWN),
+        KNIGHT => (DIRS_KNIGHT, &[]),
+        SILVER => (DIRS_SILVER, &[]),
+        GOLD | PRO_PAWN | PRO_LANCE | PRO_KNIGHT | PRO_SILVER => (DIRS_GOLD, &[]),
+        BISHOP => (&[], DIRS_DIAGONAL),
+        ROOK => (&[], DIRS_ORTHOGONAL),
+        KING => (DIRS_KING, &[]),
+        HORSE => (DIRS_ORTHOGONAL, DIRS_DIAGONAL),
+        DRAGON => (DIRS_DIAGONAL, DIRS_ORTHOGONAL),
+    }
+}
+
+/// 駒 `pc` を `src` に置いたとき、`dst` に利いているかどうかを返す。
+///
+/// `dst` にある駒は考慮しない。
+pub(crate) fn attacks(board: &Board, pc: Piece, src: Square, dst: Square) -> bool {
+    let sign = match pc.side() {
+        SENTE => 1,
+        GOTE => -1,
+    };
+    let (steps, slides) = dirs(pc.kind());
+
+    let step_hit = steps
+        .iter()
+        .any(|&(dc, dr)| src.offset(sign * dc, sign * dr) == Some(dst));
+    if step_hit {
+        return true;
+    }
+
+    slides.iter().any(|&(dc, dr)| {
+        let mut sq = src;
+        while let Some(next) = sq.offset(sign * dc, sign * dr) {
+            if next == dst {
+                return true;
+            }
+            if board[next].is_some() {
+                break;
+            }
+            sq = next;
+        }
+        false
+    })
+}
+
+/// `dst` に利いている `side` 側の駒種 `pk` の駒のマスを返す。
+pub(crate) fn attackers(board: &Board, side: Side, pk: PieceKind, dst: Square) -> Vec<Square> {
+    let pc = Piece::new(side, pk);
+
+    Square::all()
+        .into_iter()
+        .filter(|&src| board[src] == Some(pc) && attacks(board, pc, src, dst))
+        .collect()
+}
+
+/// `side` 側の玉のマスを返す。玉がなければ `None` を返す。
+pub(crate) fn king_square(board: &Board, side: Side) -> Option<Square> {
+    let king = Piece::new(side, KING);
+
+    Square::all()
+        .into_iter()
+        .find(|&sq| board[sq] == Some(king))
+}
+
+/// `dst` に `side` 側の駒が利いているかどうかを返す。
+pub(crate) fn is_attacked(board: &Board, side: Side, dst: Square) -> bool {
+    Square::all()
+        .into_iter()
+        .any(|src| board[src].is_some_and(|pc| pc.side() == side && attacks(board, pc, src, dst)))
+}
+
+/// 駒 `pc` が `src` から動けるマスを返す。自分の駒があるマスは除く。
+fn destinations(board: &Board, pc: Piece, src: Square) -> Vec<Square> {
+    let sign = match pc.side() {
+        SENTE => 1,
+        GOTE => -1,
+    };
+    let (steps, slides) = dirs(pc.kind());
+    let is_free = |sq: Square| board[sq].is_none_or(|other| other.side() != pc.side());
+
+    let mut dsts: Vec<Square> = steps
+        .iter()
+        .filter_map(|&(dc, dr)| src.offset(sign * dc, sign * dr))
+        .filter(|&sq| is_free(sq))
+        .collect();
+
+    for &(dc, dr) in slides {
+        let mut sq = src;
+        while let Some(next) = sq.offset(sign * dc, sign * dr) {
+            if is_free(next) {
+                dsts.push(next);
+            }
+            if board[next].is_some() {
+                break;
+            }
+            sq = next;
+        }
+    }
+
+    dsts
+}
+
+/// `side` 側の駒種 `pk` の駒が `dst` にあると、それ以上動けない(行き所のない駒になる)かどうかを返す。
+fn is_dead_end(side: Side, pk: PieceKind, dst: Square) -> bool {
+    // 相手陣の奥から数えた段 (1..=9)。
+    let depth = match side {
+        SENTE => dst.row().to_num(),
+        GOTE => 10 - dst.row().to_num(),
+    };
+
+    match pk {
+        PAWN | LANCE => depth == 1,
+        KNIGHT => depth <= 2,
+        _ => false,
+    }
+}
+
+impl Position {
+    /// 手番側の玉に王手がかかっているかどうかを返す。手番側の玉がなければ偽を返す。
+    pub fn is_in_check(&self) -> bool {
+        let us = self.side_to_move();
+
+        king_square(self.board(), us).is_some_and(|sq| is_attacked(self.board(), us.flip(), sq))
+    }
+
+    /// 合法手を全て返す。順序は未規定。
+    ///
+    /// 王手放置、行き所のない駒、二歩、打ち歩詰めとなる手は含まない。
+    /// 千日手は局面の履歴が必要なのでここでは考慮しない。
+    pub fn legal_moves(&self) -> Vec<Move> {
+        let us = self.side_to_move();
+        let board = self.board();
+        let mut mvs = vec![];
+
+        for src in Square::all() {
+            let Some(pc) = board[src].filter(|pc| pc.side() == us) else {
+                continue;
+            };
+            for dst in destinations(board, pc, src) {
+                let walk = MoveWalk::new(src, dst, true);
+                let can_promote = pc.kind().is_promotable()
+                    && (src.row().is_promotion_zone(us) || dst.row().is_promotion_zone(us));
+                if can_promote {
+                    mvs.push(Move::Walk(walk));
+                }
+                if !is_dead_end(us, pc.kind(), dst) {
+                    mvs.push(Move::walk(src, dst, false));
+                }
+            }
+        }
+
+        for hpk in HandPieceKind::all() {
+            if self.hands()[us][hpk] == 0 {
+                continue;
+            }
+            for dst in Square::all() {
+                if board[dst].is_some() || is_dead_end(us, hpk.into(), dst) {
+                    continue;
+                }
+                if hpk == HAND_PAWN && self.has_pawn_on_col(us, dst.col()) {
+                    continue;
+                }
+                mvs.push(Move::drop(hpk, dst));
+            }
+        }
+
+        mvs.retain(|&mv| self.is_safe_move(mv));
+
+        mvs
+    }
+
+    /// 指し手が合法かどうかを返す。`legal_moves` と同じ基準で判定する。
+    pub fn is_legal_move(&self, mv: Move) -> bool {
+        self.legal_moves().contains(&mv)
+    }
+
+    /// 手番側が詰んでいるかどうかを返す。
+    pub fn is_checkmate(&self) -> bool {
+        self.is_in_check() && self.legal_moves().is_empty()
+    }
+
+    /// 手番側が入玉宣言の条件を満たしているかどうかを返す(27 点法)。
+    ///
+    /// 玉が敵陣にあり、敵陣にある玉以外の自駒が 10 枚以上あり、王手がかかっておらず、
+    /// 敵陣の自駒と手駒の点数(大駒 5 点、小駒 1 点)が先手は 28 点以上、後手は 27 点以上であること。
+    /// 持ち時間の条件は考慮しない。
+    pub fn can_declare_win(&self) -> bool {
+        let us = self.side_to_move();
+        let board = self.board();
+
+        let Some(king_sq) = king_square(board, us) else {
+            return false;
+        };
+        if !king_sq.row().is_promotion_zone(us) || self.is_in_check() {
+            return false;
+        }
+
+        let point = |pk: PieceKind| match pk.unpromote() {
+            BISHOP | ROOK => 5,
+            _ => 1,
+        };
+
+        let mut count = 0;
+        let mut points = 0;
+        for sq in Square::all() {
+            let Some(pc) = board[sq] else {
+                continue;
+            };
+            if pc.side() == us && pc.kind() != KING && sq.row().is_promotion_zone(us) {
+                count += 1;
+                points += point(pc.kind());
+            }
+        }
+        for hpk in HandPieceKind::all() {
+            points += point(hpk.into()) * u32::from(self.hands()[us][hpk]);
+        }
+
+        let required = match us {
+            SENTE => 28,
+            GOTE => 27,
+        };
+
+        count >= 10 && points >= required
+    }
+
+    /// `side` 側の歩(成っていないもの)が筋 `col` にあるかどうかを返す。
+    fn has_pawn_on_col(&self, side: Side, col: Col) -> bool {
+        let pawn = Piece::new(side, PAWN);
+
+        Row::all()
+            .into_iter()
+            .any(|row| self.board()[Square::new(col, row)] == Some(pawn))
+    }
+
+    /// 手番側の駒種 `pk` の駒のうち、`dst` へ動いても自玉を取られないものがあるマスを全て返す。
+    ///
+    /// 指し手表記の移動元の候補に用いる。成り/不成は問わない。
+    pub(crate) fn legal_attackers(&self, pk: PieceKind, dst: Square) -> Vec<Square> {
+        let mut srcs = attackers(self.board(), self.side_to_move(), pk, dst);
+        srcs.retain(|&src| self.is_safe_move(Move::walk(src, dst, false)));
+        srcs
+    }
+
+    /// 指し手が自玉を取られる手でも打ち歩詰めでもないかどうかを返す。
+    fn is_safe_move(&self, mv: Move) -> bool {
+        let us = self.side_to_move();
+
+        let mut next = self.clone();
+        if next.do_move(mv).is_err() {
+            return false;
+        }
+
+        if let Some(king_sq) = king_square(next.board(), us) {
+            if is_attacked(next.board(), us.flip(), king_sq) {
+                return false;
+            }
+        }
+
+        !matches!(mv, Move::Drop(drop) if drop.piece_kind() == HAND_PAWN && next.is_checkmate())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr as _;
+
+    use itertools::Itertools as _;
+
+    use super::*;
+
+    #[test]
+    fn test_attackers() {
+        let board = Board::startpos();
+
+        assert_eq!(attackers(&board, SENTE, GOLD, SQ_58), [SQ_69, SQ_49]);
+        assert_eq!(attackers(&board, SENTE, PAWN, SQ_76), [SQ_77]);
+        assert_eq!(attackers(&board, GOTE, KNIGHT, SQ_73), [SQ_81]);
+        assert_eq!(attackers(&board, GOTE, KNIGHT, SQ_82), []);
+        assert_eq!(attackers(&board, SENTE, ROOK, SQ_28), []);
+        assert_eq!(attackers(&board, SENTE, ROOK, SQ_38), [SQ_28]);
+        assert_eq!(attackers(&board, SENTE, BISHOP, SQ_22), []);
+
+        let board = Board::from_str("4k4/9/9/9/4+R4/9/9/9/4K4").unwrap();
+        assert_eq!(attackers(&board, SENTE, DRAGON, SQ_51), [SQ_55]);
+        assert_eq!(attackers(&board, SENTE, DRAGON, SQ_44), [SQ_55]);
+        assert_eq!(attackers(&board, SENTE, DRAGON, SQ_33), []);
+        assert_eq!(attackers(&board, SENTE, DRAGON, SQ_59), [SQ_55]);
+    }
+
+    #[test]
+    fn test_position_legal_attackers() {
+        let pos = Position::startpos();
+        assert_eq!(pos.legal_attackers(GOLD, SQ_58), [SQ_69, SQ_49]);
+
+        // 5八金は 5 筋の飛車に対して釘付けにされている。
+        let pos = Position::from_str("4r3k/9/9/9/9/9/5G3/4G4/4K4 b - 1").unwrap();
+        assert_eq!(attackers(pos.board(), SENTE, GOLD, SQ_48), [SQ_47, SQ_58]);
+        assert_eq!(pos.legal_attackers(GOLD, SQ_48), [SQ_47]);
+        assert_eq!(pos.legal_attackers(GOLD, SQ_57), [SQ_47, SQ_58]);
+    }
+
+    #[test]
+    fn test_position_legal_moves() {
+        assert_eq!(Position::startpos().legal_moves().len(), 30);
+
+        // 王手を外す手のみ。
+        let pos = Position::from_str("4k4/9/9/9/4r4/9/9/9/4K4 b - 1").unwrap();
+        assert!(pos.is_in_check());
+        let mut mvs = pos.legal_moves();
+        mvs.sort();
+        assert_eq!(
+            mvs,
+            ["5i6h", "5i6i", "5i4h", "5i4i"]
+                .map(|s| Move::from_str(s).unwrap())
+                .into_iter()
+                .sorted()
+                .collect_vec()
+        );
+
+        // 行き所のない駒、二歩。
+        let pos = Position::from_str("4k4/P8/9/9/9/9/4P4/9/4K4 b LNP 1").unwrap();
+        assert!(pos.is_legal_move(Move::from_str("9b9a+").unwrap()));
+        assert!(!pos.is_legal_move(Move::from_str("9b9a").unwrap()));
+        assert!(!pos.is_legal_move(Move::from_str("N*1b").unwrap()));
+        assert!(pos.is_legal_move(Move::from_str("N*1c").unwrap()));
+        assert!(!pos.is_legal_move(Move::from_str("L*1a").unwrap()));
+        assert!(!pos.is_legal_move(Move::from_str("P*5d").unwrap()));
+        assert!(!pos.is_legal_move(Move::from_str("P*9d").unwrap()));
+        assert!(pos.is_legal_move(Move::from_str("P*8d").unwrap()));
+
+        // 打ち歩詰め。
+        let pos = Position::from_str("3rkr3/9/4G4/9/9/9/9/9/4K4 b P 1").unwrap();
+        assert!(!pos.is_legal_move(Move::from_str("P*5b").unwrap()));
+        let pos = Position::from_str("4k4/9/4G4/9/9/9/9/9/4K4 b P 1").unwrap();
+        assert!(pos.is_legal_move(Move::from_str("P*5b").unwrap()));
+    }
+
+    #[test]
+    fn test_position_is_checkmate() {
+        assert!(!Position::startpos().is_checkmate());
+
+        let pos = Position::from_str("4k4/4G4/4P4/9/9/9/9/9/4K4 w - 1").unwrap();
+        assert!(pos.is_checkmate());
+        let pos = Position::from_str("4k4/4G4/9/9/9/9/9/9/4K4 w - 1").unwrap();
+        assert!(!pos.is_checkmate());
+    }
+
+    #[test]
+    fn test_position_can_declare_win() {
+        // 敵陣の駒 14 枚で 22 点、手駒 6 点。
+        let pos = Position::from_str("+R+BGGGSS1K/PPPPPPP2/9/9/9/9/9/9/k8 b RP 1").unwrap();
+        assert!(pos.can_declare_win());
+
+        // 点数不足(27 点)。
+        let pos = Position::from_str("+R+BGGGSS1K/PPPPPPP2/9/9/9/9/9/9/k8 b R 1").unwrap();
+        assert!(!pos.can_declare_win());
+
+        // 後手は 27 点でよい。
+        let pos = Position::from_str("K8/9/9/9/9/9/9/2ppppppp/k1ssggg+b+r w r 1").unwrap();
+        assert!(pos.can_declare_win());
+
+        // 敵陣の駒が 9 枚。
+        let pos = Position::from_str("+R+BGGGSS1K/PP7/9/9/9/9/9/9/k8 b RBP 1").unwrap();
+        assert!(!pos.can_declare_win());
+
+        // 王手されている。
+        let pos = Position::from_str("+R+BGGGSS1K/PPPPPPP2/9/9/9/9/9/8r/k8 b RP 1").unwrap();
+        assert!(!pos.can_declare_win());
+    }
+}
@@ -0,0 +1,422 @@
+//! やねうら王の PackedSfen (局面の 256 ビット表現)。
+
+use crate::board::*;
+use crate::hand::*;
+use crate::movegen::*;
+use crate::piece::*;
+use crate::position::*;
+use crate::side::*;
+use crate::square::*;
+
+/// PackedSfen (および Apery の HuffmanCodedPos) のデコードエラー。
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PackedSfenError {
+    /// 玉の位置が不正(盤外、または両玉が同じマス)。
+    InvalidKingSquare,
+
+    /// 駒を読み終える前にビット列が尽きた。
+    UnexpectedEnd,
+
+    /// 手駒に成りフラグが立っている。
+    PromotedHandPiece,
+
+    /// 駒の枚数が平手と異なる。
+    InvalidPieceCount,
+}
+
+impl std::fmt::Display for PackedSfenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::InvalidKingSquare => f.write_str("invalid king square"),
+            Self::UnexpectedEnd => f.write_str("unexpected end of bits"),
+            Self::PromotedHandPiece => f.write_str("promoted hand piece"),
+            Self::InvalidPieceCount => f.write_str("invalid piece count"),
+        }
+    }
+}
+
+impl std::error::Error for PackedSfenError {}
+
+/// 玉を除く駒種ごとのハフマン符号 (駒種, 符号, ビット数)。符号は下位ビットから順に出力する。
+///
+/// 盤上の空きマスの符号は 1 ビットの 0。
+/// 手駒の符号は盤上の符号から先頭の 1 ビットを除いたもの(1 ビット右シフトしたもの)。
+pub(crate) type HuffmanTable = [(HandPieceKind, u8, u32); 7];
+
+/// やねうら王のハフマン符号。
+const HUFFMAN: HuffmanTable = [
+    (HAND_PAWN, 0x01, 2),
+    (HAND_LANCE, 0x03, 4),
+    (HAND_KNIGHT, 0x0B, 4),
+    (HAND_SILVER, 0x07, 4),
+    (HAND_BISHOP, 0x1F, 6),
+    (HAND_ROOK, 0x3F, 6),
+    (HAND_GOLD, 0x0F, 5),
+];
+
+/// やねうら王の手駒の出力順序。
+const HAND_ORDER: [HandPieceKind; 7] = [
+    HAND_PAWN,
+    HAND_LANCE,
+    HAND_KNIGHT,
+    HAND_SILVER,
+    HAND_BISHOP,
+    HAND_ROOK,
+    HAND_GOLD,
+];
+
+/// 平手での駒種ごとの枚数。
+pub(crate) const fn standard_count(hpk: HandPieceKind) -> u32 {
+    match hpk {
+        HAND_PAWN => 18,
+        HAND_BISHOP | HAND_ROOK => 2,
+        _ => 4,
+    }
+}
+
+/// 下位ビットから順に詰める 256 ビットのビット列の書き込み。
+pub(crate) struct BitWriter {
+    data: [u8; 32],
+    cursor: usize,
+}
+
+impl BitWriter {
+    pub(crate) fn new() -> Self {
+        Self {
+            data: [0; 32],
+            cursor: 0,
+        }
+    }
+
+    pub(crate) fn write_bit(&mut self, bit: bool) {
+        if bit {
+            self.data[self.cursor / 8] |= 1 << (self.cursor % 8);
+        }
+        self.cursor += 1;
+    }
+
+    /// `value` の下位 `n` ビットを下位ビットから順に出力する。
+    pub(crate) fn write_bits(&mut self, value: u32, n: u32) {
+        for i in 0..n {
+            self.write_bit(value & (1 << i) != 0);
+        }
+    }
+
+    /// 盤上の駒種 `hpk` (成駒は成る前の駒種)のハフマン符号を出力する。
+    pub(crate) fn write_board_huffman(&mut self, table: &HuffmanTable, hpk: HandPieceKind) {
+        let (code, bits) = huffman(table, hpk);
+        self.write_bits(code, bits);
+    }
+
+    /// 手駒の駒種 `hpk` のハフマン符号を出力する。
+    pub(crate) fn write_hand_huffman(&mut self, table: &HuffmanTable, hpk: HandPieceKind) {
+        let (code, bits) = huffman(table, hpk);
+        self.write_bits(code >> 1, bits - 1);
+    }
+
+    pub(crate) fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub(crate) fn into_data(self) -> [u8; 32] {
+        self.data
+    }
+}
+
+/// `BitWriter` で書き込んだビット列の読み込み。
+pub(crate) struct BitReader<'a> {
+    data: &'a [u8; 32],
+    cursor: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub(crate) fn new(data: &'a [u8; 32]) -> Self {
+        Self { data, cursor: 0 }
+    }
+
+    pub(crate) fn is_end(&self) -> bool {
+        self.cursor == 256
+    }
+
+    pub(crate) fn read_bit(&mut self) -> Result<bool, PackedSfenError> {
+        if self.is_end() {
+            return Err(PackedSfenError::UnexpectedEnd);
+        }
+        let bit = self.data[self.cursor / 8] & (1 << (self.cursor % 8)) != 0;
+        self.cursor += 1;
+        Ok(bit)
+    }
+
+    pub(crate) fn read_bits(&mut self, n: u32) -> Result<u32, PackedSfenError> {
+        let mut value = 0;
+        for i in 0..n {
+            value |= u32::from(self.read_bit()?) << i;
+        }
+        Ok(value)
+    }
+
+    /// 盤上の駒のハフマン符号を読む。空きマスなら `None` を返す。
+    pub(crate) fn read_board_huffman(
+        &mut self,
+        table: &HuffmanTable,
+    ) -> Result<Option<HandPieceKind>, PackedSfenError> {
+        self.read_huffman(table, 0)
+    }
+
+    /// 手駒のハフマン符号を読む。
+    pub(crate) fn read_hand_huffman(
+        &mut self,
+        table: &HuffmanTable,
+    ) -> Result<HandPieceKind, PackedSfenError> {
+        self.read_huffman(table, 1).map(Option::unwrap)
+    }
+
+    /// ハフマン符号を読む。`shift` は手駒なら 1、盤上の駒なら 0。
+    fn read_huffman(
+        &mut self,
+        table: &HuffmanTable,
+        shift: u32,
+    ) -> Result<Option<HandPieceKind>, PackedSfenError> {
+        let mut code = 0;
+        let mut bits = 0;
+        loop {
+            code |= u32::from(self.read_bit()?) << bits;
+            bits += 1;
+
+            if shift == 0 && (code, bits) == (0, 1) {
+                return Ok(None);
+            }
+            let found = table
+                .iter()
+                .copied()
+                .find(|&(_, c, b)| (u32::from(c) >> shift, b - shift) == (code, bits));
+            if let Some((hpk, _, _)) = found {
+                return Ok(Some(hpk));
+            }
+        }
+    }
+}
+
+fn huffman(table: &HuffmanTable, hpk: HandPieceKind) -> (u32, u32) {
+    let (_, code, bits) = table.iter().copied().find(|&(h, _, _)| h == hpk).unwrap();
+    (u32::from(code), bits)
+}
+
+impl Position {
+    /// やねうら王の PackedSfen (32 バイト) を返す。手数は含まれない。
+    ///
+    /// 手番 1 ビット、両玉の位置 7 ビットずつ、盤上の玉以外の駒(1一, 1二, ..., 9九 の順)、
+    /// 手駒(先手、後手の順)をハフマン符号化して下位ビットから順に詰める。
+    ///
+    /// # Panics
+    ///
+    /// 両玉がない局面、または玉以外の駒の枚数(盤上と手駒の合計)が平手と異なる局面は
+    /// 256 ビットで表現できないため、panic する。
+    pub fn to_packed_sfen(&self) -> [u8; 32] {
+        assert!(
+            is_packable(self),
+            "PackedSfen requires both kings and the standard set of pieces"
+        );
+
+        let mut w = BitWriter::new();
+
+        w.write_bit(self.side_to_move() == GOTE);
+        for side in [SENTE, GOTE] {
+            let sq = king_square(self.board(), side).unwrap();
+            w.write_bits(u32::from(sq.to_col_major_index()), 7);
+        }
+
+        for i in 0..81 {
+            let sq = Square::from_col_major_index(i).unwrap();
+            match self.board()[sq] {
+                None => w.write_bit(false),
+                Some(pc) if pc.kind() == KING => {}
+                Some(pc) => {
+                    let hpk = HandPieceKind::try_from(pc.kind().unpromote()).unwrap();
+                    w.write_board_huffman(&HUFFMAN, hpk);
+                    if hpk != HAND_GOLD {
+                        w.write_bit(pc.kind().is_promoted());
+                    }
+                    w.write_bit(pc.side() == GOTE);
+                }
+            }
+        }
+
+        for side in [SENTE, GOTE] {
+            for hpk in HAND_ORDER {
+                for _ in 0..self.hands()[side][hpk] {
+                    w.write_hand_huffman(&HUFFMAN, hpk);
+                    if hpk != HAND_GOLD {
+                        w.write_bit(false);
+                    }
+                    w.write_bit(side == GOTE);
+                }
+            }
+        }
+
+        debug_assert_eq!(w.cursor(), 256);
+
+        w.into_data()
+    }
+
+    /// やねうら王の PackedSfen (32 バイト) から局面を作る。手数は 1 とする。
+    pub fn from_packed_sfen(packed: &[u8; 32]) -> Result<Self, PackedSfenError> {
+        let mut r = BitReader::new(packed);
+
+        let side_to_move = if r.read_bit()? { GOTE } else { SENTE };
+
+        let mut board = Board::empty();
+        let mut king_sqs = vec![];
+        for side in [SENTE, GOTE] {
+            let i = r.read_bits(7)? as u8;
+            let sq = Square::from_col_major_index(i).ok_or(PackedSfenError::InvalidKingSquare)?;
+            if king_sqs.contains(&sq) {
+                return Err(PackedSfenError::InvalidKingSquare);
+            }
+            king_sqs.push(sq);
+            board[sq] = Some(Piece::new(side, KING));
+        }
+
+        for i in 0..81 {
+            let sq = Square::from_col_major_index(i).unwrap();
+            if king_sqs.contains(&sq) {
+                continue;
+            }
+            let Some(hpk) = r.read_board_huffman(&HUFFMAN)? else {
+                continue;
+            };
+            let promo = hpk != HAND_GOLD && r.read_bit()?;
+            let side = if r.read_bit()? { GOTE } else { SENTE };
+
+            let pk = PieceKind::from(hpk);
+            let pk = if promo { pk.promote().unwrap() } else { pk };
+            board[sq] = Some(Piece::new(side, pk));
+        }
+
+        let mut hands = Hands::empty();
+        while !r.is_end() {
+            let hpk = r.read_hand_huffman(&HUFFMAN)?;
+            if hpk != HAND_GOLD && r.read_bit()? {
+                return Err(PackedSfenError::PromotedHandPiece);
+            }
+            let side = if r.read_bit()? { GOTE } else { SENTE };
+
+            let count = &mut hands[side][hpk];
+            *count = count.saturating_add(1);
+        }
+
+        let pos = Self::new(side_to_move, board, hands, PLY_1);
+        if !is_packable(&pos) {
+            return Err(PackedSfenError::InvalidPieceCount);
+        }
+
+        Ok(pos)
+    }
+}
+
+/// 両玉があり、玉以外の駒の枚数(盤上と手駒の合計)が平手と同じかどうかを返す。
+pub(crate) fn is_packable(pos: &Position) -> bool {
+    let has_kings = [SENTE, GOTE].into_iter().all(|side| {
+        Square::all()
+            .into_iter()
+            .filter(|&sq| pos.board()[sq] == Some(Piece::new(side, KING)))
+            .count()
+            == 1
+    });
+
+    has_kings
+        && HAND_ORDER.into_iter().all(|hpk| {
+            let on_board = Square::all()
+                .into_iter()
+                .filter_map(|sq| pos.board()[sq])
+                .filter(|pc| pc.kind().unpromote() == PieceKind::from(hpk))
+                .count() as u32;
+            let in_hand = u32::from(pos.hands()[SENTE][hpk]) + u32::from(pos.hands()[GOTE][hpk]);
+
+            on_board + in_hand == standard_count(hpk)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr as _;
+
+    use super::*;
+
+    #[test]
+    fn test_position_packed_sfen() {
+        let startpos = Position::startpos();
+        let packed = startpos.to_packed_sfen();
+
+        // 手番 (先手 = 0)、先手玉 5九 (= 44)。
+        assert_eq!(packed[0], 44 << 1);
+        // 後手玉 5一 (= 36)、1一 の後手香の符号の先頭ビット (1)。
+        assert_eq!(packed[1], 36 | 0x80);
+        assert_eq!(Position::from_packed_sfen(&packed), Ok(startpos));
+
+        for sfen in [
+            "sfen lnsgkgsnl/1r5b1/ppppppppp/9/9/2P6/PP1PPPPPP/1B5R1/LNSGKGSNL w - 1",
+            "sfen ln1g3nl/1r1s1kgs1/p1pppp1pp/6p2/1p7/2P6/PPSPPPPPP/7R1/LN1GKGSNL w Bb 1",
+            "sfen 8l/1+R5k1/p1+Bpppg1p/6p2/9/2P6/P2PPPP1P/1+p3K3/L+n2G3L b RBG3SNL2Pgs2n2p 1",
+            "sfen 4k4/9/9/9/9/9/9/9/4K4 b 2R2B4G4S4N4L9P9p 1",
+        ] {
+            let pos = Position::from_str(sfen).unwrap();
+            let packed = pos.to_packed_sfen();
+            assert_eq!(Position::from_packed_sfen(&packed), Ok(pos), "{sfen}");
+        }
+    }
+
+    #[test]
+    fn test_position_packed_sfen_error() {
+        let mut packed = Position::startpos().to_packed_sfen();
+        // 先手玉のマスを 127 にする。
+        packed[0] |= 0xFE;
+        assert_eq!(
+            Position::from_packed_sfen(&packed),
+            Err(PackedSfenError::InvalidKingSquare)
+        );
+
+        // 両玉が 1一。
+        assert_eq!(
+            Position::from_packed_sfen(&[0; 32]),
+            Err(PackedSfenError::InvalidKingSquare)
+        );
+
+        // 先手玉が 1一、後手玉が 1二 で、盤上が全て後手の竜になり、ビット列が尽きる。
+        let mut packed = [0xFF; 32];
+        packed[0] = 0;
+        packed[1] = 0x80 | 1;
+        assert_eq!(
+            Position::from_packed_sfen(&packed),
+            Err(PackedSfenError::UnexpectedEnd)
+        );
+
+        // 先手玉が 1一、後手玉が 1二 で、盤上の他のマスが空になり、残りが先手の手駒の歩 (1 ビットずつ) で埋まる。
+        let mut packed = [0; 32];
+        packed[1] = 1;
+        assert_eq!(
+            Position::from_packed_sfen(&packed),
+            Err(PackedSfenError::InvalidPieceCount)
+        );
+
+        // 先頭の手駒 (先手の歩) の成りフラグ (15 + 79 + 1 = 95 ビット目) を立てる。
+        let pos = Position::from_str("sfen 4k4/9/9/9/9/9/9/9/4K4 b 2R2B4G4S4N4L18P 1").unwrap();
+        let mut packed = pos.to_packed_sfen();
+        packed[11] |= 1 << 7;
+        assert_eq!(
+            Position::from_packed_sfen(&packed),
+            Err(PackedSfenError::PromotedHandPiece)
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_position_packed_sfen_handicap() {
+        let pos = Position::from_str(
+            "sfen lnsgkgsnl/7b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+        )
+        .unwrap();
+        pos.to_packed_sfen();
+    }
+}
@@ -0,0 +1,420 @@
+//! やねうら王の学習用データ (PackedSfenValue) の読み書き。
+
+use std::io::{Read, Write};
+use std::num::NonZeroU32;
+
+use crate::move_::*;
+use crate::packed_sfen::*;
+use crate::position::*;
+
+/// やねうら王の学習用データの 1 レコード (PackedSfenValue)。
+///
+/// ファイル上では 40 バイトで、PackedSfen (32 バイト)、評価値 (i16)、指し手 (u16)、
+/// 手数 (u16)、勝敗 (i8)、パディング (1 バイト) をリトルエンディアンで並べたもの。
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct PackedSfenValue {
+    /// 局面。手数はレコードの手数 (gamePly) とする(0 の場合は 1)。
+    pub position: Position,
+
+    /// 局面の評価値(手番側から見た値)。
+    pub score: i16,
+
+    /// 局面で指された手。
+    pub mv: Move,
+
+    /// 対局結果(手番側から見て、勝ち = 1, 引き分け = 0, 負け = -1)。
+    pub result: i8,
+}
+
+/// PackedSfenValue のデコードエラー。
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PackedSfenValueDecodeError {
+    /// 局面が不正。
+    InvalidPosition(PackedSfenError),
+
+    /// 指し手が不正。
+    InvalidMove(MoveDecodeError),
+}
+
+impl std::fmt::Display for PackedSfenValueDecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::InvalidPosition(e) => write!(f, "invalid position: {e}"),
+            Self::InvalidMove(e) => write!(f, "invalid move: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for PackedSfenValueDecodeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::InvalidPosition(e) => Some(e),
+            Self::InvalidMove(e) => Some(e),
+        }
+    }
+}
+
+impl PackedSfenValue {
+    /// 1 レコードのバイト数。
+    pub const SIZE: usize = 40;
+
+    /// 1 レコード分のバイト列を返す。
+    ///
+    /// 手数は `u16` に飽和させる。
+    ///
+    /// # Panics
+    ///
+    /// 局面を PackedSfen で表現できない場合 (`Position::to_packed_sfen` を参照)、panic する。
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let ply = u16::try_from(self.position.ply().get()).unwrap_or(u16::MAX);
+
+        let mut bytes = [0; Self::SIZE];
+        bytes[0..32].copy_from_slice(&self.position.to_packed_sfen());
+        bytes[32..34].copy_from_slice(&self.score.to_le_bytes());
+        bytes[34..36].copy_from_slice(&self.mv.to_u16().to_le_bytes());
+        bytes[36..38].copy_from_slice(&ply.to_le_bytes());
+        bytes[38] = self.result as u8;
+
+        bytes
+    }
+
+    /// 1 レコード分のバイト列をデコードする。パディングは無視する。
+    pub fn from_bytes(bytes: &[u8; Self::SIZE]) -> Result<Self, PackedSfenValueDecodeError> {
+        let packed: &[u8; 32] = bytes[0..32].try_into().unwrap();
+        let score = i16::from_le_bytes([bytes[32], bytes[33]]);
+        let mv = u16::from_le_bytes([bytes[34], bytes[35]]);
+        let ply = u16::from_le_bytes([bytes[36], bytes[37]]);
+        let result = bytes[38] as i8;
+
+        let pos = Position::from_packed_sfen(packed)
+            .map_err(PackedSfenValueDecodeError::InvalidPosition)?;
+        let ply = NonZeroU32::new(u32::from(ply)).unwrap_or(PLY_1);
+        let position = Position::new(
+            pos.side_to_move(),
+            pos.board().clone(),
+            pos.hands().clone(),
+            ply,
+        );
+        let mv = Move::from_u16(mv).map_err(PackedSfenValueDecodeError::InvalidMove)?;
+
+        Ok(Self {
+            position,
+            score,
+            mv,
+            result,
+        })
+    }
+}
+
+/// 学習用データの読み込みエラー。`E` はレコードのデコードエラー。
+#[non_exhaustive]
+#[derive(Debug)]
+pub enum TrainingDataReadError<E> {
+    /// 入出力エラー。
+    Io(std::io::Error),
+
+    /// ファイル末尾のレコードが `len` バイトで途切れている。
+    Truncated { len: usize },
+
+    /// `index` 番目(0 始まり)のレコードが不正。
+    InvalidRecord { index: u64, error: E },
+}
+
+impl<E: std::fmt::Display> std::fmt::Display for TrainingDataReadError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "I/O error: {e}"),
+            Self::Truncated { len } => write!(f, "last record is truncated ({len} bytes)"),
+            Self::InvalidRecord { index, error } => write!(f, "invalid record {index}: {error}"),
+        }
+    }
+}
+
+impl<E: std::error::Error + 'static> std::error::Error for TrainingDataReadError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::InvalidRecord { error, .. } => Some(error),
+            Self::Truncated { .. } => None,
+        }
+    }
+}
+
+/// PackedSfenValue の読み込みエラー。
+pub type PackedSfenValueReadError = TrainingDataReadError<PackedSfenValueDecodeError>;
+
+/// 学習用データをストリームから 1 レコードずつ読み込むイテレータ。各形式のリーダーの実体。
+///
+/// 不正なレコードはエラーを返し、次のレコードから読み込みを続ける。
+/// ファイル末尾のレコードが途切れていれば `TrainingDataReadError::Truncated` を返して終了する。
+/// 入出力エラーの後も終了する。
+#[derive(Debug)]
+pub(crate) struct TrainingDataReader<R, T, E> {
+    inner: R,
+    index: u64,
+    done: bool,
+    buf: Vec<u8>,
+    read: fn(&mut R, &mut Vec<u8>) -> std::io::Result<bool>,
+    decode: fn(&[u8]) -> Result<T, E>,
+}
+
+impl<R: Read, T, E> TrainingDataReader<R, T, E> {
+    /// `read` で 1 レコード分のバイト列を読み込み、`decode` でデコードするリーダーを作る。
+    ///
+    /// `read` は空のバッファにバイト列を追加し、レコードが途切れていたら `false` を返す。
+    pub(crate) fn new(
+        inner: R,
+        read: fn(&mut R, &mut Vec<u8>) -> std::io::Result<bool>,
+        decode: fn(&[u8]) -> Result<T, E>,
+    ) -> Self {
+        Self {
+            inner,
+            index: 0,
+            done: false,
+            buf: vec![],
+            read,
+            decode,
+        }
+    }
+
+    /// `N` バイトの固定長レコードを読み込むリーダーを作る。
+    pub(crate) fn fixed<const N: usize>(inner: R, decode: fn(&[u8]) -> Result<T, E>) -> Self {
+        Self::new(inner, |inner, buf| read_bytes(inner, buf, N), decode)
+    }
+
+    pub(crate) fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read, T, E> Iterator for TrainingDataReader<R, T, E> {
+    type Item = Result<T, TrainingDataReadError<E>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        self.buf.clear();
+        let complete = match (self.read)(&mut self.inner, &mut self.buf) {
+            Ok(complete) => complete,
+            Err(e) => {
+                self.done = true;
+                return Some(Err(TrainingDataReadError::Io(e)));
+            }
+        };
+        if !complete {
+            self.done = true;
+            let len = self.buf.len();
+            return (len > 0).then_some(Err(TrainingDataReadError::Truncated { len }));
+        }
+
+        let index = self.index;
+        self.index += 1;
+
+        Some(
+            (self.decode)(&self.buf)
+                .map_err(|error| TrainingDataReadError::InvalidRecord { index, error }),
+        )
+    }
+}
+
+/// `buf` の末尾に `n` バイト読み込む。途中でストリームの終端に達したら `false` を返す。
+pub(crate) fn read_bytes(
+    inner: &mut impl Read,
+    buf: &mut Vec<u8>,
+    n: usize,
+) -> std::io::Result<bool> {
+    let start = buf.len();
+    buf.resize(start + n, 0);
+
+    let mut len = 0;
+    while len < n {
+        match inner.read(&mut buf[start + len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    buf.truncate(start + len);
+
+    Ok(len == n)
+}
+
+/// PackedSfenValue をストリームから 1 レコードずつ読み込むイテレータ。
+///
+/// 不正なレコードはエラーを返し、次のレコードから読み込みを続ける。
+/// ファイル末尾のレコードが途切れていれば `PackedSfenValueReadError::Truncated` を返して終了する。
+/// 入出力エラーの後も終了する。
+#[derive(Debug)]
+pub struct PackedSfenValueReader<R>(
+    TrainingDataReader<R, PackedSfenValue, PackedSfenValueDecodeError>,
+);
+
+impl<R: Read> PackedSfenValueReader<R> {
+    /// ストリームから読み込むリーダーを作る。大きなファイルでは `BufReader` で包むとよい。
+    pub fn new(inner: R) -> Self {
+        Self(TrainingDataReader::fixed::<{ PackedSfenValue::SIZE }>(
+            inner,
+            |bytes| PackedSfenValue::from_bytes(bytes.try_into().unwrap()),
+        ))
+    }
+
+    /// 内部のストリームを返す。
+    pub fn into_inner(self) -> R {
+        self.0.into_inner()
+    }
+}
+
+impl<R: Read> Iterator for PackedSfenValueReader<R> {
+    type Item = Result<PackedSfenValue, PackedSfenValueReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+}
+
+/// 学習用データをストリームに 1 レコードずつ書き込む。
+#[derive(Debug)]
+pub struct PackedSfenValueWriter<W> {
+    inner: W,
+}
+
+impl<W: Write> PackedSfenValueWriter<W> {
+    /// ストリームに書き込むライターを作る。大きなファイルでは `BufWriter` で包むとよい。
+    pub fn new(inner: W) -> Self {
+        Self { inner }
+    }
+
+    /// 1 レコード書き込む。
+    ///
+    /// # Panics
+    ///
+    /// 局面を PackedSfen で表現できない場合、panic する。
+    pub fn write(&mut self, psv: &PackedSfenValue) -> std::io::Result<()> {
+        self.inner.write_all(&psv.to_bytes())
+    }
+
+    /// 内部のストリームをフラッシュする。
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+
+    /// 内部のストリームを返す。
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr as _;
+
+    use super::*;
+
+    fn psv(sfen: &str, score: i16, mv: &str, result: i8) -> PackedSfenValue {
+        PackedSfenValue {
+            position: Position::from_str(sfen).unwrap(),
+            score,
+            mv: Move::from_str(mv).unwrap(),
+            result,
+        }
+    }
+
+    #[test]
+    fn test_packed_sfen_value() {
+        let records = [
+            psv("startpos", 50, "7g7f", 1),
+            psv(
+                "sfen lnsgkgsnl/1r5b1/ppppppppp/9/9/2P6/PP1PPPPPP/1B5R1/LNSGKGSNL w - 2",
+                -40,
+                "3c3d",
+                -1,
+            ),
+            psv(
+                "sfen ln1g3nl/1r1s1kgs1/p1pppp1pp/6p2/1p7/2P6/PPSPPPPPP/7R1/LN1GKGSNL w Bb 24",
+                0,
+                "B*5e",
+                0,
+            ),
+        ];
+
+        let bytes = records[0].to_bytes();
+        assert_eq!(&bytes[32..34], 50_i16.to_le_bytes());
+        // 7七 (= 60) → 7六 (= 59)。
+        assert_eq!(&bytes[34..36], (59_u16 | (60 << 7)).to_le_bytes());
+        assert_eq!(&bytes[36..38], 1_u16.to_le_bytes());
+        assert_eq!(bytes[38], 1);
+        assert_eq!(bytes[39], 0);
+
+        // 駒打ち: 5五 (= 40) に角 (= 5) を打つ。
+        let bytes = records[2].to_bytes();
+        assert_eq!(
+            &bytes[34..36],
+            (40_u16 | (5 << 7) | (1 << 14)).to_le_bytes()
+        );
+
+        // 成り: 8八 (= 70) → 2二 (= 10)。
+        let promo = psv(
+            "sfen lnsgkgsnl/1r5b1/pppppp1pp/6p2/9/2P6/PP1PPPPPP/1B5R1/LNSGKGSNL b - 5",
+            120,
+            "8h2b+",
+            1,
+        );
+        let bytes = promo.to_bytes();
+        assert_eq!(
+            &bytes[34..36],
+            (10_u16 | (70 << 7) | (1 << 15)).to_le_bytes()
+        );
+        assert_eq!(PackedSfenValue::from_bytes(&bytes), Ok(promo));
+
+        // 指し手が不正 (MOVE_NONE)。
+        let mut bytes = records[0].to_bytes();
+        bytes[34..36].copy_from_slice(&0_u16.to_le_bytes());
+        assert_eq!(
+            PackedSfenValue::from_bytes(&bytes),
+            Err(PackedSfenValueDecodeError::InvalidMove(
+                MoveDecodeError::InvalidSrc
+            ))
+        );
+
+        let mut writer = PackedSfenValueWriter::new(vec![]);
+        for record in &records {
+            writer.write(record).unwrap();
+        }
+        let mut data = writer.into_inner();
+        assert_eq!(data.len(), 3 * PackedSfenValue::SIZE);
+
+        let read: Vec<_> = PackedSfenValueReader::new(data.as_slice())
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(read, records);
+
+        // 末尾が途切れている。
+        data.truncate(2 * PackedSfenValue::SIZE + 7);
+        let mut reader = PackedSfenValueReader::new(data.as_slice());
+        assert_eq!(reader.next().unwrap().unwrap(), records[0]);
+        assert_eq!(reader.next().unwrap().unwrap(), records[1]);
+        assert!(matches!(
+            reader.next(),
+            Some(Err(PackedSfenValueReadError::Truncated { len: 7 }))
+        ));
+        assert!(reader.next().is_none());
+
+        // 不正なレコードは飛ばして読み続けられる。
+        let mut data = vec![0; PackedSfenValue::SIZE];
+        data.extend_from_slice(&records[2].to_bytes());
+        let mut reader = PackedSfenValueReader::new(data.as_slice());
+        assert!(matches!(
+            reader.next(),
+            Some(Err(PackedSfenValueReadError::InvalidRecord {
+                index: 0,
+                error: PackedSfenValueDecodeError::InvalidPosition(_),
+            }))
+        ));
+        assert_eq!(reader.next().unwrap().unwrap(), records[2]);
+        assert!(reader.next().is_none());
+    }
+}
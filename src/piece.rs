@@ -36,6 +36,82 @@ pub const PRO_SILVER: PieceKind = PieceKind::ProSilver;
 pub const HORSE: PieceKind = PieceKind::Horse;
 pub const DRAGON: PieceKind = PieceKind::Dragon;
 
+impl PieceKind {
+    const NUM: usize = 14;
+
+    /// 全ての駒種を返す。順序は未規定。
+    pub const fn all() -> [Self; Self::NUM] {
+        [
+            PAWN, LANCE, KNIGHT, SILVER, BISHOP, ROOK, GOLD, KING, PRO_PAWN, PRO_LANCE, PRO_KNIGHT,
+            PRO_SILVER, HORSE, DRAGON,
+        ]
+    }
+
+    /// 成れる駒種かどうかを返す。
+    pub const fn is_promotable(self) -> bool {
+        self.promote().is_some()
+    }
+
+    /// 成駒かどうかを返す。
+    pub const fn is_promoted(self) -> bool {
+        matches!(
+            self,
+            PRO_PAWN | PRO_LANCE | PRO_KNIGHT | PRO_SILVER | HORSE | DRAGON
+        )
+    }
+
+    /// 成った後の駒種を返す。成れない駒種なら `None` を返す。
+    pub const fn promote(self) -> Option<Self> {
+        match self {
+            PAWN => Some(PRO_PAWN),
+            LANCE => Some(PRO_LANCE),
+            KNIGHT => Some(PRO_KNIGHT),
+            SILVER => Some(PRO_SILVER),
+            BISHOP => Some(HORSE),
+            ROOK => Some(DRAGON),
+            _ => None,
+        }
+    }
+
+    /// 成る前の駒種を返す。成駒でなければ自身を返す。
+    pub const fn unpromote(self) -> Self {
+        match self {
+            PRO_PAWN => PAWN,
+            PRO_LANCE => LANCE,
+            PRO_KNIGHT => KNIGHT,
+            PRO_SILVER => SILVER,
+            HORSE => BISHOP,
+            DRAGON => ROOK,
+            _ => self,
+        }
+    }
+
+    /// CSA 形式の駒種名 (`FU`, `TO` など) を返す。
+    pub(crate) const fn to_csa(self) -> &'static str {
+        match self {
+            PAWN => "FU",
+            LANCE => "KY",
+            KNIGHT => "KE",
+            SILVER => "GI",
+            GOLD => "KI",
+            BISHOP => "KA",
+            ROOK => "HI",
+            KING => "OU",
+            PRO_PAWN => "TO",
+            PRO_LANCE => "NY",
+            PRO_KNIGHT => "NK",
+            PRO_SILVER => "NG",
+            HORSE => "UM",
+            DRAGON => "RY",
+        }
+    }
+
+    /// CSA 形式の駒種名をパースする。
+    pub(crate) fn from_csa(s: &str) -> Option<Self> {
+        Self::all().into_iter().find(|pk| pk.to_csa() == s)
+    }
+}
+
 /// 駒(先後の区別あり)。
 #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub enum Piece {
@@ -4,9 +4,15 @@ use std::num::NonZeroU32;
 use crate::board::*;
 use crate::bytes::Bytes;
 use crate::hand::*;
+use crate::move_::*;
 use crate::parse::*;
+use crate::piece::*;
+use crate::pretty::*;
 use crate::side::*;
 
+/// 1 手目を表す手数。
+pub(crate) const PLY_1: NonZeroU32 = NonZeroU32::new(1).unwrap();
+
 /// 局面。
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub struct Position {
@@ -61,6 +67,74 @@ impl Position {
         self.ply
     }
 
+    /// 手数を 1 とした局面を返す。
+    ///
+    /// 千日手の判定や定跡の検索など、手数を無視して局面を比較する際のキーに用いる。
+    pub(crate) fn without_ply(&self) -> Self {
+        Self {
+            ply: PLY_1,
+            ..self.clone()
+        }
+    }
+
+    /// 指し手を適用し、手番を反転して手数を 1 進める。
+    ///
+    /// 駒の有無、成りの可否、手駒の有無のみを検査する。
+    /// 駒の利きや王手放置などの合法性は検査しない。
+    /// エラーの場合、局面は変更されない。
+    pub fn do_move(&mut self, mv: Move) -> Result<(), MoveError> {
+        let us = self.side_to_move;
+
+        match mv {
+            Move::Walk(walk) => {
+                let pc = self.board[walk.src()]
+                    .filter(|pc| pc.side() == us)
+                    .ok_or(MoveError::NoOwnPieceAtSrc)?;
+                let captured = self.board[walk.dst()];
+                if captured.is_some_and(|pc| pc.side() == us) {
+                    return Err(MoveError::OwnPieceAtDst);
+                }
+
+                let pk_after = if walk.is_promotion() {
+                    let in_zone = walk.src().row().is_promotion_zone(us)
+                        || walk.dst().row().is_promotion_zone(us);
+                    match pc.kind().promote() {
+                        Some(pk) if in_zone => pk,
+                        _ => return Err(MoveError::CannotPromote),
+                    }
+                } else {
+                    pc.kind()
+                };
+
+                if let Some(captured) = captured {
+                    // 玉を取る手は手駒に加えない。
+                    if let Ok(hpk) = HandPieceKind::try_from(captured.kind().unpromote()) {
+                        let n = &mut self.hands[us][hpk];
+                        *n = n.saturating_add(1);
+                    }
+                }
+                self.board[walk.src()] = None;
+                self.board[walk.dst()] = Some(Piece::new(us, pk_after));
+            }
+            Move::Drop(drop) => {
+                if self.hands[us][drop.piece_kind()] == 0 {
+                    return Err(MoveError::NotInHand);
+                }
+                if self.board[drop.dst()].is_some() {
+                    return Err(MoveError::DropOnPiece);
+                }
+
+                self.hands[us][drop.piece_kind()] -= 1;
+                self.board[drop.dst()] = Some(Piece::new(us, drop.piece_kind().into()));
+            }
+        }
+
+        self.side_to_move = us.flip();
+        self.ply = self.ply.saturating_add(1);
+
+        Ok(())
+    }
+
     pub(crate) fn parse(bytes: Bytes) -> SfenParseResult<(Bytes, Self)> {
         // "position"? ("startpos" | "sfen"? Board Side Hands Ply)
 
@@ -137,7 +211,13 @@ impl std::fmt::Display for Position {
     /// 平手初期局面なら "startpos" を出力する。
     ///
     /// "position" は付けない。平手初期局面以外の場合、"sfen" は付ける。
+    ///
+    /// `{:#}` の場合は端末向けの表示 (`PrettyStyle::Unicode`) を出力する。
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if f.alternate() {
+            return self.pretty(PrettyStyle::Unicode).fmt(f);
+        }
+
         if self.is_startpos() {
             return f.write_str("startpos");
         }
@@ -155,6 +235,41 @@ impl std::fmt::Display for Position {
     }
 }
 
+/// 指し手を局面に適用できないことを表すエラー。
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MoveError {
+    /// 移動元に手番側の駒がない。
+    NoOwnPieceAtSrc,
+
+    /// 移動先に手番側の駒がある。
+    OwnPieceAtDst,
+
+    /// 成れない駒種または位置で成ろうとした。
+    CannotPromote,
+
+    /// 打とうとした駒が手駒にない。
+    NotInHand,
+
+    /// 駒があるマスに打とうとした。
+    DropOnPiece,
+}
+
+impl std::fmt::Display for MoveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let s = match self {
+            Self::NoOwnPieceAtSrc => "no piece of side to move at source square",
+            Self::OwnPieceAtDst => "piece of side to move at destination square",
+            Self::CannotPromote => "cannot promote",
+            Self::NotInHand => "piece to drop is not in hand",
+            Self::DropOnPiece => "cannot drop on occupied square",
+        };
+        f.write_str(s)
+    }
+}
+
+impl std::error::Error for MoveError {}
+
 #[cfg(test)]
 mod tests {
     use std::str::FromStr as _;
@@ -163,8 +278,6 @@ mod tests {
 
     use super::*;
 
-    const PLY_1: NonZeroU32 = unsafe { NonZeroU32::new_unchecked(1) };
-
     fn empty_position() -> Position {
         Position::new(SENTE, Board::empty(), Hands::empty(), PLY_1)
     }
@@ -218,6 +331,44 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_position_do_move() {
+        let mut pos = Position::startpos();
+        for mv in ["7g7f", "3c3d", "8h2b+", "3a2b", "B*4e"] {
+            pos.do_move(Move::from_str(mv).unwrap()).unwrap();
+        }
+        assert_eq!(
+            pos,
+            Position::from_str(
+                "sfen lnsgkg1nl/1r5s1/pppppp1pp/6p2/5B3/2P6/PP1PPPPPP/7R1/LNSGKGSNL w b 6"
+            )
+            .unwrap()
+        );
+
+        let mut pos = Position::startpos();
+        assert_eq!(
+            pos.do_move(Move::from_str("5e5d").unwrap()),
+            Err(MoveError::NoOwnPieceAtSrc)
+        );
+        assert_eq!(
+            pos.do_move(Move::from_str("5a5b").unwrap()),
+            Err(MoveError::NoOwnPieceAtSrc)
+        );
+        assert_eq!(
+            pos.do_move(Move::from_str("5i4i").unwrap()),
+            Err(MoveError::OwnPieceAtDst)
+        );
+        assert_eq!(
+            pos.do_move(Move::from_str("7g7f+").unwrap()),
+            Err(MoveError::CannotPromote)
+        );
+        assert_eq!(
+            pos.do_move(Move::from_str("P*5e").unwrap()),
+            Err(MoveError::NotInHand)
+        );
+        assert_eq!(pos, Position::startpos());
+    }
+
     #[test]
     fn test_position_fmt() {
         assert_eq!(Position::startpos().to_string(), "startpos");
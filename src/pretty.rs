@@ -0,0 +1,203 @@
+//! 盤面、局面の端末向け表示。
+
+use std::fmt::Write as _;
+
+use crate::board::*;
+use crate::bod::*;
+use crate::japanese::*;
+use crate::position::*;
+use crate::side::*;
+use crate::square::*;
+
+/// 端末向け表示の形式。
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum PrettyStyle {
+    /// ASCII 文字のみを使う。駒は SFEN 駒文字で表し、後手の駒は小文字になる。
+    Ascii,
+
+    /// 漢字で駒を表す。後手の駒には `v` を付ける(BOD 形式と同様)。
+    Unicode,
+
+    /// 漢字で駒を表し、後手の駒を ANSI エスケープシーケンスで色付けする。
+    UnicodeColor,
+}
+
+const ANSI_GOTE: &str = "\x1b[31m";
+const ANSI_RESET: &str = "\x1b[0m";
+
+const BORDER: &str = "+---------------------------+\n";
+
+/// 端末向けに表示する盤面。`Board::pretty` で作る。
+#[derive(Clone, Copy, Debug)]
+pub struct PrettyBoard<'a> {
+    board: &'a Board,
+    style: PrettyStyle,
+}
+
+/// 端末向けに表示する局面。`Position::pretty` で作る。
+#[derive(Clone, Copy, Debug)]
+pub struct PrettyPosition<'a> {
+    pos: &'a Position,
+    style: PrettyStyle,
+}
+
+impl Board {
+    /// 端末向けに表示するためのラッパーを返す。
+    ///
+    /// `{:#}` で表示した場合は `PrettyStyle::Unicode` で表示される。
+    pub fn pretty(&self, style: PrettyStyle) -> PrettyBoard<'_> {
+        PrettyBoard { board: self, style }
+    }
+}
+
+impl Position {
+    /// 端末向けに表示するためのラッパーを返す。
+    ///
+    /// `{:#}` で表示した場合は `PrettyStyle::Unicode` で表示される。
+    pub fn pretty(&self, style: PrettyStyle) -> PrettyPosition<'_> {
+        PrettyPosition { pos: self, style }
+    }
+}
+
+impl std::fmt::Display for PrettyBoard<'_> {
+    /// 筋と段の番号つきの 9x9 の盤面を出力する。
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let ascii = self.style == PrettyStyle::Ascii;
+
+        f.write_str(if ascii {
+            "   9  8  7  6  5  4  3  2  1\n"
+        } else {
+            "  ９ ８ ７ ６ ５ ４ ３ ２ １\n"
+        })?;
+        f.write_str(BORDER)?;
+
+        for row in Row::all() {
+            f.write_char('|')?;
+            for col in Col::all() {
+                let pc = self.board[Square::new(col, row)];
+                match (self.style, pc) {
+                    (PrettyStyle::Ascii, Some(pc)) => write!(f, "{:>3}", pc.to_string())?,
+                    (PrettyStyle::Ascii, None) => f.write_str("  .")?,
+                    (_, None) => f.write_str(" ・")?,
+                    (PrettyStyle::Unicode, Some(pc)) => {
+                        f.write_char(if pc.side() == SENTE { ' ' } else { 'v' })?;
+                        f.write_char(piece_kind_to_kanji(pc.kind()))?;
+                    }
+                    (PrettyStyle::UnicodeColor, Some(pc)) => {
+                        let kanji = piece_kind_to_kanji(pc.kind());
+                        match pc.side() {
+                            SENTE => write!(f, " {kanji}")?,
+                            GOTE => write!(f, " {ANSI_GOTE}{kanji}{ANSI_RESET}")?,
+                        }
+                    }
+                }
+            }
+            f.write_char('|')?;
+            if ascii {
+                writeln!(f, "{}", row.to_num())?;
+            } else {
+                writeln!(f, "{}", row_to_kanji(row))?;
+            }
+        }
+
+        f.write_str(BORDER)
+    }
+}
+
+impl std::fmt::Display for PrettyPosition<'_> {
+    /// 後手の手駒、盤面、先手の手駒、手番と手数を出力する。
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let ascii = self.style == PrettyStyle::Ascii;
+        let pos = self.pos;
+
+        let fmt_hand = |f: &mut std::fmt::Formatter, side: Side| {
+            let hand = &pos.hands()[side];
+            let label = match (ascii, side) {
+                (true, SENTE) => "sente hand:",
+                (true, GOTE) => "gote hand:",
+                (false, SENTE) => "先手の持駒:",
+                (false, GOTE) => "後手の持駒:",
+            };
+            f.write_str(label)?;
+
+            if HAND_ORDER.iter().all(|&hpk| hand[hpk] == 0) {
+                f.write_str(if ascii { " -" } else { " なし" })?;
+            }
+            for hpk in HAND_ORDER {
+                let n = hand[hpk];
+                if n == 0 {
+                    continue;
+                }
+                if ascii {
+                    write!(f, " {hpk}")?;
+                } else {
+                    write!(f, " {}", piece_kind_to_kanji(hpk.into()))?;
+                }
+                if n > 1 {
+                    write!(f, "{n}")?;
+                }
+            }
+            f.write_char('\n')
+        };
+
+        fmt_hand(f, GOTE)?;
+        pos.board().pretty(self.style).fmt(f)?;
+        fmt_hand(f, SENTE)?;
+
+        let ply = pos.ply();
+        match (ascii, pos.side_to_move()) {
+            (true, SENTE) => write!(f, "sente to move (ply {ply})"),
+            (true, GOTE) => write!(f, "gote to move (ply {ply})"),
+            (false, SENTE) => write!(f, "先手番 ({ply}手目)"),
+            (false, GOTE) => write!(f, "後手番 ({ply}手目)"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr as _;
+
+    use super::*;
+
+    #[test]
+    fn test_pretty_fmt() {
+        let pos = Position::from_str("4k4/9/9/9/4+b4/9/9/9/4K4 w R2Pr 10").unwrap();
+
+        assert_eq!(
+            pos.pretty(PrettyStyle::Ascii).to_string(),
+            "\
+gote hand: R
+   9  8  7  6  5  4  3  2  1
++---------------------------+
+|  .  .  .  .  k  .  .  .  .|1
+|  .  .  .  .  .  .  .  .  .|2
+|  .  .  .  .  .  .  .  .  .|3
+|  .  .  .  .  .  .  .  .  .|4
+|  .  .  .  . +b  .  .  .  .|5
+|  .  .  .  .  .  .  .  .  .|6
+|  .  .  .  .  .  .  .  .  .|7
+|  .  .  .  .  .  .  .  .  .|8
+|  .  .  .  .  K  .  .  .  .|9
++---------------------------+
+sente hand: R P2
+gote to move (ply 10)"
+        );
+
+        let s = format!("{pos:#}");
+        assert!(s.starts_with("後手の持駒: 飛\n"));
+        assert!(s.contains("| ・ ・ ・ ・v馬 ・ ・ ・ ・|五\n"));
+        assert!(s.ends_with("先手の持駒: 飛 歩2\n後手番 (10手目)"));
+        assert_eq!(
+            format!("{:#}", pos.board()),
+            pos.board().pretty(PrettyStyle::Unicode).to_string()
+        );
+
+        let s = pos.pretty(PrettyStyle::UnicodeColor).to_string();
+        assert!(s.contains("| ・ ・ ・ ・ \x1b[31m玉\x1b[0m ・ ・ ・ ・|一\n"));
+        assert!(s.contains("| ・ ・ ・ ・ 玉 ・ ・ ・ ・|九\n"));
+
+        // 通常の表示は SFEN のまま。
+        assert_eq!(pos.to_string(), "sfen 4k4/9/9/9/4+b4/9/9/9/4K4 w R2Pr 10");
+    }
+}
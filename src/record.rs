@@ -0,0 +1,246 @@
+use std::time::Duration;
+
+use crate::kifu::*;
+use crate::move_::*;
+use crate::position::*;
+
+/// 付加情報(ヘッダ、コメント、消費時間、変化)つきの棋譜。
+///
+/// KIF などの棋譜ファイル形式との変換に用いる。手順のみが必要なら `Kifu` を使う。
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Record {
+    headers: Vec<(String, String)>,
+    pos: Position,
+    comments: Vec<String>,
+    entries: Vec<RecordEntry>,
+}
+
+impl Record {
+    /// 開始局面を指定して、手順が空の棋譜を作る。
+    pub fn new(pos: Position) -> Self {
+        Self {
+            headers: vec![],
+            pos,
+            comments: vec![],
+            entries: vec![],
+        }
+    }
+
+    /// ヘッダ(キーと値の組)を出現順に返す。
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+
+    /// ヘッダへの可変参照を返す。
+    pub fn headers_mut(&mut self) -> &mut Vec<(String, String)> {
+        &mut self.headers
+    }
+
+    /// 指定したキーを持つ最初のヘッダの値を返す。
+    pub fn header(&self, key: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// 開始局面を返す。
+    pub const fn position(&self) -> &Position {
+        &self.pos
+    }
+
+    /// 開始局面を設定する。手順との整合性は検査しない。
+    pub fn set_position(&mut self, pos: Position) {
+        self.pos = pos;
+    }
+
+    /// 開始局面に対するコメントを返す。
+    pub fn comments(&self) -> &[String] {
+        &self.comments
+    }
+
+    /// 開始局面に対するコメントへの可変参照を返す。
+    pub fn comments_mut(&mut self) -> &mut Vec<String> {
+        &mut self.comments
+    }
+
+    /// 本譜の手順を返す。
+    pub fn entries(&self) -> &[RecordEntry] {
+        &self.entries
+    }
+
+    /// 本譜の手順への可変参照を返す。
+    pub fn entries_mut(&mut self) -> &mut Vec<RecordEntry> {
+        &mut self.entries
+    }
+
+    /// 本譜の通常の指し手からなる `Kifu` を返す。
+    pub fn kifu(&self) -> Kifu {
+        let mvs = self.entries.iter().filter_map(|entry| match entry.mv {
+            RecordMove::Move(mv) => Some(mv),
+            RecordMove::Special(_) => None,
+        });
+        Kifu::new(self.pos.clone(), mvs)
+    }
+
+    /// 本譜が特殊な指し手で終わっていればそれを返す。
+    pub fn special(&self) -> Option<SpecialMove> {
+        match self.entries.last()?.mv {
+            RecordMove::Move(_) => None,
+            RecordMove::Special(special) => Some(special),
+        }
+    }
+}
+
+impl From<Kifu> for Record {
+    fn from(kifu: Kifu) -> Self {
+        let mut record = Self::new(kifu.position().clone());
+        record.entries = kifu
+            .moves()
+            .iter()
+            .map(|&mv| RecordEntry::new(RecordMove::Move(mv)))
+            .collect();
+        record
+    }
+}
+
+/// 棋譜中の 1 手と、それに付随する情報。
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct RecordEntry {
+    mv: RecordMove,
+    time: Option<MoveTime>,
+    comments: Vec<String>,
+    variations: Vec<Vec<RecordEntry>>,
+}
+
+impl RecordEntry {
+    /// 付随情報のない 1 手を作る。
+    pub fn new(mv: RecordMove) -> Self {
+        Self {
+            mv,
+            time: None,
+            comments: vec![],
+            variations: vec![],
+        }
+    }
+
+    /// 指し手を返す。
+    pub const fn mv(&self) -> RecordMove {
+        self.mv
+    }
+
+    /// 消費時間を返す。
+    pub const fn time(&self) -> Option<MoveTime> {
+        self.time
+    }
+
+    /// 消費時間を設定する。
+    pub fn set_time(&mut self, time: Option<MoveTime>) {
+        self.time = time;
+    }
+
+    /// この手を指した後の局面に対するコメントを返す。
+    pub fn comments(&self) -> &[String] {
+        &self.comments
+    }
+
+    /// コメントへの可変参照を返す。
+    pub fn comments_mut(&mut self) -> &mut Vec<String> {
+        &mut self.comments
+    }
+
+    /// 変化を返す。各変化はこの手の代わりに指された手から始まる手順である。
+    pub fn variations(&self) -> &[Vec<RecordEntry>] {
+        &self.variations
+    }
+
+    /// 変化への可変参照を返す。
+    pub fn variations_mut(&mut self) -> &mut Vec<Vec<RecordEntry>> {
+        &mut self.variations
+    }
+}
+
+/// 棋譜中の指し手。通常の指し手または特殊な指し手。
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum RecordMove {
+    /// 通常の指し手。
+    Move(Move),
+
+    /// 特殊な指し手(投了など)。
+    Special(SpecialMove),
+}
+
+/// 特殊な指し手。
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum SpecialMove {
+    /// 中断。
+    Interrupt,
+    /// 投了。
+    Resign,
+    /// 持将棋。
+    Impasse,
+    /// 千日手。
+    Repetition,
+    /// 詰み。
+    Mate,
+    /// 不詰(詰将棋で詰まないこと)。
+    NoMate,
+    /// 切れ負け。
+    TimeUp,
+    /// 手番側の反則勝ち(直前の手が反則)。
+    IllegalWin,
+    /// 手番側の反則負け。
+    IllegalLose,
+    /// 手番側の入玉宣言勝ち。
+    DeclareWin,
+    /// 引き分け。
+    Draw,
+    /// 待った。
+    Matta,
+}
+
+/// 1 手の消費時間と、その手までの累計消費時間。
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct MoveTime {
+    elapsed: Duration,
+    total: Duration,
+}
+
+impl MoveTime {
+    /// 消費時間と累計消費時間を指定して作る。
+    pub const fn new(elapsed: Duration, total: Duration) -> Self {
+        Self { elapsed, total }
+    }
+
+    /// この手の消費時間を返す。
+    pub const fn elapsed(self) -> Duration {
+        self.elapsed
+    }
+
+    /// この手までの累計消費時間を返す。
+    pub const fn total(self) -> Duration {
+        self.total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr as _;
+
+    use super::*;
+
+    #[test]
+    fn test_record_kifu() {
+        let kifu = Kifu::from_str("position startpos moves 7g7f 3c3d").unwrap();
+
+        let mut record = Record::from(kifu.clone());
+        assert_eq!(record.kifu(), kifu);
+        assert_eq!(record.special(), None);
+
+        record
+            .entries_mut()
+            .push(RecordEntry::new(RecordMove::Special(SpecialMove::Resign)));
+        assert_eq!(record.kifu(), kifu);
+        assert_eq!(record.special(), Some(SpecialMove::Resign));
+    }
+}
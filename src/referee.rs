@@ -0,0 +1,539 @@
+//! USI エンジン同士の対局の管理。
+
+use std::ops::ControlFlow;
+use std::time::{Duration, Instant};
+
+use crate::kifu::*;
+use crate::position::*;
+use crate::record::*;
+use crate::side::*;
+use crate::usi_command::*;
+use crate::usi_process::*;
+use crate::usi_response::*;
+
+/// 持ち時間の設定。
+///
+/// `increment` が 0 でなければフィッシャールールとし、`byoyomi` は無視する。
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct TimeControl {
+    /// 持ち時間。
+    pub main: Duration,
+
+    /// 秒読み。
+    pub byoyomi: Duration,
+
+    /// 1 手ごとの加算時間。
+    pub increment: Duration,
+}
+
+/// 対局の設定。
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct RefereeConfig {
+    /// 持ち時間。
+    pub time_control: TimeControl,
+
+    /// 最大手数。棋譜の手数(開始棋譜の手順を含む)がこれに達したら引き分けとする。
+    pub max_moves: u32,
+
+    /// 時間切れの判定で持ち時間に加える猶予(通信遅延などのため)。
+    pub time_margin: Duration,
+}
+
+impl Default for RefereeConfig {
+    /// 秒読み 1 秒、最大手数 256、猶予 1 秒。
+    fn default() -> Self {
+        Self {
+            time_control: TimeControl {
+                byoyomi: Duration::from_secs(1),
+                ..Default::default()
+            },
+            max_moves: 256,
+            time_margin: Duration::from_secs(1),
+        }
+    }
+}
+
+/// 対局の終了理由。
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum GameEndReason {
+    /// 詰み(手番側に合法手がない)。
+    Checkmate,
+
+    /// 投了。
+    Resign,
+
+    /// 非合法手。
+    IllegalMove,
+
+    /// 千日手。
+    Repetition,
+
+    /// 連続王手の千日手。
+    PerpetualCheck,
+
+    /// 入玉宣言。
+    DeclareWin,
+
+    /// 条件を満たさない入玉宣言。
+    IllegalDeclareWin,
+
+    /// 最大手数に達した。
+    MaxMoves,
+
+    /// 時間切れ。
+    TimeUp,
+}
+
+impl GameEndReason {
+    /// 棋譜の末尾に記録する特殊な指し手を返す。
+    ///
+    /// `loser_to_move` は負けた側が手番かどうか。
+    pub(crate) const fn to_special(self, loser_to_move: bool) -> SpecialMove {
+        match self {
+            Self::Checkmate => SpecialMove::Mate,
+            Self::Resign => SpecialMove::Resign,
+            Self::IllegalMove | Self::IllegalDeclareWin => SpecialMove::IllegalLose,
+            Self::Repetition => SpecialMove::Repetition,
+            Self::PerpetualCheck if loser_to_move => SpecialMove::IllegalLose,
+            Self::PerpetualCheck => SpecialMove::IllegalWin,
+            Self::DeclareWin => SpecialMove::DeclareWin,
+            Self::MaxMoves => SpecialMove::Draw,
+            Self::TimeUp => SpecialMove::TimeUp,
+        }
+    }
+}
+
+/// 終局した 1 局。
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RefereeGame {
+    record: Record,
+    winner: Option<Side>,
+    reason: GameEndReason,
+}
+
+impl RefereeGame {
+    /// 棋譜を返す。
+    ///
+    /// ヘッダ "先手", "後手" にエンジン名を記録し、末尾には終局を表す特殊な指し手を置く。
+    pub const fn record(&self) -> &Record {
+        &self.record
+    }
+
+    /// 勝った側を返す。引き分けなら `None` を返す。
+    pub const fn winner(&self) -> Option<Side> {
+        self.winner
+    }
+
+    /// 終局理由を返す。
+    pub const fn reason(&self) -> GameEndReason {
+        self.reason
+    }
+}
+
+/// 対局の管理中に発生したエラー。
+#[non_exhaustive]
+#[derive(Debug)]
+pub enum RefereeError {
+    /// エンジンとの通信エラー。時間切れ、不正な `bestmove` は含まない(それらは反則として扱う)。
+    Engine(UsiProcessError),
+
+    /// 開始棋譜の手順を適用できない。
+    InvalidStart(MoveError),
+}
+
+impl std::fmt::Display for RefereeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Engine(e) => write!(f, "engine error: {e}"),
+            Self::InvalidStart(e) => write!(f, "invalid start kifu: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for RefereeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Engine(e) => Some(e),
+            Self::InvalidStart(e) => Some(e),
+        }
+    }
+}
+
+impl From<UsiProcessError> for RefereeError {
+    fn from(e: UsiProcessError) -> Self {
+        Self::Engine(e)
+    }
+}
+
+/// USI エンジン同士を対局させる。
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Referee {
+    config: RefereeConfig,
+}
+
+impl Referee {
+    /// 設定を指定して作る。
+    pub const fn new(config: RefereeConfig) -> Self {
+        Self { config }
+    }
+
+    /// 設定を返す。
+    pub const fn config(&self) -> &RefereeConfig {
+        &self.config
+    }
+
+    /// 開始棋譜の列から、各棋譜について先後を入れ替えて 2 局ずつ対局させる。
+    ///
+    /// 返される対局の列のうち、偶数番目は `engine1` が先手、奇数番目は `engine2` が先手である。
+    /// 各対局の終了時には `on_game` が呼ばれる。
+    pub fn play_match(
+        &self,
+        engine1: &mut UsiProcess,
+        engine2: &mut UsiProcess,
+        starts: &[Kifu],
+        mut on_game: impl FnMut(&RefereeGame),
+    ) -> Result<Vec<RefereeGame>, RefereeError> {
+        let mut games = Vec::with_capacity(2 * starts.len());
+
+        for start in starts {
+            for swap in [false, true] {
+                let game = if swap {
+                    self.play_game(engine2, engine1, start)?
+                } else {
+                    self.play_game(engine1, engine2, start)?
+                };
+                on_game(&game);
+                games.push(game);
+            }
+        }
+
+        Ok(games)
+    }
+
+    /// 棋譜 `start` の末尾の局面から 1 局対局させる。
+    ///
+    /// 棋譜の手順は開始局面から記録される。
+    /// 対局前に両エンジンに `isready`, `usinewgame` を送り、終局後には `gameover` を送る。
+    pub fn play_game(
+        &self,
+        sente: &mut UsiProcess,
+        gote: &mut UsiProcess,
+        start: &Kifu,
+    ) -> Result<RefereeGame, RefereeError> {
+        let tc = self.config.time_control;
+
+        let mut record = Record::new(start.position().clone());
+        for (key, engine) in [("先手", &*sente), ("後手", &*gote)] {
+            let name = engine.name().unwrap_or_default().to_owned();
+            record.headers_mut().push((key.to_owned(), name));
+        }
+
+        let mut pos = start.position().clone();
+        for &mv in start.moves() {
+            pos.do_move(mv).map_err(RefereeError::InvalidStart)?;
+            record
+                .entries_mut()
+                .push(RecordEntry::new(RecordMove::Move(mv)));
+        }
+        let mut mvs = start.moves().to_vec();
+
+        for engine in [&mut *sente, &mut *gote] {
+            engine.is_ready()?;
+            engine.new_game()?;
+        }
+
+        let mut history = vec![(pos.without_ply(), pos.is_in_check())];
+        let mut remaining = ArraySide::from_elem(tc.main);
+        let mut total = ArraySide::from_elem(Duration::ZERO);
+
+        let (winner, reason, time) = loop {
+            let us = pos.side_to_move();
+
+            if mvs.len() >= self.config.max_moves as usize {
+                break (None, GameEndReason::MaxMoves, None);
+            }
+            if pos.legal_moves().is_empty() {
+                break (Some(us.flip()), GameEndReason::Checkmate, None);
+            }
+
+            let mut params = UsiGoParams {
+                btime: Some(remaining[SENTE]),
+                wtime: Some(remaining[GOTE]),
+                ..Default::default()
+            };
+            if tc.increment.is_zero() {
+                params.byoyomi = Some(tc.byoyomi);
+            } else {
+                params.binc = Some(tc.increment);
+                params.winc = Some(tc.increment);
+            }
+
+            let engine = match us {
+                SENTE => &mut *sente,
+                GOTE => &mut *gote,
+            };
+            let kifu = Kifu::new(start.position().clone(), mvs.iter().copied());
+            let start_time = Instant::now();
+            let res = engine.go(&kifu, &params, |_| ControlFlow::Continue(()));
+            let elapsed = start_time.elapsed();
+
+            total[us] += elapsed;
+            let time = Some(MoveTime::new(elapsed, total[us]));
+
+            let best = match res {
+                Ok(best) => best,
+                Err(UsiProcessError::Timeout) => {
+                    break (Some(us.flip()), GameEndReason::TimeUp, time);
+                }
+                Err(UsiProcessError::InvalidBestMove { .. }) => {
+                    break (Some(us.flip()), GameEndReason::IllegalMove, time);
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            let byoyomi = if tc.increment.is_zero() {
+                tc.byoyomi
+            } else {
+                Duration::ZERO
+            };
+            if elapsed > remaining[us] + byoyomi + self.config.time_margin {
+                break (Some(us.flip()), GameEndReason::TimeUp, time);
+            }
+            remaining[us] = remaining[us].saturating_sub(elapsed) + tc.increment;
+
+            let mv = match best {
+                UsiBestMove::Move { mv, .. } if pos.is_legal_move(mv) => mv,
+                UsiBestMove::Move { .. } => {
+                    break (Some(us.flip()), GameEndReason::IllegalMove, time);
+                }
+                UsiBestMove::Resign => break (Some(us.flip()), GameEndReason::Resign, time),
+                UsiBestMove::Win if pos.can_declare_win() => {
+                    break (Some(us), GameEndReason::DeclareWin, time);
+                }
+                UsiBestMove::Win => {
+                    break (Some(us.flip()), GameEndReason::IllegalDeclareWin, time);
+                }
+            };
+
+            pos.do_move(mv).unwrap();
+            mvs.push(mv);
+            let mut entry = RecordEntry::new(RecordMove::Move(mv));
+            entry.set_time(time);
+            record.entries_mut().push(entry);
+
+            history.push((pos.without_ply(), pos.is_in_check()));
+            if let Some((winner, reason)) = judge_repetition(&history) {
+                break (winner, reason, None);
+            }
+        };
+
+        let loser_to_move = winner.is_some_and(|winner| winner != pos.side_to_move());
+        let mut entry = RecordEntry::new(RecordMove::Special(reason.to_special(loser_to_move)));
+        entry.set_time(time);
+        record.entries_mut().push(entry);
+
+        for (side, engine) in [(SENTE, sente), (GOTE, gote)] {
+            let result = match winner {
+                None => UsiGameResult::Draw,
+                Some(winner) if winner == side => UsiGameResult::Win,
+                Some(_) => UsiGameResult::Lose,
+            };
+            // 勝敗は既に決まっているので、通知の失敗は無視する。
+            let _ = engine.send(&UsiCommand::GameOver(result));
+        }
+
+        Ok(RefereeGame {
+            record,
+            winner,
+            reason,
+        })
+    }
+}
+
+/// 局面の履歴(局面と、手番側が王手されているかどうか)の末尾で千日手が成立したか判定する。
+///
+/// 同一局面が 4 回現れたら千日手とし、その間の一方の手が全て王手なら、王手した側の負けとする。
+pub(crate) fn judge_repetition(
+    history: &[(Position, bool)],
+) -> Option<(Option<Side>, GameEndReason)> {
+    let (key, _) = history.last()?;
+
+    let count = history.iter().filter(|(pos, _)| pos == key).count();
+    if count < 4 {
+        return None;
+    }
+
+    let first = history.iter().position(|(pos, _)| pos == key).unwrap();
+    let cycle = &history[first + 1..];
+    for side in [SENTE, GOTE] {
+        let always_checked = cycle
+            .iter()
+            .filter(|(pos, _)| pos.side_to_move() == side)
+            .all(|&(_, in_check)| in_check);
+        if always_checked {
+            return Some((Some(side), GameEndReason::PerpetualCheck));
+        }
+    }
+
+    Some((None, GameEndReason::Repetition))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::str::FromStr as _;
+
+    use super::*;
+
+    /// 指定した手を順に指すだけのエンジンを起動する。
+    fn spawn_stub(name: &str, mvs: &[&str]) -> UsiProcess {
+        let script = format!(
+            r#"
+set -- {}
+while read -r cmd args; do
+    case "$cmd" in
+        usi) echo "id name {name}"; echo "usiok" ;;
+        isready) echo "readyok" ;;
+        go) echo "bestmove $1"; shift ;;
+        quit) exit 0 ;;
+    esac
+done
+"#,
+            mvs.join(" ")
+        );
+
+        UsiProcess::spawn("sh", ["-c", &script]).unwrap()
+    }
+
+    fn play(start: &str, sente: &[&str], gote: &[&str], config: RefereeConfig) -> RefereeGame {
+        let mut sente = spawn_stub("Sente", sente);
+        let mut gote = spawn_stub("Gote", gote);
+        let start = Kifu::from_str(start).unwrap();
+
+        Referee::new(config)
+            .play_game(&mut sente, &mut gote, &start)
+            .unwrap()
+    }
+
+    fn special(game: &RefereeGame) -> Option<SpecialMove> {
+        game.record().special()
+    }
+
+    #[test]
+    fn test_referee_play_game() {
+        let kings = "sfen 4k4/9/9/9/9/9/9/9/4K4 b - 1";
+        let shuffle_sente = ["5i4i", "4i5i"].repeat(3);
+        let shuffle_gote = ["5a4a", "4a5a"].repeat(3);
+
+        // 詰み。
+        let game = play(
+            "sfen 4k4/9/4P4/9/9/9/9/9/4K4 b G 1",
+            &["G*5b"],
+            &[],
+            RefereeConfig::default(),
+        );
+        assert_eq!(game.winner(), Some(SENTE));
+        assert_eq!(game.reason(), GameEndReason::Checkmate);
+        assert_eq!(special(&game), Some(SpecialMove::Mate));
+        assert_eq!(game.record().header("先手"), Some("Sente"));
+        assert_eq!(game.record().header("後手"), Some("Gote"));
+        assert_eq!(game.record().kifu().moves().len(), 1);
+
+        // 投了。
+        let game = play(kings, &["5i4i"], &["resign"], RefereeConfig::default());
+        assert_eq!(game.winner(), Some(SENTE));
+        assert_eq!(game.reason(), GameEndReason::Resign);
+        assert_eq!(special(&game), Some(SpecialMove::Resign));
+
+        // 非合法手(自玉を取られる手、駒のないマスからの移動)。
+        let start = "sfen 4k4/9/9/9/9/9/9/9/r3K4 b - 1";
+        let game = play(start, &["5i4i"], &[], RefereeConfig::default());
+        assert_eq!(game.winner(), Some(GOTE));
+        assert_eq!(game.reason(), GameEndReason::IllegalMove);
+        assert_eq!(special(&game), Some(SpecialMove::IllegalLose));
+        let game = play(kings, &["1a1b"], &[], RefereeConfig::default());
+        assert_eq!(game.reason(), GameEndReason::IllegalMove);
+
+        // 千日手。
+        let game = play(
+            kings,
+            &shuffle_sente,
+            &shuffle_gote,
+            RefereeConfig::default(),
+        );
+        assert_eq!(game.winner(), None);
+        assert_eq!(game.reason(), GameEndReason::Repetition);
+        assert_eq!(game.record().kifu().moves().len(), 12);
+        assert_eq!(special(&game), Some(SpecialMove::Repetition));
+
+        // 連続王手の千日手。
+        let game = play(
+            "sfen 4k4/8R/9/9/9/9/9/9/4K4 b - 1",
+            &["1b1a", "1a1b"].repeat(3),
+            &["5a5b", "5b5a"].repeat(3),
+            RefereeConfig::default(),
+        );
+        assert_eq!(game.winner(), Some(GOTE));
+        assert_eq!(game.reason(), GameEndReason::PerpetualCheck);
+        assert_eq!(special(&game), Some(SpecialMove::IllegalLose));
+
+        // 入玉宣言。
+        let start = "sfen +R+BGGGSS1K/PPPPPPP2/9/9/9/9/9/9/k8 b RP 1";
+        let game = play(start, &["win"], &[], RefereeConfig::default());
+        assert_eq!(game.winner(), Some(SENTE));
+        assert_eq!(game.reason(), GameEndReason::DeclareWin);
+        let game = play(kings, &["win"], &[], RefereeConfig::default());
+        assert_eq!(game.winner(), Some(GOTE));
+        assert_eq!(game.reason(), GameEndReason::IllegalDeclareWin);
+
+        // 最大手数(開始棋譜の手順を含む)。
+        let config = RefereeConfig {
+            max_moves: 3,
+            ..Default::default()
+        };
+        let game = play(
+            &format!("{kings} moves 5i4i"),
+            &shuffle_sente[1..],
+            &shuffle_gote,
+            config,
+        );
+        assert_eq!(game.winner(), None);
+        assert_eq!(game.reason(), GameEndReason::MaxMoves);
+        assert_eq!(
+            game.record().kifu().to_string(),
+            "position sfen 4k4/9/9/9/9/9/9/9/4K4 b - 1 moves 5i4i 5a4a 4i5i"
+        );
+        assert_eq!(special(&game), Some(SpecialMove::Draw));
+        assert!(game.record().entries()[0].time().is_none());
+        assert!(game.record().entries()[1].time().is_some());
+    }
+
+    #[test]
+    fn test_referee_play_match() {
+        let mut engine1 = spawn_stub("Engine1", &["resign", "resign"]);
+        let mut engine2 = spawn_stub("Engine2", &["resign", "resign"]);
+        let starts = [
+            Kifu::startpos(),
+            Kifu::from(Position::from_str("4k4/9/9/9/9/9/9/9/4K4 b - 1").unwrap()),
+        ];
+
+        let mut count = 0;
+        let games = Referee::default()
+            .play_match(&mut engine1, &mut engine2, &starts, |_| count += 1)
+            .unwrap();
+        assert_eq!(count, 4);
+        assert_eq!(games.len(), 4);
+
+        for (i, game) in games.iter().enumerate() {
+            let (sente, gote) = if i % 2 == 0 {
+                ("Engine1", "Engine2")
+            } else {
+                ("Engine2", "Engine1")
+            };
+            assert_eq!(game.record().header("先手"), Some(sente));
+            assert_eq!(game.record().header("後手"), Some(gote));
+            assert_eq!(game.record().position(), starts[i / 2].position());
+            assert_eq!(game.winner(), Some(GOTE));
+        }
+    }
+}
@@ -25,6 +25,14 @@ impl Side {
         [SENTE, GOTE]
     }
 
+    /// 相手陣営を返す。
+    pub const fn flip(self) -> Self {
+        match self {
+            SENTE => GOTE,
+            GOTE => SENTE,
+        }
+    }
+
     pub(crate) fn parse(bytes: Bytes) -> SfenParseResult<(Bytes, Self)> {
         // 'b' | 'w'
 
@@ -83,7 +91,6 @@ impl<T> ArraySide<T> {
 }
 
 impl<T: Copy> ArraySide<T> {
-    #[allow(dead_code)]
     pub(crate) const fn from_elem(elem: T) -> Self {
         Self([elem; Side::NUM])
     }
@@ -2,6 +2,7 @@ use std::fmt::Write as _;
 
 use crate::bytes::Bytes;
 use crate::parse::*;
+use crate::side::*;
 
 /// 盤面の筋。たとえば `Col::Col1` は 1 筋。
 #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
@@ -28,7 +29,7 @@ pub const COL_2: Col = Col::Col2;
 pub const COL_1: Col = Col::Col1;
 
 impl Col {
-    const NUM: usize = 9;
+    pub(crate) const NUM: usize = 9;
 
     const fn to_index(self) -> usize {
         self as usize
@@ -46,6 +47,19 @@ impl Col {
         ]
     }
 
+    /// 筋番号 (1..=9) から筋を作る。
+    pub(crate) const fn from_num(n: u8) -> Option<Self> {
+        match n {
+            1..=9 => Some(Self::all_private()[9 - n as usize]),
+            _ => None,
+        }
+    }
+
+    /// 筋番号 (1..=9) を返す。
+    pub(crate) const fn to_num(self) -> u8 {
+        9 - self as u8
+    }
+
     pub(crate) fn parse(bytes: Bytes) -> SfenParseResult<(Bytes, Self)> {
         // [1-9]
 
@@ -168,7 +182,7 @@ pub const ROW_8: Row = Row::Row8;
 pub const ROW_9: Row = Row::Row9;
 
 impl Row {
-    const NUM: usize = 9;
+    pub(crate) const NUM: usize = 9;
 
     const fn to_index(self) -> usize {
         self as usize
@@ -186,6 +200,27 @@ impl Row {
         ]
     }
 
+    /// 段番号 (1..=9) から段を作る。
+    pub(crate) const fn from_num(n: u8) -> Option<Self> {
+        match n {
+            1..=9 => Some(Self::all_private()[n as usize - 1]),
+            _ => None,
+        }
+    }
+
+    /// 段番号 (1..=9) を返す。
+    pub(crate) const fn to_num(self) -> u8 {
+        self as u8 + 1
+    }
+
+    /// 指定した陣営にとって敵陣(成れる段)かどうかを返す。
+    pub(crate) const fn is_promotion_zone(self, side: Side) -> bool {
+        match side {
+            SENTE => matches!(self, ROW_1 | ROW_2 | ROW_3),
+            GOTE => matches!(self, ROW_7 | ROW_8 | ROW_9),
+        }
+    }
+
     pub(crate) fn parse(bytes: Bytes) -> SfenParseResult<(Bytes, Self)> {
         // [a-i]
 
@@ -509,6 +544,33 @@ impl Square {
         *TABLE.index_const(self)
     }
 
+    /// 筋番号と段番号をそれぞれ `dcol`, `drow` だけずらしたマスを返す。盤外なら `None` を返す。
+    pub(crate) fn offset(self, dcol: i8, drow: i8) -> Option<Self> {
+        let col = u8::try_from(self.col().to_num() as i8 + dcol).ok()?;
+        let row = u8::try_from(self.row().to_num() as i8 + drow).ok()?;
+
+        Some(Self::new(Col::from_num(col)?, Row::from_num(row)?))
+    }
+
+    /// 1一, 1二, ..., 1九, 2一, ..., 9九 の順での番号 (0..81) を返す。
+    ///
+    /// やねうら王、Apery などの内部表現と一致する。
+    pub(crate) const fn to_col_major_index(self) -> u8 {
+        9 * (self.col().to_num() - 1) + (self.row().to_num() - 1)
+    }
+
+    /// `to_col_major_index` の逆変換。範囲外なら `None` を返す。
+    pub(crate) fn from_col_major_index(i: u8) -> Option<Self> {
+        if i >= 81 {
+            return None;
+        }
+
+        Some(Self::new(
+            Col::from_num(i / 9 + 1)?,
+            Row::from_num(i % 9 + 1)?,
+        ))
+    }
+
     /// 全てのマスを返す。順序は未規定。
     pub const fn all() -> [Self; Self::NUM] {
         #[rustfmt::skip]
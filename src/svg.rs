@@ -0,0 +1,318 @@
+//! 局面の SVG 画像出力。
+
+use std::fmt::Write as _;
+
+use crate::bod::*;
+use crate::hand::*;
+use crate::japanese::*;
+use crate::move_::*;
+use crate::position::*;
+use crate::side::*;
+use crate::square::*;
+
+/// 1 マスの大きさ。
+const CELL: u32 = 40;
+
+/// 盤の左上の座標。上に手駒欄と筋番号欄がある。
+const BOARD_X: u32 = 20;
+const BOARD_Y: u32 = 60;
+
+const BOARD_SIZE: u32 = CELL * 9;
+
+const WIDTH: u32 = BOARD_X + BOARD_SIZE + 30;
+const HEIGHT: u32 = BOARD_Y + BOARD_SIZE + 50;
+
+const HIGHLIGHT_COLOR: &str = "#ffe08a";
+const ARROW_COLOR: &str = "#d03030";
+const PROMOTED_COLOR: &str = "#c00000";
+
+/// SVG 出力のオプション。
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct SvgOptions {
+    highlights: Vec<Square>,
+    arrows: Vec<Move>,
+    flipped: bool,
+}
+
+impl SvgOptions {
+    /// 強調するマスも矢印もなく、先手側から見た向きのオプションを作る。
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 背景色で強調するマスを返す。
+    pub fn highlights(&self) -> &[Square] {
+        &self.highlights
+    }
+
+    /// 背景色で強調するマスへの可変参照を返す。
+    pub fn highlights_mut(&mut self) -> &mut Vec<Square> {
+        &mut self.highlights
+    }
+
+    /// 矢印で示す指し手(直前の指し手など)を返す。駒打ちは移動先の強調で示す。
+    pub fn arrows(&self) -> &[Move] {
+        &self.arrows
+    }
+
+    /// 矢印で示す指し手への可変参照を返す。
+    pub fn arrows_mut(&mut self) -> &mut Vec<Move> {
+        &mut self.arrows
+    }
+
+    /// 後手側から見た向き(盤を 180 度回転した向き)で描くかどうかを返す。
+    pub const fn is_flipped(&self) -> bool {
+        self.flipped
+    }
+
+    /// 後手側から見た向きで描くかどうかを設定する。
+    pub fn set_flipped(&mut self, flipped: bool) {
+        self.flipped = flipped;
+    }
+}
+
+/// マスの左上の座標を返す。
+fn cell_origin(sq: Square, flipped: bool) -> (u32, u32) {
+    let mut x = u32::from(9 - sq.col().to_num());
+    let mut y = u32::from(sq.row().to_num() - 1);
+    if flipped {
+        x = 8 - x;
+        y = 8 - y;
+    }
+
+    (BOARD_X + CELL * x, BOARD_Y + CELL * y)
+}
+
+/// マスの中心の座標を返す。
+fn cell_center(sq: Square, flipped: bool) -> (u32, u32) {
+    let (x, y) = cell_origin(sq, flipped);
+    (x + CELL / 2, y + CELL / 2)
+}
+
+/// 文字を描く。`rotated` なら中心を軸に 180 度回転する。
+fn write_text(s: &mut String, x: u32, y: u32, size: u32, fill: &str, rotated: bool, text: &str) {
+    write!(
+        s,
+        r#"<text x="{x}" y="{y}" font-size="{size}" fill="{fill}" text-anchor="middle" dominant-baseline="central""#
+    )
+    .unwrap();
+    if rotated {
+        write!(s, r#" transform="rotate(180 {x} {y})""#).unwrap();
+    }
+    writeln!(s, ">{text}</text>").unwrap();
+}
+
+fn write_hand(s: &mut String, y: u32, side: Side, hand: &Hand) {
+    let mut text = String::from(match side {
+        SENTE => "☗",
+        GOTE => "☖",
+    });
+    for hpk in HAND_ORDER {
+        match hand[hpk] {
+            0 => {}
+            1 => write!(text, " {}", piece_kind_to_kanji(hpk.into())).unwrap(),
+            n => write!(text, " {}{n}", piece_kind_to_kanji(hpk.into())).unwrap(),
+        }
+    }
+
+    writeln!(
+        s,
+        r#"<text x="{BOARD_X}" y="{y}" font-size="20" dominant-baseline="central">{text}</text>"#
+    )
+    .unwrap();
+}
+
+impl Position {
+    /// 局面を描いた SVG 画像を返す。
+    ///
+    /// 盤、筋と段の番号、駒(後手の駒は 180 度回転する)、両陣営の手駒を描く。
+    /// 手前側の陣営の手駒は盤の下に、奥側の陣営の手駒は盤の上に描く。
+    pub fn to_svg(&self, options: &SvgOptions) -> String {
+        let flipped = options.is_flipped();
+        let mut s = String::new();
+
+        writeln!(
+            s,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{WIDTH}" height="{HEIGHT}" viewBox="0 0 {WIDTH} {HEIGHT}" font-family="serif">"#
+        )
+        .unwrap();
+        writeln!(
+            s,
+            r#"<defs><marker id="arrowhead" markerWidth="6" markerHeight="6" refX="3" refY="3" orient="auto"><path d="M0,0 L6,3 L0,6 z" fill="{ARROW_COLOR}"/></marker></defs>"#
+        )
+        .unwrap();
+        writeln!(
+            s,
+            r##"<rect x="0" y="0" width="{WIDTH}" height="{HEIGHT}" fill="#ffffff"/>"##
+        )
+        .unwrap();
+        writeln!(
+            s,
+            r##"<rect x="{BOARD_X}" y="{BOARD_Y}" width="{BOARD_SIZE}" height="{BOARD_SIZE}" fill="#f3d18f"/>"##
+        )
+        .unwrap();
+
+        // 強調するマス。駒打ちは移動先を強調する。
+        let drop_dsts = options.arrows().iter().filter_map(|mv| match mv {
+            Move::Drop(drop) => Some(drop.dst()),
+            Move::Walk(_) => None,
+        });
+        for sq in options.highlights().iter().copied().chain(drop_dsts) {
+            let (x, y) = cell_origin(sq, flipped);
+            writeln!(
+                s,
+                r#"<rect x="{x}" y="{y}" width="{CELL}" height="{CELL}" fill="{HIGHLIGHT_COLOR}"/>"#
+            )
+            .unwrap();
+        }
+
+        // 罫線と星。
+        for i in 1..9 {
+            let d = CELL * i;
+            writeln!(
+                s,
+                r#"<line x1="{x}" y1="{BOARD_Y}" x2="{x}" y2="{y2}" stroke="black" stroke-width="1"/>"#,
+                x = BOARD_X + d,
+                y2 = BOARD_Y + BOARD_SIZE,
+            )
+            .unwrap();
+            writeln!(
+                s,
+                r#"<line x1="{BOARD_X}" y1="{y}" x2="{x2}" y2="{y}" stroke="black" stroke-width="1"/>"#,
+                y = BOARD_Y + d,
+                x2 = BOARD_X + BOARD_SIZE,
+            )
+            .unwrap();
+        }
+        writeln!(
+            s,
+            r#"<rect x="{BOARD_X}" y="{BOARD_Y}" width="{BOARD_SIZE}" height="{BOARD_SIZE}" fill="none" stroke="black" stroke-width="2"/>"#
+        )
+        .unwrap();
+        for (i, j) in [(3, 3), (6, 3), (3, 6), (6, 6)] {
+            writeln!(
+                s,
+                r#"<circle cx="{}" cy="{}" r="3" fill="black"/>"#,
+                BOARD_X + CELL * i,
+                BOARD_Y + CELL * j
+            )
+            .unwrap();
+        }
+
+        // 筋と段の番号。
+        for col in Col::all() {
+            let (x, _) = cell_center(Square::new(col, ROW_1), flipped);
+            write_text(
+                &mut s,
+                x,
+                BOARD_Y - 10,
+                14,
+                "black",
+                false,
+                &col_to_zenkaku(col).to_string(),
+            );
+        }
+        for row in Row::all() {
+            let (_, y) = cell_center(Square::new(COL_1, row), flipped);
+            write_text(
+                &mut s,
+                BOARD_X + BOARD_SIZE + 15,
+                y,
+                14,
+                "black",
+                false,
+                &row_to_kanji(row).to_string(),
+            );
+        }
+
+        // 駒。
+        for sq in Square::all() {
+            let Some(pc) = self.board()[sq] else {
+                continue;
+            };
+            let (x, y) = cell_center(sq, flipped);
+            let fill = if pc.kind().is_promoted() {
+                PROMOTED_COLOR
+            } else {
+                "black"
+            };
+            let rotated = (pc.side() == GOTE) != flipped;
+            write_text(
+                &mut s,
+                x,
+                y,
+                30,
+                fill,
+                rotated,
+                &piece_kind_to_kanji(pc.kind()).to_string(),
+            );
+        }
+
+        // 直前の指し手などの矢印。
+        for mv in options.arrows() {
+            if let Move::Walk(walk) = mv {
+                let (x1, y1) = cell_center(walk.src(), flipped);
+                let (x2, y2) = cell_center(walk.dst(), flipped);
+                writeln!(
+                    s,
+                    r#"<line x1="{x1}" y1="{y1}" x2="{x2}" y2="{y2}" stroke="{ARROW_COLOR}" stroke-width="4" stroke-opacity="0.8" marker-end="url(#arrowhead)"/>"#
+                )
+                .unwrap();
+            }
+        }
+
+        // 手駒。
+        let (top, bottom) = if flipped {
+            (SENTE, GOTE)
+        } else {
+            (GOTE, SENTE)
+        };
+        write_hand(&mut s, 20, top, &self.hands()[top]);
+        write_hand(
+            &mut s,
+            BOARD_Y + BOARD_SIZE + 25,
+            bottom,
+            &self.hands()[bottom],
+        );
+
+        s.push_str("</svg>\n");
+
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr as _;
+
+    use super::*;
+
+    #[test]
+    fn test_position_to_svg() {
+        let pos = Position::from_str("4k4/9/9/9/9/9/9/9/4K4 b R2Pb 1").unwrap();
+        let svg = pos.to_svg(&SvgOptions::new());
+
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.ends_with("</svg>\n"));
+        assert_eq!(svg.matches("rotate(180").count(), 1);
+        assert!(svg.contains(r#"y="20" font-size="20" dominant-baseline="central">☖ 角</text>"#));
+        assert!(svg.contains(">☗ 飛 歩2</text>"));
+        // 後手玉は 5 筋 1 段目に回転して描かれる。
+        assert!(svg.contains(r#"<text x="200" y="80" font-size="30" fill="black" text-anchor="middle" dominant-baseline="central" transform="rotate(180 200 80)">玉</text>"#));
+
+        let mut options = SvgOptions::new();
+        options.set_flipped(true);
+        options.highlights_mut().push(SQ_55);
+        options.arrows_mut().push(Move::from_str("5i4h").unwrap());
+        options.arrows_mut().push(Move::from_str("P*5e").unwrap());
+        let svg = pos.to_svg(&options);
+
+        // 後手側から見ると、先手玉が奥で回転して描かれる。
+        assert!(svg.contains(r#"transform="rotate(180 200 80)">玉</text>"#));
+        assert!(
+            svg.contains(r#"y="20" font-size="20" dominant-baseline="central">☗ 飛 歩2</text>"#)
+        );
+        assert_eq!(svg.matches(HIGHLIGHT_COLOR).count(), 2);
+        assert_eq!(svg.matches("marker-end").count(), 1);
+    }
+}
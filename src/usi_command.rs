@@ -0,0 +1,441 @@
+//! USI プロトコルの GUI からエンジンへのコマンド。
+//!
+//! ref: <http://shogidokoro.starfree.jp/usi.html>
+
+use std::time::Duration;
+
+use crate::bytes::Bytes;
+use crate::kifu::*;
+use crate::move_::*;
+use crate::parse::*;
+
+/// GUI からエンジンへ送られる USI コマンド。
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum UsiCommand {
+    /// `usi`
+    Usi,
+
+    /// `isready`
+    IsReady,
+
+    /// `usinewgame`
+    UsiNewGame,
+
+    /// `setoption name <name> [value <value>]`
+    ///
+    /// `value` を持たないのはボタン型のオプションの場合。
+    SetOption { name: String, value: Option<String> },
+
+    /// `position ...`
+    Position(Kifu),
+
+    /// `go ...`
+    Go(UsiGoParams),
+
+    /// `stop`
+    Stop,
+
+    /// `ponderhit`
+    PonderHit,
+
+    /// `gameover <win|lose|draw>`
+    GameOver(UsiGameResult),
+
+    /// `quit`
+    Quit,
+}
+
+/// `go` コマンドのパラメータ。
+///
+/// 時間はミリ秒単位で送受信される。
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct UsiGoParams {
+    /// `ponder`: 先読みとして思考する。
+    pub ponder: bool,
+
+    /// `btime`: 先手の残り時間。
+    pub btime: Option<Duration>,
+
+    /// `wtime`: 後手の残り時間。
+    pub wtime: Option<Duration>,
+
+    /// `byoyomi`: 秒読み。
+    pub byoyomi: Option<Duration>,
+
+    /// `binc`: 先手の 1 手ごとの加算時間。
+    pub binc: Option<Duration>,
+
+    /// `winc`: 後手の 1 手ごとの加算時間。
+    pub winc: Option<Duration>,
+
+    /// `movetime`: 1 手の思考時間。
+    pub movetime: Option<Duration>,
+
+    /// `nodes`: 探索ノード数の上限。
+    pub nodes: Option<u64>,
+
+    /// `depth`: 探索深さの上限。
+    pub depth: Option<u32>,
+
+    /// `infinite`: `stop` まで思考し続ける。
+    pub infinite: bool,
+
+    /// `mate`: 詰将棋探索を行う。
+    pub mate: Option<UsiMateLimit>,
+
+    /// `searchmoves`: 探索する指し手を限定する。空なら限定しない。
+    pub searchmoves: Vec<Move>,
+}
+
+/// `go mate` の制限時間。
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum UsiMateLimit {
+    /// 制限時間。
+    Time(Duration),
+
+    /// `infinite`: 制限なし。
+    Infinite,
+}
+
+/// `gameover` コマンドで通知される対局結果(エンジンから見た勝敗)。
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum UsiGameResult {
+    Win,
+    Lose,
+    Draw,
+}
+
+/// トークンを文字列として返す。トークンは ASCII space で区切られているので UTF-8 として正しい。
+pub(crate) fn token_str(token: Bytes<'_>) -> &str {
+    std::str::from_utf8(token.as_slice()).unwrap()
+}
+
+/// トークンを `FromStr` でパースする。トークンがなければ `end` の位置のエラーとする。
+pub(crate) fn parse_token<T: std::str::FromStr>(
+    token: Option<Bytes>,
+    end: Bytes,
+    description: &'static str,
+) -> SfenParseResult<T> {
+    let token = token.ok_or_else(|| SfenParseError::invalid_input(end, description))?;
+    token_str(token)
+        .parse()
+        .map_err(|_| SfenParseError::invalid_input(token, description))
+}
+
+fn parse_millis(token: Option<Bytes>, end: Bytes) -> SfenParseResult<Duration> {
+    parse_token(token, end, "milliseconds expected").map(Duration::from_millis)
+}
+
+impl UsiGoParams {
+    /// `go` に続くパラメータを入力の終端まで読む。
+    fn parse(bytes: Bytes) -> SfenParseResult<Self> {
+        let end = bytes.range_from(bytes.len()..);
+        let mut params = Self::default();
+
+        let mut tokens = bytes.tokens().peekable();
+        while let Some(token) = tokens.next() {
+            match token.as_slice() {
+                b"ponder" => params.ponder = true,
+                b"btime" => params.btime = Some(parse_millis(tokens.next(), end)?),
+                b"wtime" => params.wtime = Some(parse_millis(tokens.next(), end)?),
+                b"byoyomi" => params.byoyomi = Some(parse_millis(tokens.next(), end)?),
+                b"binc" => params.binc = Some(parse_millis(tokens.next(), end)?),
+                b"winc" => params.winc = Some(parse_millis(tokens.next(), end)?),
+                b"movetime" => params.movetime = Some(parse_millis(tokens.next(), end)?),
+                b"nodes" => params.nodes = Some(parse_token(tokens.next(), end, "nodes expected")?),
+                b"depth" => params.depth = Some(parse_token(tokens.next(), end, "depth expected")?),
+                b"infinite" => params.infinite = true,
+                b"mate" => {
+                    let limit = match tokens.peek().map(|t| t.as_slice()) {
+                        Some(b"infinite") => {
+                            tokens.next();
+                            UsiMateLimit::Infinite
+                        }
+                        _ => UsiMateLimit::Time(parse_millis(tokens.next(), end)?),
+                    };
+                    params.mate = Some(limit);
+                }
+                b"searchmoves" => {
+                    while let Some(mv) = tokens
+                        .peek()
+                        .and_then(|&t| parser_complete(Move::parse)(t).ok())
+                    {
+                        tokens.next();
+                        params.searchmoves.push(mv);
+                    }
+                }
+                _ => return Err(SfenParseError::invalid_input(token, "unknown go parameter")),
+            }
+        }
+
+        Ok(params)
+    }
+}
+
+impl std::fmt::Display for UsiGoParams {
+    /// `go` に続くパラメータを出力する。先頭に空白が付き、`searchmoves` は末尾に置く。
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let millis = |f: &mut std::fmt::Formatter, name: &str, d: Option<Duration>| match d {
+            Some(d) => write!(f, " {name} {}", d.as_millis()),
+            None => Ok(()),
+        };
+
+        if self.ponder {
+            f.write_str(" ponder")?;
+        }
+        millis(f, "btime", self.btime)?;
+        millis(f, "wtime", self.wtime)?;
+        millis(f, "byoyomi", self.byoyomi)?;
+        millis(f, "binc", self.binc)?;
+        millis(f, "winc", self.winc)?;
+        millis(f, "movetime", self.movetime)?;
+        if let Some(nodes) = self.nodes {
+            write!(f, " nodes {nodes}")?;
+        }
+        if let Some(depth) = self.depth {
+            write!(f, " depth {depth}")?;
+        }
+        if self.infinite {
+            f.write_str(" infinite")?;
+        }
+        match self.mate {
+            Some(UsiMateLimit::Time(d)) => write!(f, " mate {}", d.as_millis())?,
+            Some(UsiMateLimit::Infinite) => f.write_str(" mate infinite")?,
+            None => {}
+        }
+        if !self.searchmoves.is_empty() {
+            f.write_str(" searchmoves")?;
+            for mv in &self.searchmoves {
+                write!(f, " {mv}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl UsiCommand {
+    /// 入力の終端まで読む。
+    pub(crate) fn parse(bytes: Bytes) -> SfenParseResult<(Bytes, Self)> {
+        let end = bytes.range_from(bytes.len()..);
+        let mut tokens = bytes.tokens();
+
+        let Some(name) = tokens.next() else {
+            return Err(SfenParseError::invalid_input(bytes, "command expected"));
+        };
+
+        let cmd = match name.as_slice() {
+            b"usi" => Self::Usi,
+            b"isready" => Self::IsReady,
+            b"usinewgame" => Self::UsiNewGame,
+            b"setoption" => {
+                let magic = tokens.next();
+                if magic.map(Bytes::as_slice) != Some(b"name") {
+                    return Err(SfenParseError::invalid_input(
+                        magic.unwrap_or(end),
+                        r#""name" expected"#,
+                    ));
+                }
+
+                let mut name = String::new();
+                let mut value = None;
+                while let Some(token) = tokens.next() {
+                    if token.as_slice() == b"value" {
+                        // 値は空白を含みうるので、行末までを値とする。
+                        value = Some(token_str(tokens.remain()).trim_end().to_owned());
+                        tokens = end.tokens();
+                        break;
+                    }
+                    if !name.is_empty() {
+                        name.push(' ');
+                    }
+                    name.push_str(token_str(token));
+                }
+                if name.is_empty() {
+                    return Err(SfenParseError::invalid_input(end, "option name expected"));
+                }
+
+                Self::SetOption { name, value }
+            }
+            b"position" => {
+                let (remain, kifu) = Kifu::parse(tokens.remain())?;
+                tokens = remain.tokens();
+                Self::Position(kifu)
+            }
+            b"go" => {
+                let params = UsiGoParams::parse(tokens.remain())?;
+                tokens = end.tokens();
+                Self::Go(params)
+            }
+            b"stop" => Self::Stop,
+            b"ponderhit" => Self::PonderHit,
+            b"gameover" => {
+                let token = tokens.next().unwrap_or(end);
+                let result = match token.as_slice() {
+                    b"win" => UsiGameResult::Win,
+                    b"lose" => UsiGameResult::Lose,
+                    b"draw" => UsiGameResult::Draw,
+                    _ => return Err(SfenParseError::invalid_input(token, "game result expected")),
+                };
+                Self::GameOver(result)
+            }
+            b"quit" => Self::Quit,
+            _ => return Err(SfenParseError::invalid_input(name, "unknown command")),
+        };
+
+        Ok((tokens.remain(), cmd))
+    }
+}
+
+impl std::str::FromStr for UsiCommand {
+    type Err = SfenParseError;
+
+    /// USI コマンド文字列(1 行)をパースする。
+    ///
+    /// 先頭/末尾の ASCII spaces は無視する。
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parser_complete(Self::parse)(Bytes::from(s))
+    }
+}
+
+impl std::fmt::Display for UsiCommand {
+    /// USI コマンド文字列を出力する。改行は付けない。
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Usi => f.write_str("usi"),
+            Self::IsReady => f.write_str("isready"),
+            Self::UsiNewGame => f.write_str("usinewgame"),
+            Self::SetOption { name, value } => {
+                write!(f, "setoption name {name}")?;
+                if let Some(value) = value {
+                    write!(f, " value {value}")?;
+                }
+                Ok(())
+            }
+            Self::Position(kifu) => write!(f, "{kifu}"),
+            Self::Go(params) => {
+                f.write_str("go")?;
+                params.fmt(f)
+            }
+            Self::Stop => f.write_str("stop"),
+            Self::PonderHit => f.write_str("ponderhit"),
+            Self::GameOver(result) => {
+                f.write_str("gameover ")?;
+                f.write_str(match result {
+                    UsiGameResult::Win => "win",
+                    UsiGameResult::Lose => "lose",
+                    UsiGameResult::Draw => "draw",
+                })
+            }
+            Self::Quit => f.write_str("quit"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr as _;
+
+    use super::*;
+
+    #[test]
+    fn test_usi_command_parse() {
+        assert_eq!(UsiCommand::from_str("usi").unwrap(), UsiCommand::Usi);
+        assert_eq!(
+            UsiCommand::from_str(" isready ").unwrap(),
+            UsiCommand::IsReady
+        );
+        assert_eq!(
+            UsiCommand::from_str("setoption name USI_Hash value 256").unwrap(),
+            UsiCommand::SetOption {
+                name: "USI_Hash".to_owned(),
+                value: Some("256".to_owned())
+            }
+        );
+        assert_eq!(
+            UsiCommand::from_str("setoption name BookFile value C:/My Books/a.db").unwrap(),
+            UsiCommand::SetOption {
+                name: "BookFile".to_owned(),
+                value: Some("C:/My Books/a.db".to_owned())
+            }
+        );
+        assert_eq!(
+            UsiCommand::from_str("setoption name ClearHash").unwrap(),
+            UsiCommand::SetOption {
+                name: "ClearHash".to_owned(),
+                value: None
+            }
+        );
+        assert_eq!(
+            UsiCommand::from_str("position startpos moves 7g7f 3c3d").unwrap(),
+            UsiCommand::Position(Kifu::from_str("position startpos moves 7g7f 3c3d").unwrap())
+        );
+        assert_eq!(
+            UsiCommand::from_str("go btime 60000 wtime 50000 byoyomi 10000").unwrap(),
+            UsiCommand::Go(UsiGoParams {
+                btime: Some(Duration::from_secs(60)),
+                wtime: Some(Duration::from_secs(50)),
+                byoyomi: Some(Duration::from_secs(10)),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            UsiCommand::from_str("go ponder binc 1000 winc 2000 searchmoves 7g7f 2g2f depth 5")
+                .unwrap(),
+            UsiCommand::Go(UsiGoParams {
+                ponder: true,
+                binc: Some(Duration::from_secs(1)),
+                winc: Some(Duration::from_secs(2)),
+                depth: Some(5),
+                searchmoves: vec![
+                    Move::from_str("7g7f").unwrap(),
+                    Move::from_str("2g2f").unwrap()
+                ],
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            UsiCommand::from_str("go mate infinite").unwrap(),
+            UsiCommand::Go(UsiGoParams {
+                mate: Some(UsiMateLimit::Infinite),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            UsiCommand::from_str("gameover lose").unwrap(),
+            UsiCommand::GameOver(UsiGameResult::Lose)
+        );
+
+        assert!(UsiCommand::from_str("").is_err());
+        assert!(UsiCommand::from_str("hello").is_err());
+        assert!(UsiCommand::from_str("usi extra").is_err());
+        assert!(UsiCommand::from_str("setoption USI_Hash").is_err());
+        assert!(UsiCommand::from_str("go btime").is_err());
+        assert!(UsiCommand::from_str("go nodes -1").is_err());
+        assert!(UsiCommand::from_str("gameover tie").is_err());
+    }
+
+    #[test]
+    fn test_usi_command_fmt() {
+        for s in [
+            "usi",
+            "isready",
+            "usinewgame",
+            "setoption name USI_Ponder value true",
+            "setoption name ClearHash",
+            "position startpos",
+            "position sfen 4k4/9/9/9/9/9/9/9/4K4 b G 1 moves G*5b",
+            "go",
+            "go ponder btime 1000 wtime 2000 byoyomi 3000",
+            "go binc 1000 winc 1000 movetime 500 nodes 100000 depth 10 searchmoves 7g7f",
+            "go infinite",
+            "go mate 30000",
+            "stop",
+            "ponderhit",
+            "gameover draw",
+            "quit",
+        ] {
+            assert_eq!(UsiCommand::from_str(s).unwrap().to_string(), s);
+        }
+    }
+}
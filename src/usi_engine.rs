@@ -0,0 +1,364 @@
+//! USI エンジンを実装するための枠組み。
+
+use std::io::{BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread::ScopedJoinHandle;
+
+use crate::kifu::*;
+use crate::usi_command::*;
+use crate::usi_option::*;
+use crate::usi_response::*;
+
+/// USI エンジンとして振る舞うためのトレイト。
+///
+/// `run_usi_engine` に渡すと、コマンドのパース、思考中の `stop`/`ponderhit` の処理、
+/// 応答の出力は全てそちらで行われる。
+/// `go` は別スレッドで呼ばれ、その間 `stop`/`ponderhit` は `UsiSearch` を通じて通知される。
+pub trait UsiEngine {
+    /// エンジン名を返す。
+    fn name(&self) -> &str;
+
+    /// 作者名を返す。
+    fn author(&self) -> &str;
+
+    /// 設定可能なオプションを返す。
+    fn options(&self) -> Vec<UsiOption> {
+        vec![]
+    }
+
+    /// オプションを設定する。値は `options` の宣言に照らして検査済みである。
+    fn set_option(&mut self, _name: &str, _value: Option<&str>) {}
+
+    /// `isready` を受け取ったときに呼ばれる。時間のかかる初期化はここで行う。
+    fn is_ready(&mut self) {}
+
+    /// `usinewgame` を受け取ったときに呼ばれる。
+    fn new_game(&mut self) {}
+
+    /// 思考対象の棋譜を受け取る。
+    fn on_position(&mut self, kifu: Kifu);
+
+    /// 直前に受け取った棋譜の末尾の局面について思考し、最善手を返す。
+    ///
+    /// `search.is_stopped()` が真になったら速やかに返ること。
+    /// ponder 中や `infinite` 指定時に早く思考を終えた場合、
+    /// `bestmove` の出力は `stop`/`ponderhit` を受け取るまで保留される。
+    fn go(&mut self, search: &UsiSearch<'_>) -> UsiBestMove;
+
+    /// `gameover` を受け取ったときに呼ばれる。
+    fn game_over(&mut self, _result: UsiGameResult) {}
+}
+
+/// 思考中のエンジンに渡される情報。
+pub struct UsiSearch<'a> {
+    params: &'a UsiGoParams,
+    state: &'a SearchState,
+    on_info: &'a (dyn Fn(&UsiInfo) + Sync),
+}
+
+impl UsiSearch<'_> {
+    /// `go` コマンドのパラメータを返す。
+    pub fn params(&self) -> &UsiGoParams {
+        self.params
+    }
+
+    /// `stop` (または `quit`) を受け取ったかどうかを返す。
+    pub fn is_stopped(&self) -> bool {
+        self.state.stop.load(Ordering::Acquire)
+    }
+
+    /// ponder 中かどうかを返す。`ponderhit` を受け取ると偽になる。
+    pub fn is_pondering(&self) -> bool {
+        self.state.ponder.load(Ordering::Acquire)
+    }
+
+    /// `info` 応答を出力する。
+    pub fn send_info(&self, info: &UsiInfo) {
+        (self.on_info)(info);
+    }
+}
+
+impl std::fmt::Debug for UsiSearch<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("UsiSearch")
+            .field("params", &self.params)
+            .field("stopped", &self.is_stopped())
+            .field("pondering", &self.is_pondering())
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Default)]
+struct SearchState {
+    stop: AtomicBool,
+    ponder: AtomicBool,
+}
+
+/// 標準入出力で USI プロトコルを処理する。`quit` を受け取るか入力が終わると返る。
+pub fn run_usi_engine_stdio<E: UsiEngine + Send>(engine: &mut E) -> std::io::Result<()> {
+    let stdin = std::io::stdin();
+    let mut stdout = std::io::stdout();
+
+    run_usi_engine(engine, stdin.lock(), &mut stdout)
+}
+
+/// `input` から USI コマンドを読み、`engine` を駆動して応答を `output` に書く。
+/// `quit` を受け取るか入力が終わると返る。
+///
+/// パースできないコマンドや検査に失敗した `setoption` は `info string` で報告して無視する。
+pub fn run_usi_engine<E, R, W>(engine: &mut E, input: R, output: &mut W) -> std::io::Result<()>
+where
+    E: UsiEngine + Send,
+    R: BufRead,
+    W: Write + Send,
+{
+    let output = Mutex::new(output);
+    let writeln = |res: &dyn std::fmt::Display| -> std::io::Result<()> {
+        let mut output = output.lock().unwrap();
+        writeln!(output, "{res}")?;
+        output.flush()
+    };
+    let on_info = |info: &UsiInfo| {
+        // 読み筋の出力に失敗しても思考は続ける。
+        let _ = writeln(&UsiResponse::Info(info.clone()));
+    };
+
+    let options = engine.options();
+    let state = SearchState::default();
+
+    std::thread::scope(|scope| {
+        let mut engine = Some(engine);
+        let mut search: Option<ScopedJoinHandle<'_, (&mut E, std::io::Result<()>)>> = None;
+
+        for line in input.lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let cmd = match line.parse::<UsiCommand>() {
+                Ok(cmd) => cmd,
+                Err(e) => {
+                    writeln(&UsiResponse::Info(info_string(format!("{e}"))))?;
+                    continue;
+                }
+            };
+
+            // 思考中にも受け付けるコマンド。
+            match cmd {
+                UsiCommand::Stop | UsiCommand::Quit => state.stop.store(true, Ordering::Release),
+                UsiCommand::PonderHit => state.ponder.store(false, Ordering::Release),
+                _ => {}
+            }
+            if let Some(handle) = &search {
+                if matches!(cmd, UsiCommand::Stop | UsiCommand::PonderHit) {
+                    handle.thread().unpark();
+                    continue;
+                }
+            }
+
+            // それ以外のコマンドは思考を止め、終了を待ってから処理する。
+            // ponder 中や infinite 指定時は stop/ponderhit を待ち続けるので、ここで止める必要がある。
+            if let Some(handle) = search.take() {
+                state.stop.store(true, Ordering::Release);
+                handle.thread().unpark();
+                let (e, res) = match handle.join() {
+                    Ok(x) => x,
+                    Err(panic) => std::panic::resume_unwind(panic),
+                };
+                engine = Some(e);
+                res?;
+            }
+            let e = engine.take().unwrap();
+
+            match cmd {
+                UsiCommand::Usi => {
+                    writeln(&UsiResponse::IdName(e.name().to_owned()))?;
+                    writeln(&UsiResponse::IdAuthor(e.author().to_owned()))?;
+                    for option in &options {
+                        writeln(&UsiResponse::Option(option.clone()))?;
+                    }
+                    writeln(&UsiResponse::UsiOk)?;
+                }
+                UsiCommand::IsReady => {
+                    e.is_ready();
+                    writeln(&UsiResponse::ReadyOk)?;
+                }
+                UsiCommand::SetOption { .. } => {
+                    if let Err(err) = UsiOption::validate_command(&options, &cmd) {
+                        writeln(&UsiResponse::Info(info_string(format!("{err}"))))?;
+                    } else if let UsiCommand::SetOption { name, value } = &cmd {
+                        e.set_option(name, value.as_deref());
+                    }
+                }
+                UsiCommand::UsiNewGame => e.new_game(),
+                UsiCommand::Position(kifu) => e.on_position(kifu),
+                UsiCommand::Go(params) => {
+                    state.stop.store(false, Ordering::Release);
+                    state.ponder.store(params.ponder, Ordering::Release);
+                    let state = &state;
+                    let writeln = &writeln;
+                    let on_info = &on_info;
+                    search = Some(scope.spawn(move || {
+                        let search = UsiSearch {
+                            params: &params,
+                            state,
+                            on_info,
+                        };
+                        let best = e.go(&search);
+
+                        // ponder 中や infinite 指定時は stop/ponderhit まで bestmove を保留する。
+                        while (search.is_pondering() || params.infinite) && !search.is_stopped() {
+                            std::thread::park();
+                        }
+
+                        let res = writeln(&UsiResponse::BestMove(best));
+                        (e, res)
+                    }));
+                    continue;
+                }
+                UsiCommand::GameOver(result) => e.game_over(result),
+                UsiCommand::Quit => return Ok(()),
+                UsiCommand::Stop | UsiCommand::PonderHit => {}
+            }
+
+            engine = Some(e);
+        }
+
+        // 入力が終わったら思考を止めて返る。
+        if let Some(handle) = search.take() {
+            state.stop.store(true, Ordering::Release);
+            handle.thread().unpark();
+            match handle.join() {
+                Ok((_, res)) => res?,
+                Err(panic) => std::panic::resume_unwind(panic),
+            }
+        }
+
+        Ok(())
+    })
+}
+
+fn info_string(s: String) -> UsiInfo {
+    UsiInfo {
+        string: Some(s),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr as _;
+
+    use crate::move_::*;
+
+    use super::*;
+
+    /// 思考中に `stop` を待ち、常に同じ手を返すエンジン。
+    #[derive(Debug, Default)]
+    struct TestEngine {
+        kifu: Option<Kifu>,
+        hash: i64,
+    }
+
+    impl UsiEngine for TestEngine {
+        fn name(&self) -> &str {
+            "Test Engine"
+        }
+
+        fn author(&self) -> &str {
+            "sfen"
+        }
+
+        fn options(&self) -> Vec<UsiOption> {
+            vec![UsiOption::from_str("name USI_Hash type spin default 16 min 1 max 1024").unwrap()]
+        }
+
+        fn set_option(&mut self, name: &str, value: Option<&str>) {
+            assert_eq!(name, "USI_Hash");
+            self.hash = value.unwrap().parse().unwrap();
+        }
+
+        fn on_position(&mut self, kifu: Kifu) {
+            self.kifu = Some(kifu);
+        }
+
+        fn go(&mut self, search: &UsiSearch<'_>) -> UsiBestMove {
+            if search.params().infinite {
+                while !search.is_stopped() {
+                    std::thread::yield_now();
+                }
+            }
+            let kifu = self.kifu.as_ref().unwrap();
+            search.send_info(&UsiInfo {
+                depth: Some(kifu.moves().len() as u32),
+                ..Default::default()
+            });
+
+            UsiBestMove::Move {
+                mv: Move::from_str("7g7f").unwrap(),
+                ponder: None,
+            }
+        }
+    }
+
+    fn run(input: &str) -> (TestEngine, Vec<String>) {
+        let mut engine = TestEngine::default();
+        let mut output = Vec::<u8>::new();
+        run_usi_engine(&mut engine, input.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(str::to_owned)
+            .collect();
+
+        (engine, output)
+    }
+
+    #[test]
+    fn test_run_usi_engine() {
+        let (engine, output) = run("\
+usi
+setoption name USI_Hash value 256
+setoption name USI_Hash value 4096
+isready
+usinewgame
+position startpos moves 2g2f 8c8d
+go btime 1000 wtime 1000 byoyomi 1000
+go infinite
+stop
+go ponder
+ponderhit
+quit
+");
+        assert_eq!(engine.hash, 256);
+        assert_eq!(
+            output,
+            [
+                "id name Test Engine",
+                "id author sfen",
+                "option name USI_Hash type spin default 16 min 1 max 1024",
+                "usiok",
+                "info string option value 4096 is out of range (min: 1, max: 1024)",
+                "readyok",
+                "info depth 2",
+                "bestmove 7g7f",
+                "info depth 2",
+                "bestmove 7g7f",
+                "info depth 2",
+                "bestmove 7g7f",
+            ]
+        );
+
+        // ponder/infinite の思考中に stop/ponderhit 以外のコマンドが来たら、思考を止めてから処理する。
+        let (_, output) = run("position startpos\ngo ponder\ngameover win\nisready\nquit\n");
+        assert_eq!(output, ["info depth 0", "bestmove 7g7f", "readyok"]);
+        let (_, output) = run("position startpos\ngo infinite\nisready\nquit\n");
+        assert_eq!(output, ["info depth 0", "bestmove 7g7f", "readyok"]);
+
+        // 入力が終われば思考中でも止めて返る。
+        let (_, output) = run("position startpos\ngo infinite\n");
+        assert_eq!(output, ["info depth 0", "bestmove 7g7f"]);
+    }
+}
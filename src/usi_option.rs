@@ -0,0 +1,483 @@
+//! USI プロトコルのエンジンオプション宣言 (`option name ... type ...`)。
+
+use crate::bytes::Bytes;
+use crate::parse::*;
+use crate::usi_command::*;
+
+/// エンジンオプションの宣言。
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct UsiOption {
+    name: String,
+    kind: UsiOptionKind,
+}
+
+/// エンジンオプションの型と、型ごとの既定値などのパラメータ。
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum UsiOptionKind {
+    /// `type check`: 真偽値。
+    Check { default: Option<bool> },
+
+    /// `type spin`: 整数。
+    Spin {
+        default: Option<i64>,
+        min: Option<i64>,
+        max: Option<i64>,
+    },
+
+    /// `type combo`: `vars` のいずれか。
+    Combo {
+        default: Option<String>,
+        vars: Vec<String>,
+    },
+
+    /// `type button`: 値を持たない。
+    Button,
+
+    /// `type string`: 任意の文字列。
+    String { default: Option<String> },
+
+    /// `type filename`: ファイル名。
+    Filename { default: Option<String> },
+}
+
+/// `setoption` の値がオプション宣言に合わないことを表すエラー。
+#[non_exhaustive]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum UsiOptionValueError {
+    /// 宣言されていないオプション名。
+    UnknownOption,
+
+    /// 値が必要だが与えられていない。
+    MissingValue,
+
+    /// ボタン型のオプションに値が与えられた。
+    UnexpectedValue,
+
+    /// `check` 型の値が `true`/`false` でない。
+    NotBool,
+
+    /// `spin` 型の値が整数でない。
+    NotInteger,
+
+    /// `spin` 型の値が範囲外。
+    OutOfRange {
+        value: i64,
+        min: Option<i64>,
+        max: Option<i64>,
+    },
+
+    /// `combo` 型の値が選択肢にない。
+    NotInVars,
+}
+
+impl std::fmt::Display for UsiOptionValueError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::UnknownOption => f.write_str("unknown option"),
+            Self::MissingValue => f.write_str("option value is missing"),
+            Self::UnexpectedValue => f.write_str("button option takes no value"),
+            Self::NotBool => f.write_str(r#"option value must be "true" or "false""#),
+            Self::NotInteger => f.write_str("option value must be an integer"),
+            Self::OutOfRange { value, min, max } => {
+                write!(f, "option value {value} is out of range (min: ")?;
+                match min {
+                    Some(min) => write!(f, "{min}")?,
+                    None => f.write_str("none")?,
+                }
+                f.write_str(", max: ")?;
+                match max {
+                    Some(max) => write!(f, "{max}")?,
+                    None => f.write_str("none")?,
+                }
+                f.write_str(")")
+            }
+            Self::NotInVars => f.write_str("option value is not one of the vars"),
+        }
+    }
+}
+
+impl std::error::Error for UsiOptionValueError {}
+
+/// `string`/`filename` 型で空文字列を表す値。
+const EMPTY_STRING: &str = "<empty>";
+
+fn parse_string_value(s: &str) -> String {
+    if s == EMPTY_STRING {
+        String::new()
+    } else {
+        s.to_owned()
+    }
+}
+
+fn fmt_string_value(s: &str) -> &str {
+    if s.is_empty() {
+        EMPTY_STRING
+    } else {
+        s
+    }
+}
+
+impl UsiOption {
+    /// 名前と型を指定してオプション宣言を作る。
+    pub fn new(name: impl Into<String>, kind: UsiOptionKind) -> Self {
+        Self {
+            name: name.into(),
+            kind,
+        }
+    }
+
+    /// オプション名を返す。
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// オプションの型とパラメータを返す。
+    pub const fn kind(&self) -> &UsiOptionKind {
+        &self.kind
+    }
+
+    /// `setoption` の値がこの宣言に合うか検査する。
+    ///
+    /// `value` はボタン型なら `None`、それ以外なら `Some` でなければならない。
+    pub fn validate(&self, value: Option<&str>) -> Result<(), UsiOptionValueError> {
+        let value = match (&self.kind, value) {
+            (UsiOptionKind::Button, None) => return Ok(()),
+            (UsiOptionKind::Button, Some(_)) => return Err(UsiOptionValueError::UnexpectedValue),
+            (_, None) => return Err(UsiOptionValueError::MissingValue),
+            (_, Some(value)) => value,
+        };
+
+        match &self.kind {
+            UsiOptionKind::Check { .. } => match value {
+                "true" | "false" => Ok(()),
+                _ => Err(UsiOptionValueError::NotBool),
+            },
+            &UsiOptionKind::Spin { min, max, .. } => {
+                let value: i64 = value.parse().map_err(|_| UsiOptionValueError::NotInteger)?;
+                let ok = min.is_none_or(|min| min <= value) && max.is_none_or(|max| value <= max);
+                if ok {
+                    Ok(())
+                } else {
+                    Err(UsiOptionValueError::OutOfRange { value, min, max })
+                }
+            }
+            UsiOptionKind::Combo { vars, .. } => {
+                if vars.iter().any(|var| var == value) {
+                    Ok(())
+                } else {
+                    Err(UsiOptionValueError::NotInVars)
+                }
+            }
+            UsiOptionKind::String { .. } | UsiOptionKind::Filename { .. } => Ok(()),
+            UsiOptionKind::Button => unreachable!(),
+        }
+    }
+
+    /// `setoption` コマンドを、オプション宣言の列 `options` に照らして検査する。
+    ///
+    /// `cmd` が `setoption` コマンドでなければ何もしない。
+    pub fn validate_command(options: &[Self], cmd: &UsiCommand) -> Result<(), UsiOptionValueError> {
+        let UsiCommand::SetOption { name, value } = cmd else {
+            return Ok(());
+        };
+
+        let option = options
+            .iter()
+            .find(|option| option.name == *name)
+            .ok_or(UsiOptionValueError::UnknownOption)?;
+
+        option.validate(value.as_deref())
+    }
+
+    /// `option` に続く部分を入力の終端まで読む。
+    pub(crate) fn parse(bytes: Bytes) -> SfenParseResult<Self> {
+        let end = bytes.range_from(bytes.len()..);
+        let mut tokens = bytes.tokens();
+
+        let magic = tokens.next();
+        if magic.map(Bytes::as_slice) != Some(b"name") {
+            return Err(SfenParseError::invalid_input(
+                magic.unwrap_or(end),
+                r#""name" expected"#,
+            ));
+        }
+
+        let mut name = String::new();
+        loop {
+            let token = tokens
+                .next()
+                .ok_or_else(|| SfenParseError::invalid_input(end, r#""type" expected"#))?;
+            if token.as_slice() == b"type" {
+                break;
+            }
+            if !name.is_empty() {
+                name.push(' ');
+            }
+            name.push_str(token_str(token));
+        }
+        if name.is_empty() {
+            return Err(SfenParseError::invalid_input(bytes, "option name expected"));
+        }
+
+        let ty = tokens.next().unwrap_or(end);
+
+        let mut default = None;
+        let mut min = None;
+        let mut max = None;
+        let mut vars = vec![];
+        while let Some(token) = tokens.next() {
+            let value = tokens
+                .next()
+                .ok_or_else(|| SfenParseError::invalid_input(end, "parameter value expected"))?;
+            match token.as_slice() {
+                b"default" if matches!(ty.as_slice(), b"string" | b"filename") => {
+                    // 文字列の既定値は空白を含みうるので、行末までを値とする。
+                    default = Some(bytes.range_from(value.base_offset() - bytes.base_offset()..));
+                    break;
+                }
+                b"default" => default = Some(value),
+                b"min" => min = Some(parse_token(Some(value), end, "integer expected")?),
+                b"max" => max = Some(parse_token(Some(value), end, "integer expected")?),
+                b"var" => vars.push(token_str(value).to_owned()),
+                _ => {
+                    return Err(SfenParseError::invalid_input(
+                        token,
+                        "unknown option parameter",
+                    ))
+                }
+            }
+        }
+
+        let kind = match ty.as_slice() {
+            b"check" => UsiOptionKind::Check {
+                default: default
+                    .map(|token| match token.as_slice() {
+                        b"true" => Ok(true),
+                        b"false" => Ok(false),
+                        _ => Err(SfenParseError::invalid_input(token, "bool expected")),
+                    })
+                    .transpose()?,
+            },
+            b"spin" => UsiOptionKind::Spin {
+                default: default
+                    .map(|token| parse_token(Some(token), end, "integer expected"))
+                    .transpose()?,
+                min,
+                max,
+            },
+            b"combo" => UsiOptionKind::Combo {
+                default: default.map(|token| token_str(token).to_owned()),
+                vars,
+            },
+            b"button" => UsiOptionKind::Button,
+            b"string" => UsiOptionKind::String {
+                default: default.map(|token| parse_string_value(token_str(token).trim_end())),
+            },
+            b"filename" => UsiOptionKind::Filename {
+                default: default.map(|token| parse_string_value(token_str(token).trim_end())),
+            },
+            _ => return Err(SfenParseError::invalid_input(ty, "unknown option type")),
+        };
+
+        Ok(Self { name, kind })
+    }
+}
+
+impl std::str::FromStr for UsiOption {
+    type Err = SfenParseError;
+
+    /// オプション宣言文字列 (`option name ... type ...`) をパースする。
+    ///
+    /// 先頭の "option" の有無は任意。
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = Bytes::from(s);
+        let mut tokens = bytes.tokens();
+        let bytes = match tokens.next() {
+            Some(token) if token.as_slice() == b"option" => tokens.remain(),
+            _ => bytes,
+        };
+
+        Self::parse(bytes)
+    }
+}
+
+impl std::fmt::Display for UsiOption {
+    /// オプション宣言文字列を出力する。先頭に "option" を付ける。
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "option name {} type ", self.name)?;
+
+        match &self.kind {
+            UsiOptionKind::Check { default } => {
+                f.write_str("check")?;
+                if let Some(default) = default {
+                    write!(f, " default {default}")?;
+                }
+            }
+            UsiOptionKind::Spin { default, min, max } => {
+                f.write_str("spin")?;
+                if let Some(default) = default {
+                    write!(f, " default {default}")?;
+                }
+                if let Some(min) = min {
+                    write!(f, " min {min}")?;
+                }
+                if let Some(max) = max {
+                    write!(f, " max {max}")?;
+                }
+            }
+            UsiOptionKind::Combo { default, vars } => {
+                f.write_str("combo")?;
+                if let Some(default) = default {
+                    write!(f, " default {default}")?;
+                }
+                for var in vars {
+                    write!(f, " var {var}")?;
+                }
+            }
+            UsiOptionKind::Button => f.write_str("button")?,
+            UsiOptionKind::String { default } => {
+                f.write_str("string")?;
+                if let Some(default) = default {
+                    write!(f, " default {}", fmt_string_value(default))?;
+                }
+            }
+            UsiOptionKind::Filename { default } => {
+                f.write_str("filename")?;
+                if let Some(default) = default {
+                    write!(f, " default {}", fmt_string_value(default))?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr as _;
+
+    use super::*;
+
+    #[test]
+    fn test_usi_option_parse() {
+        assert_eq!(
+            UsiOption::from_str("option name USI_Hash type spin default 256 min 1 max 1024")
+                .unwrap(),
+            UsiOption::new(
+                "USI_Hash",
+                UsiOptionKind::Spin {
+                    default: Some(256),
+                    min: Some(1),
+                    max: Some(1024)
+                }
+            )
+        );
+        assert_eq!(
+            UsiOption::from_str(
+                "option name Style type combo default Normal var Solid var Normal var Risky"
+            )
+            .unwrap(),
+            UsiOption::new(
+                "Style",
+                UsiOptionKind::Combo {
+                    default: Some("Normal".to_owned()),
+                    vars: vec!["Solid".to_owned(), "Normal".to_owned(), "Risky".to_owned()]
+                }
+            )
+        );
+        assert_eq!(
+            UsiOption::from_str("option name EvalDir type filename default <empty>").unwrap(),
+            UsiOption::new(
+                "EvalDir",
+                UsiOptionKind::Filename {
+                    default: Some(String::new())
+                }
+            )
+        );
+
+        // 文字列の既定値は行末まで。
+        assert_eq!(
+            UsiOption::from_str("option name BookFile type string default user book.db").unwrap(),
+            UsiOption::new(
+                "BookFile",
+                UsiOptionKind::String {
+                    default: Some("user book.db".to_owned())
+                }
+            )
+        );
+
+        assert!(UsiOption::from_str("option name X").is_err());
+        assert!(UsiOption::from_str("option name X type number").is_err());
+        assert!(UsiOption::from_str("option name X type check default yes").is_err());
+        assert!(UsiOption::from_str("option name X type spin min").is_err());
+    }
+
+    #[test]
+    fn test_usi_option_fmt() {
+        for s in [
+            "option name USI_Ponder type check default true",
+            "option name USI_Hash type spin default 256 min 1 max 1024",
+            "option name Style type combo default Normal var Solid var Normal",
+            "option name ClearHash type button",
+            "option name BookFile type string default <empty>",
+            "option name EvalDir type filename default eval",
+            "option name BookFile type filename default user book.db",
+        ] {
+            assert_eq!(UsiOption::from_str(s).unwrap().to_string(), s);
+        }
+    }
+
+    #[test]
+    fn test_usi_option_validate() {
+        let options = [
+            UsiOption::from_str("option name USI_Ponder type check default true").unwrap(),
+            UsiOption::from_str("option name USI_Hash type spin default 256 min 1 max 1024")
+                .unwrap(),
+            UsiOption::from_str("option name Style type combo default A var A var B").unwrap(),
+            UsiOption::from_str("option name ClearHash type button").unwrap(),
+            UsiOption::from_str("option name BookFile type string").unwrap(),
+        ];
+        let validate =
+            |s: &str| UsiOption::validate_command(&options, &UsiCommand::from_str(s).unwrap());
+
+        assert_eq!(validate("setoption name USI_Ponder value false"), Ok(()));
+        assert_eq!(
+            validate("setoption name USI_Ponder value 1"),
+            Err(UsiOptionValueError::NotBool)
+        );
+        assert_eq!(validate("setoption name USI_Hash value 1024"), Ok(()));
+        assert_eq!(
+            validate("setoption name USI_Hash value 2048"),
+            Err(UsiOptionValueError::OutOfRange {
+                value: 2048,
+                min: Some(1),
+                max: Some(1024)
+            })
+        );
+        assert_eq!(
+            validate("setoption name USI_Hash value big"),
+            Err(UsiOptionValueError::NotInteger)
+        );
+        assert_eq!(validate("setoption name Style value B"), Ok(()));
+        assert_eq!(
+            validate("setoption name Style value C"),
+            Err(UsiOptionValueError::NotInVars)
+        );
+        assert_eq!(validate("setoption name ClearHash"), Ok(()));
+        assert_eq!(
+            validate("setoption name ClearHash value true"),
+            Err(UsiOptionValueError::UnexpectedValue)
+        );
+        assert_eq!(
+            validate("setoption name BookFile"),
+            Err(UsiOptionValueError::MissingValue)
+        );
+        assert_eq!(validate("setoption name BookFile value a b.db"), Ok(()));
+        assert_eq!(
+            validate("setoption name Threads value 4"),
+            Err(UsiOptionValueError::UnknownOption)
+        );
+        assert_eq!(validate("isready"), Ok(()));
+    }
+}
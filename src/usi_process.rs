@@ -0,0 +1,461 @@
+//! 子プロセスとして起動した USI エンジンとの通信。
+
+use std::ffi::OsStr;
+use std::io::{BufRead as _, BufReader, Write as _};
+use std::ops::ControlFlow;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use crate::kifu::*;
+use crate::parse::*;
+use crate::side::*;
+use crate::usi_command::*;
+use crate::usi_option::*;
+use crate::usi_response::*;
+
+/// 既定の応答待ちの制限時間。
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// USI エンジンとの通信エラー。
+#[non_exhaustive]
+#[derive(Debug)]
+pub enum UsiProcessError {
+    /// 入出力エラー。
+    Io(std::io::Error),
+
+    /// 制限時間内に期待する応答がなかった。
+    Timeout,
+
+    /// エンジンとの接続が切れた(エンジンが終了した)。
+    Disconnected,
+
+    /// `bestmove` 行をパースできない。
+    InvalidBestMove { line: String, error: SfenParseError },
+
+    /// `setoption` の値がエンジンのオプション宣言に合わない。
+    InvalidOption(UsiOptionValueError),
+}
+
+impl std::fmt::Display for UsiProcessError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "I/O error: {e}"),
+            Self::Timeout => f.write_str("engine did not respond in time"),
+            Self::Disconnected => f.write_str("engine disconnected"),
+            Self::InvalidBestMove { line, error } => {
+                write!(f, "invalid bestmove line {line:?}: {error}")
+            }
+            Self::InvalidOption(e) => write!(f, "invalid option: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for UsiProcessError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::InvalidBestMove { error, .. } => Some(error),
+            Self::InvalidOption(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for UsiProcessError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+pub type UsiProcessResult<T> = Result<T, UsiProcessError>;
+
+/// 子プロセスとして起動した USI エンジン。
+///
+/// エンジンの出力は別スレッドで読み取り、応答待ちには制限時間を設ける。
+/// パースできない出力行は無視する(`bestmove` 行を除く)。
+/// drop 時には `quit` を送り、終了しなければ強制終了する。
+#[derive(Debug)]
+pub struct UsiProcess {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    lines: Receiver<String>,
+    timeout: Duration,
+    name: Option<String>,
+    author: Option<String>,
+    options: Vec<UsiOption>,
+}
+
+impl UsiProcess {
+    /// エンジンを起動し、`usi` コマンドによるハンドシェイクを行う。
+    ///
+    /// `id` と `option` の応答は記録され、`name`, `author`, `options` で参照できる。
+    pub fn spawn<I, S>(program: impl AsRef<OsStr>, args: I) -> UsiProcessResult<Self>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        let stdin = child.stdin.take();
+        let stdout = child.stdout.take().unwrap();
+
+        let (tx, lines) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut this = Self {
+            child,
+            stdin,
+            lines,
+            timeout: DEFAULT_TIMEOUT,
+            name: None,
+            author: None,
+            options: vec![],
+        };
+
+        this.send(&UsiCommand::Usi)?;
+        let deadline = Instant::now() + this.timeout;
+        loop {
+            match this.recv(Some(deadline))? {
+                UsiResponse::IdName(name) => this.name = Some(name),
+                UsiResponse::IdAuthor(author) => this.author = Some(author),
+                UsiResponse::Option(option) => this.options.push(option),
+                UsiResponse::UsiOk => break,
+                _ => {}
+            }
+        }
+
+        Ok(this)
+    }
+
+    /// 応答待ちの制限時間を返す。既定値は 10 秒。
+    pub const fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// 応答待ちの制限時間を設定する。
+    ///
+    /// `go` の場合は、持ち時間などから求めた思考時間の上限にこの時間を加えたものが制限時間になる。
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// `id name` で通知されたエンジン名を返す。
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// `id author` で通知された作者名を返す。
+    pub fn author(&self) -> Option<&str> {
+        self.author.as_deref()
+    }
+
+    /// エンジンが宣言したオプションを返す。
+    pub fn options(&self) -> &[UsiOption] {
+        &self.options
+    }
+
+    /// コマンドを送る。
+    pub fn send(&mut self, cmd: &UsiCommand) -> UsiProcessResult<()> {
+        let stdin = self.stdin.as_mut().ok_or(UsiProcessError::Disconnected)?;
+        writeln!(stdin, "{cmd}")
+            .and_then(|()| stdin.flush())
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::BrokenPipe => UsiProcessError::Disconnected,
+                _ => UsiProcessError::Io(e),
+            })
+    }
+
+    /// 応答を 1 つ受け取る。`deadline` を過ぎたらエラーを返す。
+    fn recv(&mut self, deadline: Option<Instant>) -> UsiProcessResult<UsiResponse> {
+        loop {
+            let line = match deadline {
+                Some(deadline) => {
+                    let timeout = deadline.saturating_duration_since(Instant::now());
+                    self.lines.recv_timeout(timeout).map_err(|e| match e {
+                        RecvTimeoutError::Timeout => UsiProcessError::Timeout,
+                        RecvTimeoutError::Disconnected => UsiProcessError::Disconnected,
+                    })?
+                }
+                None => self
+                    .lines
+                    .recv()
+                    .map_err(|_| UsiProcessError::Disconnected)?,
+            };
+            let line = line.trim_end_matches('\r');
+
+            match line.parse::<UsiResponse>() {
+                Ok(res) => return Ok(res),
+                Err(error) if line.trim_start().starts_with("bestmove") => {
+                    return Err(UsiProcessError::InvalidBestMove {
+                        line: line.to_owned(),
+                        error,
+                    })
+                }
+                Err(_) => {}
+            }
+        }
+    }
+
+    /// オプションを設定する。値はエンジンのオプション宣言に照らして検査される。
+    ///
+    /// ボタン型のオプションの場合、`value` は `None` とする。
+    pub fn set_option(&mut self, name: &str, value: Option<&str>) -> UsiProcessResult<()> {
+        let cmd = UsiCommand::SetOption {
+            name: name.to_owned(),
+            value: value.map(str::to_owned),
+        };
+        UsiOption::validate_command(&self.options, &cmd).map_err(UsiProcessError::InvalidOption)?;
+
+        self.send(&cmd)
+    }
+
+    /// `isready` を送り、`readyok` を待つ。
+    pub fn is_ready(&mut self) -> UsiProcessResult<()> {
+        self.send(&UsiCommand::IsReady)?;
+        let deadline = Instant::now() + self.timeout;
+        while !matches!(self.recv(Some(deadline))?, UsiResponse::ReadyOk) {}
+
+        Ok(())
+    }
+
+    /// `usinewgame` を送る。
+    pub fn new_game(&mut self) -> UsiProcessResult<()> {
+        self.send(&UsiCommand::UsiNewGame)
+    }
+
+    /// 棋譜 `kifu` の末尾の局面について思考させ、`bestmove` を返す。
+    ///
+    /// `info` 行はパースして `on_info` に渡す。`on_info` が `ControlFlow::Break` を返すと
+    /// `stop` を送る(その後も `bestmove` を待つ)。
+    /// `infinite` や `ponder` を指定した場合は制限時間を設けないので、`on_info` から止めること。
+    pub fn go(
+        &mut self,
+        kifu: &Kifu,
+        params: &UsiGoParams,
+        mut on_info: impl FnMut(&UsiInfo) -> ControlFlow<()>,
+    ) -> UsiProcessResult<UsiBestMove> {
+        self.send(&UsiCommand::Position(kifu.clone()))?;
+        self.send(&UsiCommand::Go(params.clone()))?;
+
+        // 制限時間が大きすぎて表せなければ、制限時間を設けない。
+        let deadline = think_limit(kifu, params)
+            .and_then(|limit| limit.checked_add(self.timeout))
+            .and_then(|limit| Instant::now().checked_add(limit));
+        let mut stopped = false;
+        loop {
+            match self.recv(deadline)? {
+                UsiResponse::Info(info) => {
+                    let flow = on_info(&info);
+                    if flow.is_break() && !stopped {
+                        self.send(&UsiCommand::Stop)?;
+                        stopped = true;
+                    }
+                }
+                UsiResponse::BestMove(best) => return Ok(best),
+                _ => {}
+            }
+        }
+    }
+
+    /// `quit` を送り、エンジンの終了を待つ。制限時間内に終了しなければ強制終了する。
+    pub fn quit(mut self) -> UsiProcessResult<()> {
+        self.shutdown()
+    }
+
+    fn shutdown(&mut self) -> UsiProcessResult<()> {
+        if self.stdin.is_some() {
+            // エンジンが既に終了していれば書き込みは失敗するが、終了処理は続ける。
+            let _ = self.send(&UsiCommand::Quit);
+            self.stdin = None;
+        }
+
+        let deadline = Instant::now() + self.timeout;
+        while Instant::now() < deadline {
+            if self.child.try_wait()?.is_some() {
+                return Ok(());
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        self.child.kill()?;
+        self.child.wait()?;
+
+        Ok(())
+    }
+}
+
+impl Drop for UsiProcess {
+    fn drop(&mut self) {
+        let _ = self.shutdown();
+    }
+}
+
+/// `go` のパラメータから思考時間の上限を求める。
+///
+/// 上限がないか、大きすぎて `Duration` で表せなければ `None` を返す。
+fn think_limit(kifu: &Kifu, params: &UsiGoParams) -> Option<Duration> {
+    if params.infinite || params.ponder || params.mate == Some(UsiMateLimit::Infinite) {
+        return None;
+    }
+    if let Some(UsiMateLimit::Time(limit)) = params.mate {
+        return Some(limit);
+    }
+    if let Some(movetime) = params.movetime {
+        return Some(movetime);
+    }
+
+    let mut side = kifu.position().side_to_move();
+    if kifu.moves().len() % 2 == 1 {
+        side = side.flip();
+    }
+    let (time, inc) = match side {
+        SENTE => (params.btime, params.binc),
+        GOTE => (params.wtime, params.winc),
+    };
+
+    [time, inc, params.byoyomi]
+        .into_iter()
+        .flatten()
+        .try_fold(Duration::ZERO, Duration::checked_add)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::str::FromStr as _;
+
+    use crate::move_::*;
+
+    use super::*;
+
+    /// 最小限の USI エンジンとして振る舞うシェルスクリプト。
+    const STUB_ENGINE: &str = r#"
+while read -r cmd args; do
+    case "$cmd" in
+        usi)
+            echo "id name Stub Engine"
+            echo "id author sfen"
+            echo "option name USI_Hash type spin default 16 min 1 max 1024"
+            echo "some noise"
+            echo "usiok"
+            ;;
+        isready) echo "readyok" ;;
+        go)
+            case "$args" in
+                *infinite*)
+                    echo "info depth 1 score cp 0 pv 2g2f"
+                    read -r stop
+                    echo "bestmove 2g2f"
+                    ;;
+                *)
+                    echo "info depth 1 score cp 10 pv 7g7f"
+                    echo "info depth 2 score cp 20 pv 7g7f 3c3d"
+                    echo "bestmove 7g7f ponder 3c3d"
+                    ;;
+            esac
+            ;;
+        quit) exit 0 ;;
+    esac
+done
+"#;
+
+    fn spawn_stub() -> UsiProcess {
+        UsiProcess::spawn("sh", ["-c", STUB_ENGINE]).unwrap()
+    }
+
+    #[test]
+    fn test_usi_process() {
+        let mut engine = spawn_stub();
+        assert_eq!(engine.name(), Some("Stub Engine"));
+        assert_eq!(engine.author(), Some("sfen"));
+        assert_eq!(engine.options().len(), 1);
+
+        engine.set_option("USI_Hash", Some("64")).unwrap();
+        assert!(matches!(
+            engine.set_option("USI_Hash", Some("4096")),
+            Err(UsiProcessError::InvalidOption(_))
+        ));
+        engine.is_ready().unwrap();
+        engine.new_game().unwrap();
+
+        let mut infos = vec![];
+        let params = UsiGoParams {
+            btime: Some(Duration::from_secs(1)),
+            byoyomi: Some(Duration::from_secs(1)),
+            ..Default::default()
+        };
+        let best = engine
+            .go(&Kifu::startpos(), &params, |info| {
+                infos.push(info.clone());
+                ControlFlow::Continue(())
+            })
+            .unwrap();
+        assert_eq!(
+            best,
+            UsiBestMove::Move {
+                mv: Move::from_str("7g7f").unwrap(),
+                ponder: Some(Move::from_str("3c3d").unwrap())
+            }
+        );
+        assert_eq!(infos.len(), 2);
+        assert_eq!(infos[1].depth, Some(2));
+
+        // 制限時間が大きすぎる場合は制限時間を設けない。
+        for params in [
+            UsiGoParams {
+                btime: Some(Duration::MAX),
+                byoyomi: Some(Duration::MAX),
+                ..Default::default()
+            },
+            UsiGoParams {
+                movetime: Some(Duration::MAX),
+                ..Default::default()
+            },
+        ] {
+            let best = engine
+                .go(&Kifu::startpos(), &params, |_| ControlFlow::Continue(()))
+                .unwrap();
+            assert!(matches!(best, UsiBestMove::Move { .. }));
+        }
+
+        let params = UsiGoParams {
+            infinite: true,
+            ..Default::default()
+        };
+        let best = engine
+            .go(&Kifu::startpos(), &params, |_| ControlFlow::Break(()))
+            .unwrap();
+        assert!(matches!(best, UsiBestMove::Move { ponder: None, .. }));
+
+        engine.quit().unwrap();
+    }
+
+    #[test]
+    fn test_usi_process_timeout() {
+        let mut engine = spawn_stub();
+        engine.set_timeout(Duration::from_millis(200));
+        // スタブは `stop` に応答しないので時間切れになる。
+        engine.send(&UsiCommand::Stop).unwrap();
+        let res = engine.recv(Some(Instant::now() + engine.timeout()));
+        assert!(matches!(res, Err(UsiProcessError::Timeout)));
+        engine.quit().unwrap();
+
+        // 終了したエンジン。
+        let res = UsiProcess::spawn("sh", ["-c", "exit 0"]);
+        assert!(matches!(res, Err(UsiProcessError::Disconnected)));
+    }
+}
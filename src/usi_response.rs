@@ -0,0 +1,591 @@
+//! USI プロトコルのエンジンから GUI への応答。
+
+use std::time::Duration;
+
+use crate::bytes::Bytes;
+use crate::move_::*;
+use crate::parse::*;
+use crate::usi_command::*;
+use crate::usi_option::*;
+
+/// エンジンから GUI へ送られる USI の応答。
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum UsiResponse {
+    /// `id name <name>`
+    IdName(String),
+
+    /// `id author <author>`
+    IdAuthor(String),
+
+    /// `option name ... type ...`
+    Option(UsiOption),
+
+    /// `usiok`
+    UsiOk,
+
+    /// `readyok`
+    ReadyOk,
+
+    /// `bestmove ...`
+    BestMove(UsiBestMove),
+
+    /// `checkmate ...`
+    Checkmate(UsiCheckmate),
+
+    /// `info ...`
+    Info(UsiInfo),
+}
+
+/// `bestmove` の内容。
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum UsiBestMove {
+    /// `bestmove <move> [ponder <move>]`
+    Move { mv: Move, ponder: Option<Move> },
+
+    /// `bestmove resign`: 投了。
+    Resign,
+
+    /// `bestmove win`: 入玉宣言勝ち。
+    Win,
+}
+
+/// `checkmate` (`go mate` への応答)の内容。
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum UsiCheckmate {
+    /// 詰み手順。
+    Mate(Vec<Move>),
+
+    /// `checkmate nomate`: 詰みがない。
+    NoMate,
+
+    /// `checkmate timeout`: 時間内に詰みを見つけられなかった。
+    Timeout,
+
+    /// `checkmate notimplemented`: 詰将棋探索を実装していない。
+    NotImplemented,
+}
+
+/// `info` の内容。
+///
+/// 各項目は省略可能。出力時の順序は固定で、`pv` と `string` は末尾に置く。
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct UsiInfo {
+    /// `depth`: 探索深さ。
+    pub depth: Option<u32>,
+
+    /// `seldepth`: 選択的探索深さ。
+    pub seldepth: Option<u32>,
+
+    /// `time`: 思考時間。
+    pub time: Option<Duration>,
+
+    /// `nodes`: 探索ノード数。
+    pub nodes: Option<u64>,
+
+    /// `nps`: 1 秒あたりの探索ノード数。
+    pub nps: Option<u64>,
+
+    /// `hashfull`: 置換表の使用率(千分率)。
+    pub hashfull: Option<u32>,
+
+    /// `multipv`: 何番目の候補手か(1 始まり)。
+    pub multipv: Option<u32>,
+
+    /// `score`: 評価値。
+    pub score: Option<UsiScore>,
+
+    /// `currmove`: 現在探索中の指し手。
+    pub currmove: Option<Move>,
+
+    /// `pv`: 読み筋。
+    pub pv: Vec<Move>,
+
+    /// `string`: 任意の文字列。
+    pub string: Option<String>,
+}
+
+/// `info` の評価値。手番側から見た値。
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct UsiScore {
+    value: UsiScoreValue,
+    bound: UsiScoreBound,
+}
+
+/// 評価値の値。
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum UsiScoreValue {
+    /// `cp <x>`: センチポーン単位の評価値。
+    Cp(i32),
+
+    /// `mate <x>`: 詰みまでの手数。負なら手番側が詰まされる。
+    Mate(i32),
+
+    /// `mate +` / `mate -`: 手数不明の詰み。`true` なら手番側が詰ませる。
+    MateUnknown(bool),
+}
+
+/// 評価値が厳密な値か、上界/下界か。
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum UsiScoreBound {
+    Exact,
+
+    /// `lowerbound`: 真の値はこれ以上。
+    Lower,
+
+    /// `upperbound`: 真の値はこれ以下。
+    Upper,
+}
+
+impl UsiScore {
+    /// 値と上界/下界の別を指定して評価値を作る。
+    pub const fn new(value: UsiScoreValue, bound: UsiScoreBound) -> Self {
+        Self { value, bound }
+    }
+
+    /// 厳密なセンチポーン単位の評価値を作る。
+    pub const fn cp(cp: i32) -> Self {
+        Self::new(UsiScoreValue::Cp(cp), UsiScoreBound::Exact)
+    }
+
+    /// 厳密な詰みの評価値を作る。
+    pub const fn mate(plies: i32) -> Self {
+        Self::new(UsiScoreValue::Mate(plies), UsiScoreBound::Exact)
+    }
+
+    /// 値を返す。
+    pub const fn value(self) -> UsiScoreValue {
+        self.value
+    }
+
+    /// 上界/下界の別を返す。
+    pub const fn bound(self) -> UsiScoreBound {
+        self.bound
+    }
+}
+
+impl std::fmt::Display for UsiScore {
+    /// `score` に続く部分 (`cp 100 lowerbound` など) を出力する。
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.value {
+            UsiScoreValue::Cp(cp) => write!(f, "cp {cp}")?,
+            UsiScoreValue::Mate(plies) => write!(f, "mate {plies}")?,
+            UsiScoreValue::MateUnknown(true) => f.write_str("mate +")?,
+            UsiScoreValue::MateUnknown(false) => f.write_str("mate -")?,
+        }
+        match self.bound {
+            UsiScoreBound::Exact => Ok(()),
+            UsiScoreBound::Lower => f.write_str(" lowerbound"),
+            UsiScoreBound::Upper => f.write_str(" upperbound"),
+        }
+    }
+}
+
+/// トークンが指し手ならパースして返す。
+fn parse_move_token(token: Bytes) -> Option<Move> {
+    parser_complete(Move::parse)(token).ok()
+}
+
+impl UsiInfo {
+    /// `info` に続く部分を入力の終端まで読む。
+    ///
+    /// 未知の項目 (`currmovenumber` など) や、`pv` の後に続く指し手でないトークン
+    /// (やねうら王の `rep_draw` など) は読み飛ばす。
+    fn parse(bytes: Bytes) -> SfenParseResult<Self> {
+        let end = bytes.range_from(bytes.len()..);
+        let mut info = Self::default();
+
+        let mut tokens = bytes.tokens().peekable();
+        while let Some(token) = tokens.next() {
+            match token.as_slice() {
+                b"depth" => info.depth = Some(parse_token(tokens.next(), end, "depth expected")?),
+                b"seldepth" => {
+                    info.seldepth = Some(parse_token(tokens.next(), end, "seldepth expected")?)
+                }
+                b"time" => {
+                    let ms = parse_token(tokens.next(), end, "milliseconds expected")?;
+                    info.time = Some(Duration::from_millis(ms));
+                }
+                b"nodes" => info.nodes = Some(parse_token(tokens.next(), end, "nodes expected")?),
+                b"nps" => info.nps = Some(parse_token(tokens.next(), end, "nps expected")?),
+                b"hashfull" => {
+                    info.hashfull = Some(parse_token(tokens.next(), end, "hashfull expected")?)
+                }
+                b"multipv" => {
+                    info.multipv = Some(parse_token(tokens.next(), end, "multipv expected")?)
+                }
+                b"score" => {
+                    let kind = tokens.next().unwrap_or(end);
+                    let value = match kind.as_slice() {
+                        b"cp" => UsiScoreValue::Cp(parse_token(tokens.next(), end, "cp expected")?),
+                        b"mate" => {
+                            let token = tokens.next();
+                            match token.map(Bytes::as_slice) {
+                                Some(b"+") => UsiScoreValue::MateUnknown(true),
+                                Some(b"-") => UsiScoreValue::MateUnknown(false),
+                                _ => UsiScoreValue::Mate(parse_token(
+                                    token,
+                                    end,
+                                    "mate plies expected",
+                                )?),
+                            }
+                        }
+                        _ => {
+                            return Err(SfenParseError::invalid_input(kind, "score type expected"))
+                        }
+                    };
+                    let bound = match tokens.peek().map(|t| t.as_slice()) {
+                        Some(b"lowerbound") => UsiScoreBound::Lower,
+                        Some(b"upperbound") => UsiScoreBound::Upper,
+                        _ => UsiScoreBound::Exact,
+                    };
+                    if bound != UsiScoreBound::Exact {
+                        tokens.next();
+                    }
+                    info.score = Some(UsiScore::new(value, bound));
+                }
+                b"currmove" => {
+                    let token = tokens.next().unwrap_or(end);
+                    let mv = parse_move_token(token)
+                        .ok_or_else(|| SfenParseError::invalid_input(token, "`Move` expected"))?;
+                    info.currmove = Some(mv);
+                }
+                b"pv" => {
+                    while let Some(mv) = tokens.peek().and_then(|&t| parse_move_token(t)) {
+                        tokens.next();
+                        info.pv.push(mv);
+                    }
+                }
+                b"string" => {
+                    // 行末までを文字列とする。
+                    let rest = match tokens.peek() {
+                        Some(&t) => {
+                            token_str(bytes.range_from(t.base_offset() - bytes.base_offset()..))
+                        }
+                        None => "",
+                    };
+                    info.string = Some(rest.trim_end().to_owned());
+                    break;
+                }
+                // 未知の項目とその値は読み飛ばす。
+                _ => {}
+            }
+        }
+
+        Ok(info)
+    }
+}
+
+impl std::fmt::Display for UsiInfo {
+    /// `info` に続く部分を出力する。各項目の前に空白が付く。
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if let Some(depth) = self.depth {
+            write!(f, " depth {depth}")?;
+        }
+        if let Some(seldepth) = self.seldepth {
+            write!(f, " seldepth {seldepth}")?;
+        }
+        if let Some(multipv) = self.multipv {
+            write!(f, " multipv {multipv}")?;
+        }
+        if let Some(score) = self.score {
+            write!(f, " score {score}")?;
+        }
+        if let Some(nodes) = self.nodes {
+            write!(f, " nodes {nodes}")?;
+        }
+        if let Some(nps) = self.nps {
+            write!(f, " nps {nps}")?;
+        }
+        if let Some(hashfull) = self.hashfull {
+            write!(f, " hashfull {hashfull}")?;
+        }
+        if let Some(time) = self.time {
+            write!(f, " time {}", time.as_millis())?;
+        }
+        if let Some(currmove) = self.currmove {
+            write!(f, " currmove {currmove}")?;
+        }
+        if !self.pv.is_empty() {
+            f.write_str(" pv")?;
+            for mv in &self.pv {
+                write!(f, " {mv}")?;
+            }
+        }
+        if let Some(string) = &self.string {
+            write!(f, " string {string}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl UsiResponse {
+    /// 入力の終端まで読む。
+    pub(crate) fn parse(bytes: Bytes) -> SfenParseResult<(Bytes, Self)> {
+        let end = bytes.range_from(bytes.len()..);
+        let mut tokens = bytes.tokens();
+
+        let Some(name) = tokens.next() else {
+            return Err(SfenParseError::invalid_input(bytes, "response expected"));
+        };
+
+        let res = match name.as_slice() {
+            b"id" => {
+                let kind = tokens.next().unwrap_or(end);
+                let value = token_str(tokens.remain()).trim_end().to_owned();
+                tokens = end.tokens();
+                match kind.as_slice() {
+                    b"name" => Self::IdName(value),
+                    b"author" => Self::IdAuthor(value),
+                    _ => {
+                        return Err(SfenParseError::invalid_input(
+                            kind,
+                            r#""name" or "author" expected"#,
+                        ))
+                    }
+                }
+            }
+            b"option" => {
+                let option = UsiOption::parse(tokens.remain())?;
+                tokens = end.tokens();
+                Self::Option(option)
+            }
+            b"usiok" => Self::UsiOk,
+            b"readyok" => Self::ReadyOk,
+            b"bestmove" => {
+                let token = tokens.next().unwrap_or(end);
+                let best = match token.as_slice() {
+                    b"resign" => UsiBestMove::Resign,
+                    b"win" => UsiBestMove::Win,
+                    _ => {
+                        let mv = parse_move_token(token).ok_or_else(|| {
+                            SfenParseError::invalid_input(token, "`Move` expected")
+                        })?;
+                        let mut ponder = None;
+                        if let Some(magic) = tokens.next() {
+                            if magic.as_slice() != b"ponder" {
+                                return Err(SfenParseError::invalid_input(
+                                    magic,
+                                    r#""ponder" expected"#,
+                                ));
+                            }
+                            let token = tokens.next().unwrap_or(end);
+                            ponder = Some(parse_move_token(token).ok_or_else(|| {
+                                SfenParseError::invalid_input(token, "`Move` expected")
+                            })?);
+                        }
+                        UsiBestMove::Move { mv, ponder }
+                    }
+                };
+                Self::BestMove(best)
+            }
+            b"checkmate" => {
+                let mut mvs = vec![];
+                let res = match tokens.clone().next().map(Bytes::as_slice) {
+                    Some(b"nomate") => UsiCheckmate::NoMate,
+                    Some(b"timeout") => UsiCheckmate::Timeout,
+                    Some(b"notimplemented") => UsiCheckmate::NotImplemented,
+                    _ => {
+                        for token in tokens.by_ref() {
+                            let mv = parse_move_token(token).ok_or_else(|| {
+                                SfenParseError::invalid_input(token, "`Move` expected")
+                            })?;
+                            mvs.push(mv);
+                        }
+                        UsiCheckmate::Mate(mvs)
+                    }
+                };
+                if !matches!(res, UsiCheckmate::Mate(_)) {
+                    tokens.next();
+                }
+                Self::Checkmate(res)
+            }
+            b"info" => {
+                let info = UsiInfo::parse(tokens.remain())?;
+                tokens = end.tokens();
+                Self::Info(info)
+            }
+            _ => return Err(SfenParseError::invalid_input(name, "unknown response")),
+        };
+
+        Ok((tokens.remain(), res))
+    }
+}
+
+impl std::str::FromStr for UsiResponse {
+    type Err = SfenParseError;
+
+    /// USI の応答文字列(1 行)をパースする。
+    ///
+    /// 先頭/末尾の ASCII spaces は無視する。
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parser_complete(Self::parse)(Bytes::from(s))
+    }
+}
+
+impl std::fmt::Display for UsiResponse {
+    /// USI の応答文字列を出力する。改行は付けない。
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::IdName(name) => write!(f, "id name {name}"),
+            Self::IdAuthor(author) => write!(f, "id author {author}"),
+            Self::Option(option) => option.fmt(f),
+            Self::UsiOk => f.write_str("usiok"),
+            Self::ReadyOk => f.write_str("readyok"),
+            Self::BestMove(best) => match best {
+                UsiBestMove::Move { mv, ponder } => {
+                    write!(f, "bestmove {mv}")?;
+                    if let Some(ponder) = ponder {
+                        write!(f, " ponder {ponder}")?;
+                    }
+                    Ok(())
+                }
+                UsiBestMove::Resign => f.write_str("bestmove resign"),
+                UsiBestMove::Win => f.write_str("bestmove win"),
+            },
+            Self::Checkmate(checkmate) => match checkmate {
+                UsiCheckmate::Mate(mvs) => {
+                    f.write_str("checkmate")?;
+                    for mv in mvs {
+                        write!(f, " {mv}")?;
+                    }
+                    Ok(())
+                }
+                UsiCheckmate::NoMate => f.write_str("checkmate nomate"),
+                UsiCheckmate::Timeout => f.write_str("checkmate timeout"),
+                UsiCheckmate::NotImplemented => f.write_str("checkmate notimplemented"),
+            },
+            Self::Info(info) => write!(f, "info{info}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr as _;
+
+    use super::*;
+
+    #[test]
+    fn test_usi_response_parse() {
+        assert_eq!(
+            UsiResponse::from_str("id name My Engine 1.0").unwrap(),
+            UsiResponse::IdName("My Engine 1.0".to_owned())
+        );
+        assert_eq!(
+            UsiResponse::from_str("bestmove 7g7f ponder 3c3d").unwrap(),
+            UsiResponse::BestMove(UsiBestMove::Move {
+                mv: Move::from_str("7g7f").unwrap(),
+                ponder: Some(Move::from_str("3c3d").unwrap())
+            })
+        );
+        assert_eq!(
+            UsiResponse::from_str("bestmove resign").unwrap(),
+            UsiResponse::BestMove(UsiBestMove::Resign)
+        );
+        assert_eq!(
+            UsiResponse::from_str("checkmate G*5b").unwrap(),
+            UsiResponse::Checkmate(UsiCheckmate::Mate(vec![Move::from_str("G*5b").unwrap()]))
+        );
+        assert_eq!(
+            UsiResponse::from_str("checkmate nomate").unwrap(),
+            UsiResponse::Checkmate(UsiCheckmate::NoMate)
+        );
+        assert_eq!(
+            UsiResponse::from_str(
+                "info depth 10 seldepth 14 score cp -52 upperbound nodes 12345 nps 500000 hashfull 12 time 25 pv 7g7f 3c3d 2g2f"
+            )
+            .unwrap(),
+            UsiResponse::Info(UsiInfo {
+                depth: Some(10),
+                seldepth: Some(14),
+                score: Some(UsiScore::new(UsiScoreValue::Cp(-52), UsiScoreBound::Upper)),
+                nodes: Some(12345),
+                nps: Some(500000),
+                hashfull: Some(12),
+                time: Some(Duration::from_millis(25)),
+                pv: vec![
+                    Move::from_str("7g7f").unwrap(),
+                    Move::from_str("3c3d").unwrap(),
+                    Move::from_str("2g2f").unwrap()
+                ],
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            UsiResponse::from_str(
+                "info multipv 2 score mate -3 currmove 5i4h string 詰まされる 3 手"
+            )
+            .unwrap(),
+            UsiResponse::Info(UsiInfo {
+                multipv: Some(2),
+                score: Some(UsiScore::mate(-3)),
+                currmove: Some(Move::from_str("5i4h").unwrap()),
+                string: Some("詰まされる 3 手".to_owned()),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            UsiResponse::from_str("info score mate + lowerbound").unwrap(),
+            UsiResponse::Info(UsiInfo {
+                score: Some(UsiScore::new(
+                    UsiScoreValue::MateUnknown(true),
+                    UsiScoreBound::Lower
+                )),
+                ..Default::default()
+            })
+        );
+
+        assert!(UsiResponse::from_str("").is_err());
+        assert!(UsiResponse::from_str("bestmove").is_err());
+        assert!(UsiResponse::from_str("bestmove 7g7f ponder").is_err());
+        assert!(UsiResponse::from_str("id version 1").is_err());
+        assert!(UsiResponse::from_str("info depth x").is_err());
+        assert!(UsiResponse::from_str("info score").is_err());
+
+        // 未知の項目や読み筋の後の余分なトークンは読み飛ばす。
+        assert_eq!(
+            UsiResponse::from_str(
+                "info depth 5 currmovenumber 3 score cp 0 pv 7g7f 3c3d rep_draw nodes 100"
+            )
+            .unwrap(),
+            UsiResponse::Info(UsiInfo {
+                depth: Some(5),
+                score: Some(UsiScore::cp(0)),
+                pv: vec![
+                    Move::from_str("7g7f").unwrap(),
+                    Move::from_str("3c3d").unwrap()
+                ],
+                nodes: Some(100),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            UsiResponse::from_str("info foo 1").unwrap(),
+            UsiResponse::Info(UsiInfo::default())
+        );
+    }
+
+    #[test]
+    fn test_usi_response_fmt() {
+        for s in [
+            "id name My Engine",
+            "id author Someone",
+            "option name USI_Hash type spin default 256 min 1 max 1024",
+            "usiok",
+            "readyok",
+            "bestmove 7g7f",
+            "bestmove 8h2b+ ponder 3a2b",
+            "bestmove win",
+            "checkmate 5c5b+ 4a5b G*4b",
+            "checkmate timeout",
+            "checkmate notimplemented",
+            "info depth 3 seldepth 5 multipv 1 score cp 30 lowerbound nodes 100 nps 2000 hashfull 1 time 50 pv 7g7f 3c3d",
+            "info score mate 5 currmove 2g2f",
+            "info score mate -",
+            "info string hello world",
+        ] {
+            assert_eq!(UsiResponse::from_str(s).unwrap().to_string(), s);
+        }
+    }
+}
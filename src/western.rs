@@ -0,0 +1,316 @@
+//! 西洋式の指し手表記 (Hodges 式、Hosking 式)。
+
+use crate::hand::*;
+use crate::japanese::*;
+use crate::move_::*;
+use crate::piece::*;
+use crate::position::*;
+use crate::square::*;
+
+/// 西洋式の指し手表記の流儀。
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum WesternStyle {
+    /// Hodges 式 (`P-7f`, `Bx2b+`, `S*4e`, `G4i-5h`)。段を英小文字で表す。
+    Hodges,
+
+    /// Hosking 式 (`P76`, `Bx22+`, `S*45`, `G49-58`)。段を数字で表し、移動元を書かない通常の移動では `-` を省く。
+    Hosking,
+}
+
+const fn piece_kind_to_western(pk: PieceKind) -> &'static str {
+    match pk {
+        PAWN => "P",
+        LANCE => "L",
+        KNIGHT => "N",
+        SILVER => "S",
+        GOLD => "G",
+        BISHOP => "B",
+        ROOK => "R",
+        KING => "K",
+        PRO_PAWN => "+P",
+        PRO_LANCE => "+L",
+        PRO_KNIGHT => "+N",
+        PRO_SILVER => "+S",
+        HORSE => "+B",
+        DRAGON => "+R",
+    }
+}
+
+/// 先頭の駒種を読む。成駒は `+P` 形式のほか、`T` (と), `H` (馬), `D` (龍) も受け付ける。
+fn parse_piece_kind_western(s: &str) -> Option<(&str, PieceKind)> {
+    let (s, promoted) = match s.strip_prefix('+') {
+        Some(s) => (s, true),
+        None => (s, false),
+    };
+
+    let c = s.chars().next()?;
+    let pk = match c {
+        'P' => PAWN,
+        'L' => LANCE,
+        'N' => KNIGHT,
+        'S' => SILVER,
+        'G' => GOLD,
+        'B' => BISHOP,
+        'R' => ROOK,
+        'K' => KING,
+        'T' if !promoted => return Some((&s[1..], PRO_PAWN)),
+        'H' if !promoted => return Some((&s[1..], HORSE)),
+        'D' if !promoted => return Some((&s[1..], DRAGON)),
+        _ => return None,
+    };
+    let pk = if promoted { pk.promote()? } else { pk };
+
+    Some((&s[1..], pk))
+}
+
+fn fmt_square_western(s: &mut String, sq: Square, style: WesternStyle) {
+    s.push(char::from(b'0' + sq.col().to_num()));
+    match style {
+        WesternStyle::Hodges => s.push(char::from(b'a' + sq.row().to_num() - 1)),
+        WesternStyle::Hosking => s.push(char::from(b'0' + sq.row().to_num())),
+    }
+}
+
+/// 先頭のマス (`7f` または `76`) を読む。
+fn parse_square_western(s: &str) -> Option<(&str, Square)> {
+    let bytes = s.as_bytes();
+    let col = Col::from_num(bytes.first()?.wrapping_sub(b'0'))?;
+    let row = match *bytes.get(1)? {
+        c @ b'a'..=b'i' => Row::from_num(c - b'a' + 1)?,
+        c => Row::from_num(c.wrapping_sub(b'0'))?,
+    };
+
+    Some((&s[2..], Square::new(col, row)))
+}
+
+impl Move {
+    /// 局面 `pos` における指し手を西洋式の表記 (`P-7f`, `Bx2b+`, `S*4e` など) で返す。
+    ///
+    /// 移動元は、同じ駒種の駒が複数移動先に動ける(動いても自玉を取られない)場合のみ書く。
+    /// 成れる指し手で成る場合は `+`、成らない場合は `=` を付ける。
+    pub fn to_western(self, pos: &Position, style: WesternStyle) -> Result<String, MoveError> {
+        let us = pos.side_to_move();
+        let mut s = String::new();
+
+        match self {
+            Self::Walk(walk) => {
+                let pk = pos.board()[walk.src()]
+                    .filter(|pc| pc.side() == us)
+                    .ok_or(MoveError::NoOwnPieceAtSrc)?
+                    .kind();
+                s.push_str(piece_kind_to_western(pk));
+
+                let needs_src = pos.legal_attackers(pk, walk.dst()).len() > 1;
+                if needs_src {
+                    fmt_square_western(&mut s, walk.src(), style);
+                }
+
+                let is_capture = pos.board()[walk.dst()].is_some();
+                match (is_capture, style, needs_src) {
+                    (true, _, _) => s.push('x'),
+                    (false, WesternStyle::Hodges, _) | (false, WesternStyle::Hosking, true) => {
+                        s.push('-')
+                    }
+                    (false, WesternStyle::Hosking, false) => {}
+                }
+                fmt_square_western(&mut s, walk.dst(), style);
+
+                if walk.is_promotion() {
+                    s.push('+');
+                } else if can_promote(us, pk, walk) {
+                    s.push('=');
+                }
+            }
+            Self::Drop(drop) => {
+                s.push_str(piece_kind_to_western(drop.piece_kind().into()));
+                s.push('*');
+                fmt_square_western(&mut s, drop.dst(), style);
+            }
+        }
+
+        Ok(s)
+    }
+
+    /// 局面 `pos` における西洋式の表記の指し手をパースする。
+    ///
+    /// Hodges 式と Hosking 式のどちらも受け付け、`-` は省略してもよい。
+    /// 動いても自玉を取られない駒のみを移動元の候補とする。
+    /// 表記が複数の指し手に該当する場合は、候補を列挙したエラーを返す。
+    pub fn from_western(s: &str, pos: &Position) -> Result<Self, MoveNotationParseError> {
+        let (s, pk) =
+            parse_piece_kind_western(s.trim()).ok_or(invalid_notation("piece kind expected"))?;
+
+        // 移動元は、その後にさらにマスが続く場合のみ存在する。
+        let (s, src) = match parse_square_western(s) {
+            Some((remain, sq))
+                if parse_square_western(remain.trim_start_matches(['-', 'x'])).is_some() =>
+            {
+                (remain, Some(sq))
+            }
+            _ => (s, None),
+        };
+
+        let (s, sep) = match s.chars().next() {
+            Some(c @ ('-' | 'x' | '*')) => (&s[1..], Some(c)),
+            _ => (s, None),
+        };
+
+        let (s, dst) = parse_square_western(s).ok_or(invalid_notation("destination expected"))?;
+
+        let promo = match s {
+            "" => false,
+            "+" => true,
+            "=" => false,
+            _ => return Err(invalid_notation("extra input after move")),
+        };
+
+        let mv = if sep == Some('*') {
+            if src.is_some() || !s.is_empty() {
+                return Err(invalid_notation("drop with source or promotion"));
+            }
+            let hpk = HandPieceKind::try_from(pk)
+                .map_err(|_| invalid_notation("piece kind cannot be dropped"))?;
+            Move::drop(hpk, dst)
+        } else {
+            let mut srcs = pos.legal_attackers(pk, dst);
+            if let Some(src) = src {
+                srcs.retain(|&sq| sq == src);
+            }
+            match srcs[..] {
+                [src] => Move::walk(src, dst, promo),
+                [] => return Err(MoveNotationParseError::NoCandidate),
+                _ => {
+                    return Err(MoveNotationParseError::Ambiguous {
+                        candidates: srcs
+                            .into_iter()
+                            .map(|src| Move::walk(src, dst, promo))
+                            .collect(),
+                    })
+                }
+            }
+        };
+
+        pos.clone()
+            .do_move(mv)
+            .map_err(MoveNotationParseError::InvalidMove)?;
+
+        Ok(mv)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr as _;
+
+    use super::*;
+
+    #[test]
+    fn test_move_to_western() {
+        let pos = Position::startpos();
+        let mv = Move::from_str("7g7f").unwrap();
+        assert_eq!(mv.to_western(&pos, WesternStyle::Hodges).unwrap(), "P-7f");
+        assert_eq!(mv.to_western(&pos, WesternStyle::Hosking).unwrap(), "P76");
+
+        let mv = Move::from_str("4i5h").unwrap();
+        assert_eq!(mv.to_western(&pos, WesternStyle::Hodges).unwrap(), "G4i-5h");
+        assert_eq!(
+            mv.to_western(&pos, WesternStyle::Hosking).unwrap(),
+            "G49-58"
+        );
+
+        let pos = Position::from_str(
+            "lnsgkgsnl/1r5b1/pppppp1pp/6p2/9/2P6/PP1PPPPPP/1B5R1/LNSGKGSNL b - 3",
+        )
+        .unwrap();
+        let mv = Move::from_str("8h2b+").unwrap();
+        assert_eq!(mv.to_western(&pos, WesternStyle::Hodges).unwrap(), "Bx2b+");
+        assert_eq!(mv.to_western(&pos, WesternStyle::Hosking).unwrap(), "Bx22+");
+        let mv = Move::from_str("8h2b").unwrap();
+        assert_eq!(mv.to_western(&pos, WesternStyle::Hodges).unwrap(), "Bx2b=");
+
+        let pos = Position::from_str("4k4/9/9/9/9/9/9/9/4K4 b S 1").unwrap();
+        let mv = Move::from_str("S*4e").unwrap();
+        assert_eq!(mv.to_western(&pos, WesternStyle::Hodges).unwrap(), "S*4e");
+        assert_eq!(mv.to_western(&pos, WesternStyle::Hosking).unwrap(), "S*45");
+
+        let pos = Position::from_str("4k4/9/9/9/9/8N/9/9/4K4 b - 1").unwrap();
+        let mv = Move::from_str("1f2d").unwrap();
+        assert_eq!(mv.to_western(&pos, WesternStyle::Hodges).unwrap(), "N-2d");
+        let pos = Position::from_str("4k4/9/9/9/9/9/9/9/+B3K4 b - 1").unwrap();
+        let mv = Move::from_str("9i5e").unwrap();
+        assert_eq!(mv.to_western(&pos, WesternStyle::Hodges).unwrap(), "+B-5e");
+    }
+
+    #[test]
+    fn test_move_from_western() {
+        let pos = Position::startpos();
+        let mv = Move::from_str("7g7f").unwrap();
+        assert_eq!(Move::from_western("P-7f", &pos), Ok(mv));
+        assert_eq!(Move::from_western("P7f", &pos), Ok(mv));
+        assert_eq!(Move::from_western("P76", &pos), Ok(mv));
+        assert_eq!(Move::from_western("P7g-7f", &pos), Ok(mv));
+        assert_eq!(
+            Move::from_western("G-5h", &pos),
+            Err(MoveNotationParseError::Ambiguous {
+                candidates: vec![
+                    Move::from_str("6i5h").unwrap(),
+                    Move::from_str("4i5h").unwrap(),
+                ]
+            })
+        );
+        assert_eq!(
+            Move::from_western("G49-58", &pos),
+            Ok(Move::from_str("4i5h").unwrap())
+        );
+        assert_eq!(
+            Move::from_western("P-7e", &pos),
+            Err(MoveNotationParseError::NoCandidate)
+        );
+        assert!(Move::from_western("Q-7f", &pos).is_err());
+        assert!(Move::from_western("P-7f!", &pos).is_err());
+
+        let pos = Position::from_str(
+            "lnsgkgsnl/1r5b1/pppppp1pp/6p2/9/2P6/PP1PPPPPP/1B5R1/LNSGKGSNL b - 3",
+        )
+        .unwrap();
+        assert_eq!(
+            Move::from_western("Bx2b+", &pos),
+            Ok(Move::from_str("8h2b+").unwrap())
+        );
+        assert_eq!(
+            Move::from_western("Bx22=", &pos),
+            Ok(Move::from_str("8h2b").unwrap())
+        );
+
+        let pos = Position::from_str("4k4/9/9/9/9/9/9/9/4K4 b S 1").unwrap();
+        assert_eq!(
+            Move::from_western("S*4e", &pos),
+            Ok(Move::from_str("S*4e").unwrap())
+        );
+        assert_eq!(
+            Move::from_western("G*4e", &pos),
+            Err(MoveNotationParseError::InvalidMove(MoveError::NotInHand))
+        );
+
+        let pos = Position::from_str("4k4/9/2N6/9/9/9/9/9/4K4 b - 1").unwrap();
+        assert_eq!(
+            Move::from_western("N7a=", &pos),
+            Err(MoveNotationParseError::NoCandidate)
+        );
+        let pos = Position::from_str("4k4/9/9/2N6/9/9/9/9/4K4 b - 1").unwrap();
+        assert_eq!(
+            Move::from_western("N8b=", &pos),
+            Ok(Move::from_str("7d8b").unwrap())
+        );
+
+        // 5八金は 5 筋の飛車に対して釘付けにされているので、移動元の候補にしない。
+        let pos = Position::from_str("4r3k/9/9/9/9/9/5G3/4G4/4K4 b - 1").unwrap();
+        let mv = Move::from_str("4g4h").unwrap();
+        assert_eq!(Move::from_western("G-4h", &pos), Ok(mv));
+        assert_eq!(mv.to_western(&pos, WesternStyle::Hodges).unwrap(), "G-4h");
+        assert_eq!(
+            Move::from_western("G5h-4h", &pos),
+            Err(MoveNotationParseError::NoCandidate)
+        );
+    }
+}